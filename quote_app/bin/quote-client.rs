@@ -12,16 +12,46 @@
     mod parsecli;
     #[path = "../src/logger.rs"]
     mod logger;
+    #[path = "../src/portfolio.rs"]
+    mod portfolio;
+    #[path = "../src/alerts.rs"]
+    mod alerts;
+    #[path = "../src/multicast.rs"]
+    mod multicast;
+    #[path = "../src/metrics.rs"]
+    mod metrics;
+    #[path = "../src/tls.rs"]
+    mod tls;
+    #[path = "../src/udp_seal.rs"]
+    mod udp_seal;
+    #[path = "../src/shutdown.rs"]
+    mod shutdown;
+    #[path = "../src/config.rs"]
+    mod config;
+    #[path = "../src/netaddr.rs"]
+    mod netaddr;
+    #[path = "../src/reconnect.rs"]
+    mod reconnect;
+    #[path = "../src/dedup.rs"]
+    mod dedup;
 
 
     use std::fs::File;
     use std::io::BufReader;
+    use std::time::Duration;
     use quote_lib::quote::stockquote::StockQuote;
     #[cfg(feature = "client")]
-    use crate::client::QuoteStreamClient;
+    use crate::client::{ClientTuning, QuoteStreamClient, StreamTransport};
     use log::{warn};
     use crate::logger::setup_logger;
+    #[cfg(feature = "client")]
+    use crate::portfolio::Portfolio;
+    #[cfg(feature = "client")]
+    use crate::alerts::{AlertEngine, AlertSink};
     use crate::parsecli::CliArgs;
+    use crate::metrics::run_metrics_server;
+    use crate::shutdown::register_shutdown;
+    use crate::reconnect::ReconnectPolicy;
 
 
     fn main() {
@@ -29,14 +59,107 @@
             let cli_args = CliArgs::get_cli_args();
             if let Some(arg) = cli_args {
                 setup_logger(arg.log_level, &arg.file_log);
+                let udp_addr = arg.client_udp_addr();
                 let mut reader = BufReader::new(File::open(arg.tickers_file).unwrap());
                 let tickers = StockQuote::get_tickers_string_from_file(&mut reader).unwrap();
                 let mut quote_stream_client = QuoteStreamClient::default();
-                if let Err(e) = quote_stream_client.get_quote_stream(&arg.udp_addr,
-                                                                     &arg.server_addr, tickers)
+                if let Some(positions_file) = &arg.positions_file {
+                    //недоступный или ошибочный файл позиций - ошибка запуска, код завершения 1
+                    let file = match File::open(positions_file) {
+                        Ok(file) => file,
+                        Err(e) => {
+                            println!("Error: positions file {}: {}", positions_file, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let mut reader = BufReader::new(file);
+                    match Portfolio::from_reader(&mut reader) {
+                        Ok(mut portfolio) => {
+                            if let Some(snapshot_file) = &arg.pnl_snapshot_file {
+                                portfolio.set_snapshot_file(snapshot_file,
+                                                            Duration::from_secs(arg.pnl_snapshot_period));
+                            }
+                            quote_stream_client.set_portfolio(portfolio);
+                        }
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                quote_stream_client.set_multicast_options(arg.multicast_options);
+                let Some(transport) = StreamTransport::parse(&arg.transport,
+                                                             Duration::from_secs(arg.fallback_timeout)) else {
+                    println!("Error: bad transport {}", arg.transport);
+                    return;
+                };
+                quote_stream_client.set_transport(transport);
+                if let Some(auth) = arg.auth {
+                    quote_stream_client.set_auth(auth);
+                }
+                //TLS управляющего соединения: CA сервера и, при необходимости, сертификат клиента
+                if let Some(tls_ca) = &arg.tls_ca {
+                    let client_cert = arg.tls_cert.as_deref().zip(arg.tls_key.as_deref());
+                    match tls::client_config(tls_ca, client_cert) {
+                        Ok(config) => quote_stream_client.set_tls(config, arg.tls_server_name.clone()),
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            return;
+                        }
+                    }
+                }
+                if let Some(metrics_addr) = arg.metrics_addr {
+                    let metrics = quote_stream_client.metrics();
+                    std::thread::spawn(move || {
+                        if let Err(e) = run_metrics_server(&metrics_addr, || metrics.render()) {
+                            log::error!("metrics error: {}", e);
+                        }
+                    });
+                }
+                if let Some(alert_rules_file) = &arg.alert_rules_file {
+                    let Some(sink) = AlertSink::parse(&arg.alert_output) else {
+                        println!("Error: bad alert output {}", arg.alert_output);
+                        std::process::exit(1);
+                    };
+                    let file = match File::open(alert_rules_file) {
+                        Ok(file) => file,
+                        Err(e) => {
+                            println!("Error: alert rules file {}: {}", alert_rules_file, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let mut reader = BufReader::new(file);
+                    match AlertEngine::from_reader(&mut reader, sink) {
+                        Ok(alerts) => quote_stream_client.set_alerts(alerts),
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                quote_stream_client.set_tuning(ClientTuning::from_tuning(&arg.tuning));
+                //SIGINT и SIGTERM останавливают поток командой STOP
+                match register_shutdown() {
+                    Ok(shutdown) => quote_stream_client.set_shutdown(shutdown),
+                    Err(e) => {
+                        println!("Error: {}", e);
+                        return;
+                    }
+                }
+                quote_stream_client.set_addresses(arg.tcp_local.clone(), arg.udp_remote.clone());
+                quote_stream_client.set_failover(arg.failover_addrs.clone());
+                quote_stream_client.set_reconnect(ReconnectPolicy {
+                    delay: Duration::from_millis(arg.reconnect_delay),
+                    max_delay: Duration::from_millis(arg.reconnect_max_delay),
+                    max_attempts: arg.reconnect_attempts,
+                });
+                if let Err(e) = quote_stream_client.get_quote_stream(&udp_addr,
+                                                                     &arg.server_addr, tickers,
+                                                                     arg.book_depth)
                 {
                     println!("Error: {}", e);
                 }
+                log::logger().flush();
             } else {
                 print!("Bad command args")
             }
@@ -14,25 +14,138 @@ mod error;
 mod parsecli;
 #[path="../src/logger.rs"]
 mod logger;
+#[path="../src/multicast.rs"]
+mod multicast;
+#[path="../src/metrics.rs"]
+mod metrics;
+#[path="../src/tls.rs"]
+mod tls;
+#[path="../src/udp_seal.rs"]
+mod udp_seal;
+#[path="../src/shutdown.rs"]
+mod shutdown;
+#[path="../src/config.rs"]
+mod config;
+#[path="../src/netaddr.rs"]
+mod netaddr;
 
 
 use std::fs::File;
 use std::io::BufReader;
 #[cfg(feature = "server")]
-use crate::server::{QuoteServer};
+use crate::server::{QuoteServer, ServerOptions};
+#[cfg(feature = "server")]
+use crate::quote::auth::Credentials;
+#[cfg(feature = "server")]
+use crate::quote::entitlements::Entitlements;
+use crate::quote::limits::ServerLimits;
+use crate::quote::quote_stream::StreamTuning;
+use crate::quote::volume_generator::GeneratorTuning;
 use crate::parsecli::CliArgs;
 use crate::logger::setup_logger;
+use crate::shutdown::{register_reload, register_shutdown};
 
 fn main() {
     #[cfg(feature = "server")]{
         let cli_args = CliArgs::get_cli_args();
         if let Some(arg) = cli_args {
             setup_logger(arg.log_level, &arg.file_log);
-            let mut reader = BufReader::new(File::open(arg.tickers_file).unwrap());
+            let mut reader = BufReader::new(File::open(&arg.tickers_file).unwrap());
+            let credentials = match &arg.credentials_file {
+                Some(credentials_file) => {
+                    let mut reader = BufReader::new(File::open(credentials_file).unwrap());
+                    match Credentials::from_reader(&mut reader) {
+                        Ok(credentials) => Some(credentials),
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            return;
+                        }
+                    }
+                }
+                None => None,
+            };
+            let entitlements = match &arg.entitlements_file {
+                Some(entitlements_file) => {
+                    let mut reader = BufReader::new(File::open(entitlements_file).unwrap());
+                    match Entitlements::from_reader(&mut reader) {
+                        Ok(entitlements) => Some(entitlements),
+                        Err(e) => {
+                            println!("Error: {}", e);
+                            return;
+                        }
+                    }
+                }
+                None => None,
+            };
+            //TLS управляющего соединения, при заданном CA клиентов сертификат клиента обязателен
+            let tls = match (&arg.tls_cert, &arg.tls_key) {
+                (Some(cert), Some(key)) => match tls::server_config(cert, key, arg.tls_ca.as_deref()) {
+                    Ok(config) => Some(config),
+                    Err(e) => {
+                        println!("Error: {}", e);
+                        return;
+                    }
+                },
+                _ => None,
+            };
+            //SIGINT и SIGTERM завершают сервер после остановки сессий
+            let shutdown = match register_shutdown() {
+                Ok(shutdown) => shutdown,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    return;
+                }
+            };
+            //SIGHUP перечитывает файл тикеров
+            let reload = match register_reload() {
+                Ok(reload) => reload,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    return;
+                }
+            };
+            let generator_tuning = match GeneratorTuning::from_tuning(&arg.tuning) {
+                Ok(generator_tuning) => generator_tuning,
+                Err(e) => {
+                    println!("Error config: {}", e);
+                    return;
+                }
+            };
+            let tcp_bind = arg.server_tcp_addr().to_string();
+            let udp_bind = arg.server_udp_addr();
+            let options = ServerOptions {
+                book_depth: arg.book_depth,
+                multicast_groups: arg.multicast_groups,
+                multicast_options: arg.multicast_options,
+                ws_bind: arg.ws_addr,
+                http_bind: arg.http_addr,
+                metrics_bind: arg.metrics_addr,
+                admin_bind: arg.admin_addr,
+                admin_allow_remote: arg.admin_allow_remote,
+                credentials,
+                allow_any_udp_target: arg.allow_any_udp_target,
+                tls,
+                entitlements,
+                limits: ServerLimits {
+                    max_sessions: arg.max_sessions,
+                    max_sessions_per_ip: arg.max_sessions_per_ip,
+                    max_tickers: arg.max_tickers,
+                    max_commands_per_second: arg.max_commands_per_second,
+                    max_datagram_rate: arg.max_datagram_rate,
+                },
+                shutdown,
+                tickers_file: Some(arg.tickers_file.clone()),
+                reload,
+                watch_tickers: arg.watch_tickers,
+                stream_tuning: StreamTuning::from_tuning(&arg.tuning),
+                generator_tuning,
+            };
             if let Err(quote_server) =
-                QuoteServer::run_quote_server(&mut reader, &arg.server_addr, &arg.udp_addr) {
+                QuoteServer::run_quote_server(&mut reader, &tcp_bind, &udp_bind, options) {
                 println!("Error: {}", quote_server);
             }
+            log::info!("server stopped");
+            log::logger().flush();
         } else {
             println!("Error parsing argument");
         }
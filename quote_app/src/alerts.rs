@@ -0,0 +1,327 @@
+
+use crate::error::clienterror::QuoteClientError;
+use quote_lib::quote::alert::AlertComparison;
+use quote_lib::quote::marketmessage::MarketMessage;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::process::Command;
+use std::thread;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AlertCondition {
+    //цена относительно порога
+    Price,
+    //пересечение порога: не срабатывает, если порог пройден до первого значения
+    Crosses,
+    //изменение цены в процентах за окно времени, мс
+    PercentChange(u64),
+}
+
+#[derive(Debug)]
+struct AlertRule {
+    text: String,
+    ticker: String,
+    condition: AlertCondition,
+    comparison: AlertComparison,
+    threshold: f64,
+    hysteresis: f64,
+    cooldown: u64,
+    armed: bool,
+    last_fired: Option<u64>,
+    history: VecDeque<(u64, f64)>,
+}
+
+//куда отправляются сработавшие правила
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AlertSink {
+    Stderr,
+    File(String),
+    Command(String),
+}
+
+impl AlertSink {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        //stderr | file:<path> | command:<command line>
+        match s.split_once(':') {
+            None if s == "stderr" => Some(AlertSink::Stderr),
+            Some(("file", path)) if !path.is_empty() => Some(AlertSink::File(path.to_string())),
+            Some(("command", command)) if !command.trim().is_empty() => {
+                Some(AlertSink::Command(command.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn parse_duration_millis(s: &str) -> Option<u64> {
+    //длительность: `30s`, `5m`, `1h` или секунды
+    let (value, multiplier) = match s.chars().last()? {
+        's' => (&s[..s.len() - 1], 1000),
+        'm' => (&s[..s.len() - 1], 60 * 1000),
+        'h' => (&s[..s.len() - 1], 60 * 60 * 1000),
+        _ => (s, 1000),
+    };
+    value.parse::<u64>().ok().map(|value| value * multiplier)
+}
+
+impl AlertRule {
+    fn parse(text: &str) -> Option<Self> {
+        //<ticker> <op> <value>
+        //<ticker> crosses above|below <value>
+        //<ticker> % change over <window> <op> <value>
+        //[hysteresis <value>] [cooldown <duration>]
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let ticker = parts.first()?.to_string();
+        let (condition, comparison, threshold, options) = match parts.get(1..)? {
+            ["crosses", direction, value, options @ ..] => {
+                let comparison = match *direction {
+                    "above" => AlertComparison::Greater,
+                    "below" => AlertComparison::Less,
+                    _ => return None,
+                };
+                (AlertCondition::Crosses, comparison, value.parse().ok()?, options)
+            }
+            ["%", "change", "over", window, op, value, options @ ..] => (
+                AlertCondition::PercentChange(parse_duration_millis(window)?),
+                AlertComparison::parse(op)?,
+                value.parse().ok()?,
+                options,
+            ),
+            [op, value, options @ ..] => (
+                AlertCondition::Price,
+                AlertComparison::parse(op)?,
+                value.parse().ok()?,
+                options,
+            ),
+            _ => return None,
+        };
+        let mut rule = AlertRule {
+            text: text.to_string(),
+            ticker,
+            condition,
+            comparison,
+            threshold,
+            hysteresis: 0.0,
+            cooldown: 0,
+            armed: condition != AlertCondition::Crosses,
+            last_fired: None,
+            history: VecDeque::new(),
+        };
+        for option in options.chunks(2) {
+            match option {
+                ["hysteresis", value] => rule.hysteresis = value.parse::<f64>().ok()?.abs(),
+                ["cooldown", value] => rule.cooldown = parse_duration_millis(value)?,
+                _ => return None,
+            }
+        }
+        Some(rule)
+    }
+
+    fn value(&mut self, price: f64, timestamp: u64) -> Option<f64> {
+        match self.condition {
+            AlertCondition::Price | AlertCondition::Crosses => Some(price),
+            AlertCondition::PercentChange(window) => {
+                //история цен за окно, изменение от самой старой цены окна
+                self.history.push_back((timestamp, price));
+                while let Some((oldest, _)) = self.history.front()
+                    && timestamp.saturating_sub(*oldest) > window
+                {
+                    self.history.pop_front();
+                }
+                let (_, base) = self.history.front()?;
+                if *base == 0.0 {
+                    return None;
+                }
+                Some((price - base) / base * 100.0)
+            }
+        }
+    }
+
+    fn evaluate(&mut self, price: f64, timestamp: u64) -> Option<f64> {
+        let value = self.value(price, timestamp)?;
+        if !self.armed {
+            //пересечение требует значения по другую сторону порога
+            if self.comparison.rearm(value, self.threshold, self.hysteresis)
+                || (self.last_fired.is_none()
+                    && !self.comparison.matches(value, self.threshold))
+            {
+                self.armed = true;
+            }
+            return None;
+        }
+        if !self.comparison.matches(value, self.threshold) {
+            return None;
+        }
+        if let Some(last_fired) = self.last_fired
+            && timestamp.saturating_sub(last_fired) < self.cooldown
+        {
+            return None;
+        }
+        self.armed = false;
+        self.last_fired = Some(timestamp);
+        Some(value)
+    }
+}
+
+pub(crate) struct AlertEngine {
+    rules: Vec<AlertRule>,
+    sink: AlertSink,
+}
+
+impl AlertEngine {
+    pub(crate) fn from_reader<R: Read>(r: &mut R, sink: AlertSink) -> Result<Self, QuoteClientError> {
+        //файл правил: одно правило в строке, `#` - комментарий
+        let mut data = String::new();
+        r.read_to_string(&mut data)?;
+        let mut rules = Vec::new();
+        for (number, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match AlertRule::parse(line) {
+                Some(rule) => rules.push(rule),
+                None => {
+                    return Err(QuoteClientError::BadAlertRule(format!(
+                        "line {}: {}",
+                        number + 1,
+                        line
+                    )));
+                }
+            }
+        }
+        Ok(Self { rules, sink })
+    }
+
+    fn evaluate(&mut self, message: &MarketMessage) -> Vec<String> {
+        let Some(price) = message.mark_price() else {
+            return Vec::new();
+        };
+        let timestamp = message.timestamp();
+        let date_time = chrono::DateTime::from_timestamp_millis(timestamp as i64)
+            .map(|date_time| date_time.format("%Y-%m-%dT%H:%M:%S%.3f").to_string())
+            .unwrap_or_default();
+        self.rules
+            .iter_mut()
+            .filter(|rule| rule.ticker == message.ticker())
+            .filter_map(|rule| {
+                let value = rule.evaluate(price, timestamp)?;
+                Some(format!(
+                    "{{\"type\": \"alert\", \"rule\": \"{}\", \"ticker\": \"{}\", \"price\": {}, \
+                     \"value\": {:.4}, \"timestamp\": \"{}\"}}",
+                    rule.text, rule.ticker, price, value, date_time
+                ))
+            })
+            .collect()
+    }
+
+    pub(crate) fn process(&mut self, message: &MarketMessage) -> Result<(), QuoteClientError> {
+        for alert in self.evaluate(message) {
+            log::info!("alert: {}", alert);
+            match &self.sink {
+                AlertSink::Stderr => eprintln!("{}", alert),
+                AlertSink::File(path) => {
+                    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                    writeln!(file, "{}", alert)?;
+                }
+                AlertSink::Command(command) => {
+                    //команда запускается без ожидания, запись передается в переменной окружения
+                    let mut args = command.split_whitespace();
+                    if let Some(program) = args.next() {
+                        let mut child = Command::new(program)
+                            .args(args)
+                            .env("QUOTE_ALERT", &alert)
+                            .env("QUOTE_ALERT_TICKER", message.ticker())
+                            .spawn()?;
+                        thread::spawn(move || child.wait());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn trade(ticker: &str, price: f64, timestamp: u64) -> MarketMessage {
+        MarketMessage::from_string(&format!("T|{}|{}|{}|100|R|{}", ticker, timestamp, price, timestamp)).unwrap()
+    }
+
+    fn engine(rules: &str) -> AlertEngine {
+        AlertEngine::from_reader(&mut Cursor::new(rules), AlertSink::Stderr).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let engine = engine(
+            "# rules\nAAPL > 200\nMSFT crosses below 150 hysteresis 1\n\
+             AAPL % change over 5m > 2 cooldown 30s\n",
+        );
+        assert_eq!(engine.rules.len(), 3);
+        assert_eq!(engine.rules[1].condition, AlertCondition::Crosses);
+        assert_eq!(engine.rules[2].condition, AlertCondition::PercentChange(300_000));
+        assert_eq!(engine.rules[2].cooldown, 30_000);
+        let result = AlertEngine::from_reader(&mut Cursor::new("AAPL >> 200"), AlertSink::Stderr);
+        assert_eq!(
+            result.err().unwrap(),
+            QuoteClientError::BadAlertRule("line 1: AAPL >> 200".to_string())
+        );
+    }
+
+    #[test]
+    fn test_threshold_hysteresis() {
+        let mut engine = engine("AAPL > 200 hysteresis 1");
+        assert_eq!(engine.evaluate(&trade("AAPL", 201.0, 1)).len(), 1);
+        assert!(engine.evaluate(&trade("AAPL", 202.0, 2)).is_empty());
+        //колебание у порога в пределах гистерезиса не срабатывает повторно
+        assert!(engine.evaluate(&trade("AAPL", 199.5, 3)).is_empty());
+        assert!(engine.evaluate(&trade("AAPL", 200.5, 4)).is_empty());
+        assert!(engine.evaluate(&trade("AAPL", 198.9, 5)).is_empty());
+        assert_eq!(engine.evaluate(&trade("AAPL", 200.5, 6)).len(), 1);
+        assert!(engine.evaluate(&trade("MSFT", 300.0, 7)).is_empty());
+    }
+
+    #[test]
+    fn test_crosses_and_cooldown() {
+        let mut engine = engine("MSFT crosses below 150 cooldown 10s");
+        //значение ниже порога на первом наблюдении - не пересечение
+        assert!(engine.evaluate(&trade("MSFT", 149.0, 0)).is_empty());
+        assert!(engine.evaluate(&trade("MSFT", 151.0, 1000)).is_empty());
+        assert_eq!(engine.evaluate(&trade("MSFT", 149.0, 2000)).len(), 1);
+        assert!(engine.evaluate(&trade("MSFT", 151.0, 3000)).is_empty());
+        assert!(engine.evaluate(&trade("MSFT", 149.0, 4000)).is_empty());
+        assert!(engine.evaluate(&trade("MSFT", 148.0, 13000)).len() == 1);
+    }
+
+    #[test]
+    fn test_percent_change() {
+        let mut engine = engine("AAPL % change over 5m > 2");
+        assert!(engine.evaluate(&trade("AAPL", 100.0, 0)).is_empty());
+        assert!(engine.evaluate(&trade("AAPL", 101.0, 60_000)).is_empty());
+        let alerts = engine.evaluate(&trade("AAPL", 102.5, 120_000));
+        assert!(alerts[0].contains("\"value\": 2.5000"));
+        //старые цены вышли из окна
+        let mut engine = self::engine("AAPL % change over 1m > 2");
+        engine.evaluate(&trade("AAPL", 100.0, 0));
+        assert!(engine.evaluate(&trade("AAPL", 103.0, 120_000)).is_empty());
+    }
+
+    #[test]
+    fn test_alert_sink_parse() {
+        assert_eq!(AlertSink::parse("stderr"), Some(AlertSink::Stderr));
+        assert_eq!(
+            AlertSink::parse("file:alerts.log"),
+            Some(AlertSink::File("alerts.log".to_string()))
+        );
+        assert_eq!(
+            AlertSink::parse("command:notify-send quote"),
+            Some(AlertSink::Command("notify-send quote".to_string()))
+        );
+        assert!(AlertSink::parse("mail:desk").is_none());
+    }
+}
@@ -1,20 +1,82 @@
 
+use crate::config::Tuning;
 use crate::error::clienterror::QuoteClientError;
+use crate::alerts::AlertEngine;
+use crate::metrics::ClientMetrics;
+use crate::multicast::MulticastOptions;
+use crate::netaddr;
+use crate::dedup::StreamDedup;
+use crate::reconnect::{Backoff, ConnectionState, ReconnectPolicy, ServerList};
+use crate::portfolio::Portfolio;
+use crate::tls::ControlStream;
+use crate::udp_seal::{KeyExchange, OpenError, UdpOpener};
 use log;
-use quote_lib::quote::stockquote::StockQuote;
+use quote_lib::quote::marketmessage::MarketMessage;
+use quote_lib::quote::orderbook::OrderBook;
+use quote_lib::quote::subscription::TickerSubscription;
+use rustls::ClientConfig;
 use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
 use std::io::{BufRead, BufReader, ErrorKind, Write};
 use std::net::{SocketAddr, TcpStream, UdpSocket};
 use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::Ordering::{Relaxed, SeqCst};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+//транспорт потока котировок
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum StreamTransport {
+    #[default]
+    Udp,
+    //котировки по управляющему TCP соединению
+    Tcp,
+    //UDP с переходом на TCP, если данные по UDP не пришли за таймаут
+    Auto(Duration),
+}
+
+impl StreamTransport {
+    pub(crate) fn parse(s: &str, fallback_timeout: Duration) -> Option<Self> {
+        match s {
+            "udp" => Some(StreamTransport::Udp),
+            "tcp" => Some(StreamTransport::Tcp),
+            "auto" => Some(StreamTransport::Auto(fallback_timeout)),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Default)]
 pub(crate) struct QuoteStreamClient {
     is_running_ping: Arc<AtomicBool>,
     remote_add: Arc<Mutex<String>>,
+    //локальные стаканы, восстановленные по снимкам и обновлениям
+    books: HashMap<String, OrderBook>,
+    //портфель для расчета P&L по входящим котировкам
+    portfolio: Option<Portfolio>,
+    //правила оповещений по входящим котировкам
+    alerts: Option<AlertEngine>,
+    //параметры вступления в группы, если сервер работает в режиме многоадресной рассылки
+    multicast_options: MulticastOptions,
+    transport: StreamTransport,
+    metrics: Arc<ClientMetrics>,
+    //аргумент AUTH, отправляемой перед STREAM: `<token>` или `<user> <password>`
+    auth: Option<String>,
+    //TLS управляющего соединения и имя сервера для проверки сертификата, None - открытый TCP
+    tls: Option<(Arc<ClientConfig>, Option<String>)>,
+    //флаг завершения клиента по сигналу: поток останавливается командой STOP
+    shutdown: Arc<AtomicBool>,
+    tuning: ClientTuning,
+    //локальный адрес управляющего соединения, None - выбирает система
+    tcp_local: Option<String>,
+    //адрес потока UDP в команде STREAM (NAT), None - локальный адрес управляющего соединения
+    udp_remote: Option<String>,
+    reconnect: ReconnectPolicy,
+    state: ConnectionState,
+    //резервные серверы в порядке перехода после основного
+    failover: Vec<String>,
+    dedup: StreamDedup,
 }
 
 //константа таймаут чтения udp сек
@@ -29,11 +91,148 @@ const TCP_KEEPALIVE_WITH_TIME: u64 = 10;
 const TCP_KEEPALIVE_WITH_INTERVAL: u64 = 5;
 //константа паузы потока отправки данных PING
 const PING_SEND_THREAD_WAIT: u64 = 2;
+//шаг ожидания переподключения: проверка флага завершения, мс
+const RECONNECT_WAIT_STEP_MILLISECOND: u64 = 100;
+//поток TCP без данных дольше таймаута - сервер считается недоступным, сек
+const TCP_STREAM_DATA_TIMEOUT_SECOND: u64 = 10;
+
+//ответ сервера `Error ...`: ограничение сервера (Error limit) - разрыв с переходом
+//на следующий сервер списка, прочие ошибки завершают клиент
+fn server_error(line: &str) -> QuoteClientError {
+    let line = line.trim().to_string();
+    if line.starts_with("Error limit") {
+        QuoteClientError::ConnectionLost(line)
+    } else {
+        QuoteClientError::BadNetworkBindSocket(line)
+    }
+}
+
+//параметры клиента из секции [client] файла настроек, сек
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ClientTuning {
+    pub(crate) udp_read_timeout: u64,
+    pub(crate) ping_send_period: u64,
+}
+
+impl Default for ClientTuning {
+    fn default() -> Self {
+        Self {
+            udp_read_timeout: UDP_READ_TIMEOUT_SECOND,
+            ping_send_period: PING_SEND_THREAD_WAIT,
+        }
+    }
+}
+
+impl ClientTuning {
+    pub(crate) fn from_tuning(tuning: &Tuning) -> Self {
+        let default = ClientTuning::default();
+        Self {
+            udp_read_timeout: tuning.client_udp_read_timeout.unwrap_or(default.udp_read_timeout),
+            ping_send_period: tuning.client_ping_send_period.unwrap_or(default.ping_send_period),
+        }
+    }
+}
 
 impl QuoteStreamClient {
-    fn connect(server_addr: &str) -> Result<TcpStream, QuoteClientError> {
-        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
-        let socket_addr = server_addr.parse::<SocketAddr>()?;
+    pub fn set_portfolio(&mut self, portfolio: Portfolio) {
+        self.portfolio = Some(portfolio);
+    }
+
+    pub fn set_alerts(&mut self, alerts: AlertEngine) {
+        self.alerts = Some(alerts);
+    }
+
+    pub fn set_multicast_options(&mut self, multicast_options: MulticastOptions) {
+        self.multicast_options = multicast_options;
+    }
+
+    pub fn set_transport(&mut self, transport: StreamTransport) {
+        self.transport = transport;
+    }
+
+    pub fn set_auth(&mut self, auth: String) {
+        self.auth = Some(auth);
+    }
+
+    pub fn set_tls(&mut self, config: Arc<ClientConfig>, server_name: Option<String>) {
+        self.tls = Some((config, server_name));
+    }
+
+    pub(crate) fn set_addresses(&mut self, tcp_local: Option<String>, udp_remote: Option<String>) {
+        self.tcp_local = tcp_local;
+        self.udp_remote = udp_remote;
+    }
+
+    pub(crate) fn set_failover(&mut self, failover: Vec<String>) {
+        self.failover = failover;
+    }
+
+    pub(crate) fn set_reconnect(&mut self, reconnect: ReconnectPolicy) {
+        self.reconnect = reconnect;
+    }
+
+    pub(crate) fn set_tuning(&mut self, tuning: ClientTuning) {
+        self.tuning = tuning;
+    }
+
+    pub fn set_shutdown(&mut self, shutdown: Arc<AtomicBool>) {
+        self.shutdown = shutdown;
+    }
+
+    pub fn metrics(&self) -> Arc<ClientMetrics> {
+        self.metrics.clone()
+    }
+
+    fn join_multicast(&self, reply: &str, session: &mut UdpOpener) -> Result<(UdpSocket, UdpOpener), QuoteClientError> {
+        //ответ сервера `OK Multicast <group:port> ... key=<hex>` - группы, публикующие тикеры клиента,
+        //и ключ групп, запечатанный ключом сессии; рассылка без ключа не принимается
+        let (grants, groups): (Vec<&str>, Vec<&str>) =
+            reply.split_whitespace().partition(|token| token.starts_with("key="));
+        let Some(grant) = grants.first().and_then(|grant| grant.strip_prefix("key=")) else {
+            return Err(QuoteClientError::BadNetworkBindSocket("multicast without group key".to_string()));
+        };
+        let opener = session.for_group(grant).map_err(QuoteClientError::BadNetworkBindSocket)?;
+        let groups = groups
+            .iter()
+            .map(|group| group.parse::<SocketAddr>())
+            .collect::<Result<Vec<SocketAddr>, _>>()?;
+        let socket = self.multicast_options.receiver_socket(&groups)?;
+        socket.set_read_timeout(Some(Duration::from_secs(self.tuning.udp_read_timeout)))?;
+        log::info!("joined multicast groups {:?}", groups);
+        Ok((socket, opener))
+    }
+
+    fn connect(server_addr: &str, tcp_local: Option<&str>) -> Result<TcpStream, QuoteClientError> {
+        //имя хоста разрешается в адреса IPv4 и IPv6, подключение к первому доступному
+        let local_addrs = match tcp_local {
+            Some(tcp_local) => netaddr::resolve(tcp_local)?,
+            None => Vec::new(),
+        };
+        let mut error = QuoteClientError::AddressParseError(format!("no address for {}", server_addr));
+        for socket_addr in netaddr::resolve(server_addr)? {
+            let local_addr = local_addrs.iter().find(|local| local.is_ipv4() == socket_addr.is_ipv4());
+            if tcp_local.is_some() && local_addr.is_none() {
+                error = QuoteClientError::AddressParseError(format!(
+                    "local address {} does not match {}", tcp_local.unwrap_or_default(), socket_addr
+                ));
+                continue;
+            }
+            match QuoteStreamClient::connect_addr(socket_addr, local_addr) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    log::warn!("error connect to {}: {}", socket_addr, e);
+                    error = e;
+                }
+            }
+        }
+        Err(error)
+    }
+
+    fn connect_addr(socket_addr: SocketAddr, local_addr: Option<&SocketAddr>) -> Result<TcpStream, QuoteClientError> {
+        let socket = Socket::new(Domain::for_address(socket_addr), Type::STREAM, Some(Protocol::TCP))?;
+        if let Some(local_addr) = local_addr {
+            socket.bind(&(*local_addr).into())?;
+        }
         // Включаем TCP keepalive
         socket.set_keepalive(true)?;
         #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -51,10 +250,199 @@ impl QuoteStreamClient {
         Ok(stream)
     }
 
+    fn open_control(&self, server_addr: &str) -> Result<ControlStream, QuoteClientError> {
+        //управляющее соединение, рукопожатие TLS сразу после подключения
+        let stream = QuoteStreamClient::connect(server_addr, self.tcp_local.as_deref())?;
+        let Some((config, server_name)) = &self.tls else {
+            return Ok(ControlStream::Plain(stream));
+        };
+        let server_name = match server_name {
+            Some(server_name) => server_name.clone(),
+            None => netaddr::host(server_addr)
+                .ok_or(QuoteClientError::AddressParseError(server_addr.to_string()))?
+                .to_string(),
+        };
+        ControlStream::connect(stream, config, &server_name)
+            .map_err(|e| QuoteClientError::BadNetworkBindSocket(format!("tls: {}", e)))
+    }
+
+    fn udp_target(&self, socket: &UdpSocket, control: &ControlStream) -> Result<String, QuoteClientError> {
+        //адрес потока: явный --udp-remote, адрес привязки UDP или IP управляющего соединения и порт UDP
+        if let Some(udp_remote) = &self.udp_remote {
+            return Ok(udp_remote.clone());
+        }
+        let local = socket.local_addr()?;
+        if !local.ip().is_unspecified() {
+            return Ok(local.to_string());
+        }
+        let control_ip = control
+            .tcp_stream()
+            .local_addr()
+            .map_err(QuoteClientError::lost)?
+            .ip()
+            .to_canonical();
+        Ok(SocketAddr::new(control_ip, local.port()).to_string())
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        //смена состояния соединения - в журнал и метрику quote_client_connected
+        if self.state == state {
+            return;
+        }
+        match state {
+            ConnectionState::Reconnecting | ConnectionState::Disconnected => {
+                log::warn!("connection state: {} -> {}", self.state, state)
+            }
+            _ => log::info!("connection state: {} -> {}", self.state, state),
+        }
+        self.state = state;
+        self.metrics
+            .connected
+            .store((state == ConnectionState::Connected) as u64, Relaxed);
+    }
+
+    fn wait_reconnect(&mut self, backoff: &mut Backoff, servers: &mut ServerList, lost: &str) -> Result<bool, QuoteClientError> {
+        //после разрыва - сразу следующий сервер списка, задержка после круга по всем серверам;
+        //Ok(false) - клиент завершается по сигналу
+        let lost_server = servers.current().to_string();
+        self.set_state(ConnectionState::Reconnecting);
+        if !servers.fail() {
+            self.metrics.failovers.fetch_add(1, Relaxed);
+            log::warn!("failover from {} to {}: {}", lost_server, servers.current(), lost);
+            return Ok(!self.shutdown.load(SeqCst));
+        }
+        let Some(delay) = backoff.next_delay() else {
+            self.set_state(ConnectionState::Disconnected);
+            return Err(QuoteClientError::BadNetworkBindSocket(format!(
+                "Error connect address {}, error: {}",
+                lost_server, lost
+            )));
+        };
+        log::warn!("reconnect to {} in {:?} (attempt {}): {}", servers.current(), delay, backoff.attempt(), lost);
+        let deadline = Instant::now() + delay;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            if self.shutdown.load(SeqCst) {
+                return Ok(false);
+            }
+            thread::sleep(left.min(Duration::from_millis(RECONNECT_WAIT_STEP_MILLISECOND)));
+        }
+        Ok(!self.shutdown.load(SeqCst))
+    }
+
+    fn authenticate(&self, reader: &mut BufReader<ControlStream>, writer: &mut ControlStream) -> Result<(), QuoteClientError> {
+        //AUTH до STREAM, ответ ожидается после приветствия сервера
+        let Some(auth) = &self.auth else {
+            return Ok(());
+        };
+        writer
+            .write_all(format!("AUTH {}\n", auth).as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(QuoteClientError::lost)?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).map_err(QuoteClientError::lost)? == 0 {
+                return Err(QuoteClientError::ConnectionLost("connection closed".to_string()));
+            }
+            if line.starts_with("OK Auth") {
+                return Ok(());
+            }
+            if line.starts_with("Error limit") {
+                return Err(server_error(&line));
+            }
+            if line.starts_with("Error") {
+                return Err(QuoteClientError::AuthError(line.trim().to_string()));
+            }
+        }
+    }
+
+    fn negotiate_key(
+        &self,
+        reader: &mut BufReader<ControlStream>,
+        writer: &mut ControlStream,
+    ) -> Result<UdpOpener, QuoteClientError> {
+        //KEY до STREAM: ключ сессии для проверки датаграмм потока UDP
+        let exchange = KeyExchange::new().map_err(QuoteClientError::BadNetworkBindSocket)?;
+        writer
+            .write_all(format!("KEY {}\n", exchange.public_hex()).as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(QuoteClientError::lost)?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).map_err(QuoteClientError::lost)? == 0 {
+                return Err(QuoteClientError::ConnectionLost("connection closed".to_string()));
+            }
+            if let Some(server_public) = line.trim().strip_prefix("OK Key ") {
+                let key = exchange.derive(server_public, false).map_err(QuoteClientError::BadNetworkBindSocket)?;
+                return Ok(UdpOpener::new(key));
+            }
+            if line.starts_with("Error") {
+                return Err(server_error(&line));
+            }
+        }
+    }
+
+    fn open_datagram(&self, opener: &mut UdpOpener, datagram: &[u8]) -> Option<Vec<u8>> {
+        //подделанные и повторные датаграммы отбрасываются и учитываются в метриках
+        match opener.open(datagram) {
+            Ok(payload) => Some(payload),
+            Err(OpenError::Forged) => {
+                self.metrics.forged_datagrams.fetch_add(1, Relaxed);
+                log::debug!("dropped forged datagram");
+                None
+            }
+            Err(OpenError::Replayed) => {
+                self.metrics.replayed_datagrams.fetch_add(1, Relaxed);
+                log::debug!("dropped replayed datagram");
+                None
+            }
+        }
+    }
+
+    fn read_challenge(socket: &UdpSocket, wait: Duration) -> Result<String, QuoteClientError> {
+        //датаграмма `CHALLENGE|<nonce>` сервера, котировки прежнего потока пропускаются
+        let started = Instant::now();
+        let mut data = [0u8; 1024];
+        while started.elapsed() < wait {
+            match socket.recv_from(&mut data) {
+                Ok((size, _)) => {
+                    if let Some(nonce) = String::from_utf8_lossy(&data[..size]).trim().strip_prefix("CHALLENGE|") {
+                        return Ok(nonce.to_string());
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => return Err(QuoteClientError::lost(e)),
+            }
+        }
+        //UDP не доходит до клиента - переход на следующий сервер списка
+        Err(QuoteClientError::ConnectionLost("no udp challenge from server".to_string()))
+    }
+
+    fn challenge_wait(&self) -> Duration {
+        //в режиме auto CHALLENGE ожидается не дольше таймаута перехода на TCP
+        let wait = Duration::from_secs(DURATION_WAIT_TO_CONNECT);
+        match self.transport {
+            StreamTransport::Auto(fallback_timeout) => wait.min(fallback_timeout),
+            _ => wait,
+        }
+    }
+
+    fn udp_fallback(&self, is_received: bool, started: Instant) -> Option<Duration> {
+        //UDP блокируется (например, межсетевым экраном): данных нет дольше таймаута режима auto
+        match self.transport {
+            StreamTransport::Auto(fallback_timeout) if !is_received && started.elapsed() >= fallback_timeout => {
+                Some(fallback_timeout)
+            }
+            _ => None,
+        }
+    }
+
     fn thread_ping_quote_server(
         socket: UdpSocket,
         server_adr: Arc<Mutex<String>>,
         is_running_ping: Arc<AtomicBool>,
+        ping_send_period: u64,
     ) -> Result<(), QuoteClientError> {
         is_running_ping.store(true, SeqCst);
         let mut send_addr = String::new();
@@ -66,94 +454,416 @@ impl QuoteStreamClient {
                 send_addr = server_adr.to_string();
             }
             socket.send_to("PING\n".as_bytes(), &send_addr)?;
-            thread::sleep(Duration::from_secs(PING_SEND_THREAD_WAIT));
+            thread::sleep(Duration::from_secs(ping_send_period));
         }
         is_running_ping.store(false, SeqCst);
         Ok(())
     }
 
+    fn close_stream(&mut self, control: Option<ControlStream>) {
+        //завершение по сигналу: STOP серверу, остановка PING и закрытие управляющего соединения
+        log::info!("client shutdown");
+        while self.is_running_ping.load(SeqCst) {
+            self.is_running_ping.store(false, SeqCst);
+        }
+        if let Some(mut control) = control {
+            let _ = control.write_all(b"STOP\n");
+            let _ = control.flush();
+            let _ = control.shutdown();
+        }
+        if let Some(portfolio) = &mut self.portfolio
+            && let Err(e) = portfolio.write_snapshot_if_due()
+        {
+            log::error!("error write portfolio snapshot: {}", e);
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        message: MarketMessage,
+        book_depth: usize,
+    ) -> Result<Option<String>, QuoteClientError> {
+        //сообщения стакана применяются к локальному стакану, выводится top-N глубины
+        let book = match message {
+            MarketMessage::Book(book) => {
+                self.books.insert(book.ticker.clone(), book.clone());
+                book
+            }
+            MarketMessage::BookUpdate(update) => {
+                let Some(book) = self.books.get_mut(&update.ticker) else {
+                    return Ok(None);
+                };
+                if !book.apply(&update) {
+                    log::warn!("book {} sequence gap, waiting snapshot", update.ticker);
+                    self.books.remove(&update.ticker);
+                    return Ok(None);
+                }
+                book.clone()
+            }
+            message => return Ok(Some(message.to_json()?)),
+        };
+        Ok(Some(MarketMessage::Book(book.top(book_depth)).to_json()?))
+    }
+
+    fn process_message(
+        &mut self,
+        quote: MarketMessage,
+        book_depth: usize,
+    ) -> Result<(), QuoteClientError> {
+        //повторы после переподключения или перехода на резервный сервер отбрасываются
+        if !self.dedup.accept(&quote) {
+            self.metrics.duplicates_dropped.fetch_add(1, Relaxed);
+            return Ok(());
+        }
+        if let Some(portfolio) = &mut self.portfolio {
+            portfolio.mark(&quote).iter().for_each(|line| println!("{}", line));
+        }
+        if let Some(alerts) = &mut self.alerts
+            && let Err(e) = alerts.process(&quote)
+        {
+            log::error!("error send alert: {}", e);
+        }
+        if let Some(line) = self.handle_message(quote, book_depth)? {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    fn start_tcp_stream(
+        &self,
+        stream: ControlStream,
+        tickers: &str,
+    ) -> Result<(BufReader<ControlStream>, ControlStream), QuoteClientError> {
+        //AUTH и STREAM tcp, ответ OK Stream читается вместе с потоком;
+        //ConnectionLost - разрыв соединения, клиент переподключается
+        let mut writer = stream.try_clone().map_err(QuoteClientError::lost)?;
+        let mut reader = BufReader::new(stream);
+        self.authenticate(&mut reader, &mut writer)?;
+        writer
+            .write_all(format!("STREAM tcp {}\n", tickers).as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(QuoteClientError::lost)?;
+        Ok((reader, writer))
+    }
+
+    fn start_udp_stream(
+        &self,
+        stream: ControlStream,
+        socket: &UdpSocket,
+        tickers: &str,
+    ) -> Result<(ControlStream, UdpOpener, Option<String>), QuoteClientError> {
+        //AUTH, KEY, STREAM и VERIFY: управляющее соединение, ключ сессии и ответ OK Multicast,
+        //если сервер работает в режиме многоадресной рассылки; ConnectionLost - разрыв соединения
+        let udp_target = self.udp_target(socket, &stream)?;
+        let mut writer = stream.try_clone().map_err(QuoteClientError::lost)?;
+        let mut reader = BufReader::new(stream);
+        self.authenticate(&mut reader, &mut writer)?;
+        let opener = self.negotiate_key(&mut reader, &mut writer)?;
+        //отправляем команду для получения данных
+        writer
+            .write_all(format!("STREAM udp://{} {}\n", udp_target, tickers).as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(QuoteClientError::lost)?;
+        let mut result = String::new();
+        //ожидание ответа на STREAM и VERIFY: сервер принял соединение, но не отвечает - разрыв
+        let mut waiting = Instant::now();
+        loop {
+            match reader.read_line(&mut result) {
+                Ok(0) => return Err(QuoteClientError::ConnectionLost("connection closed by server".to_string())),
+                Ok(_) => {
+                    //тикеры без прав доступа исключены сервером из подписки
+                    if let Some(denied) = result.trim().strip_prefix("DENIED|") {
+                        log::warn!("tickers not entitled: {}", denied);
+                        result.clear();
+                        continue;
+                    }
+                    //сервер проверяет адрес UDP: nonce из датаграммы возвращается по TCP
+                    if result.lines().any(|line| line.starts_with("OK Challenge")) {
+                        let nonce = QuoteStreamClient::read_challenge(socket, self.challenge_wait())?;
+                        writer
+                            .write_all(format!("VERIFY {}\n", nonce).as_bytes())
+                            .and_then(|_| writer.flush())
+                            .map_err(QuoteClientError::lost)?;
+                        result.clear();
+                        waiting = Instant::now();
+                        continue;
+                    }
+                    if let Some(error) = result.lines().find(|line| line.starts_with("Error")) {
+                        return Err(server_error(error));
+                    }
+                    //сервер ответил сообщение ОК, коннект установлен
+                    if result.contains("OK") {
+                        let multicast = result
+                            .lines()
+                            .find_map(|line| line.strip_prefix("OK Multicast"))
+                            .map(str::to_string);
+                        return Ok((writer, opener, multicast));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    result.clear();
+                    if waiting.elapsed() >= Duration::from_secs(DURATION_WAIT_TO_CONNECT) {
+                        return Err(QuoteClientError::ConnectionLost("no stream response from server".to_string()));
+                    }
+                    log::error!("waiting for server response...");
+                    thread::sleep(Duration::from_millis(DURATION_WAIT_TO_CONNECT));
+                }
+                Err(e) => return Err(QuoteClientError::lost(e)),
+            }
+        }
+    }
+
+    fn get_quote_stream_tcp(
+        &mut self,
+        server_adr: &str,
+        tickers: &str,
+        book_depth: usize,
+    ) -> Result<(), QuoteClientError> {
+        //котировки по управляющему TCP соединению, одна строка - одно сообщение
+        let mut was_connected = false;
+        let mut backoff = Backoff::new(self.reconnect);
+        let mut servers = ServerList::new(server_adr, &self.failover);
+        //причина разрыва соединения, пустая строка - первое подключение
+        let mut lost = String::new();
+        loop {
+            if !lost.is_empty() && !self.wait_reconnect(&mut backoff, &mut servers, &lost)? {
+                self.close_stream(None);
+                return Ok(());
+            }
+            if was_connected {
+                self.metrics.reconnects.fetch_add(1, Relaxed);
+            }
+            if lost.is_empty() {
+                self.set_state(ConnectionState::Connecting);
+            }
+            log::info!("try connecting to server at {} (tcp stream)", servers.current());
+            let stream = match self.open_control(servers.current()) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::error!("error connect to server: {}", e);
+                    lost = e.to_string();
+                    continue;
+                }
+            };
+            let (mut reader, writer) = match self.start_tcp_stream(stream, tickers) {
+                Ok(control) => control,
+                Err(QuoteClientError::ConnectionLost(e)) => {
+                    log::error!("connection lost: {}", e);
+                    lost = e;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let mut line = String::new();
+            let mut last_data = Instant::now();
+            loop {
+                if self.shutdown.load(SeqCst) {
+                    self.close_stream(Some(writer));
+                    return Ok(());
+                }
+                match reader.read_line(&mut line) {
+                    Ok(0) => {
+                        log::error!("tcp stream closed by server");
+                        lost = "tcp stream closed by server".to_string();
+                        break;
+                    }
+                    Ok(_) => {
+                        //ответ OK Stream - подписка восстановлена
+                        if line.starts_with("OK Stream") {
+                            //после разрыва повторы последних сообщений отбрасываются
+                            if was_connected {
+                                self.dedup.reconnected();
+                            }
+                            was_connected = true;
+                            self.set_state(ConnectionState::Connected);
+                            backoff.reset();
+                            servers.connected();
+                        }
+                        last_data = Instant::now();
+                        if let Some(quote) = MarketMessage::from_string(&line) {
+                            self.metrics.observe_latency(&quote);
+                            self.process_message(quote, book_depth)?;
+                        } else if line.starts_with("Error limit") {
+                            log::error!("tcp stream: {}", line.trim());
+                            lost = line.trim().to_string();
+                            break;
+                        } else if line.starts_with("Error") {
+                            return Err(QuoteClientError::BadNetworkBindSocket(format!(
+                                "Error tcp stream: {}",
+                                line.trim()
+                            )));
+                        } else if let Some(denied) = line.trim().strip_prefix("DENIED|") {
+                            log::warn!("tickers not entitled: {}", denied);
+                        } else if let Some(removed) = line.trim().strip_prefix("REMOVED|") {
+                            log::warn!("tickers removed by server: {}", removed);
+                        } else if line.starts_with("SHUTDOWN") {
+                            //сервер перезапускается - переподключение с задержкой
+                            log::info!("server shutdown");
+                            lost = "server shutdown".to_string();
+                            break;
+                        } else if !line.starts_with("OK") && !line.starts_with("Welcome") {
+                            self.metrics.parse_failures.fetch_add(1, Relaxed);
+                        }
+                        line.clear();
+                    }
+                    //таймаут чтения - прочитанная часть строки сохраняется до следующего чтения
+                    Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                        log::error!("waiting tcp stream data...");
+                        if last_data.elapsed() >= Duration::from_secs(TCP_STREAM_DATA_TIMEOUT_SECOND) {
+                            lost = format!("no tcp stream data in {} seconds", TCP_STREAM_DATA_TIMEOUT_SECOND);
+                            break;
+                        }
+                    }
+                    //чтение прервано сигналом - флаг завершения проверяется в начале цикла
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        log::error!("error read tcp stream: {}", e);
+                        lost = format!("error read tcp stream: {}", e);
+                        break;
+                    }
+                }
+                if let Some(portfolio) = &mut self.portfolio
+                    && let Err(e) = portfolio.write_snapshot_if_due()
+                {
+                    log::error!("error write portfolio snapshot: {}", e);
+                }
+            }
+            if self.shutdown.load(SeqCst) {
+                return Ok(());
+            }
+        }
+    }
+
     pub fn get_quote_stream(
         &mut self,
         udp_bind_adr: &str,
         server_adr: &str,
         tickers: String,
+        book_depth: usize,
     ) -> Result<(), QuoteClientError> {
-        let socket = UdpSocket::bind(udp_bind_adr)?;
-        socket.set_read_timeout(Some(Duration::from_secs(UDP_READ_TIMEOUT_SECOND)))?;
+        if self.transport == StreamTransport::Tcp {
+            return self.get_quote_stream_tcp(server_adr, &tickers, book_depth);
+        }
+        let started = Instant::now();
+        let mut is_received = false;
+        let mut socket = netaddr::bind_udp(udp_bind_adr)?;
+        socket.set_read_timeout(Some(Duration::from_secs(self.tuning.udp_read_timeout)))?;
+        //группа публикует все свои тикеры - лишние отбрасываются по подписке клиента
+        let subscriptions = TickerSubscription::parse_list(&tickers);
+        let mut is_multicast = false;
         let mut is_connected = false;
+        let mut was_connected = false;
         let mut udp_src_addr = String::new();
+        //ключ датаграмм текущего соединения или, в режиме многоадресной рассылки, ключ групп
+        let mut opener: Option<UdpOpener> = None;
+        //управляющее соединение открыто на время потока - для STOP при завершении
+        let mut control: Option<ControlStream> = None;
+        let mut backoff = Backoff::new(self.reconnect);
+        let mut servers = ServerList::new(server_adr, &self.failover);
+        //причина разрыва соединения, пустая строка - первое подключение
+        let mut lost = String::new();
         loop {
-            //подключаемся к серверу
+            if self.shutdown.load(SeqCst) {
+                self.close_stream(control.take());
+                return Ok(());
+            }
+            //подключаемся к серверу, после разрыва - с задержкой и повторной подпиской на те же тикеры
             if !is_connected {
-                log::info!("try connecting to server at {}", server_adr);
+                control = None;
+                if !lost.is_empty() && !self.wait_reconnect(&mut backoff, &mut servers, &lost)? {
+                    self.close_stream(None);
+                    return Ok(());
+                }
+                if was_connected {
+                    self.metrics.reconnects.fetch_add(1, Relaxed);
+                }
+                if lost.is_empty() {
+                    self.set_state(ConnectionState::Connecting);
+                }
+                log::info!("try connecting to server at {}", servers.current());
                 udp_src_addr = "".to_string();
                 while self.is_running_ping.load(SeqCst) {
                     self.is_running_ping.store(false, SeqCst);
                 }
-                match QuoteStreamClient::connect(server_adr) {
-                    Ok(stream) => {
-                        let mut writer = stream.try_clone().expect("failed to clone stream");
-                        let mut reader = BufReader::new(stream);
-                        let mut result = String::new();
-                        //отправляем команду для получения данных
-                        writer.write_all(
-                            format!("STREAM udp://{} {}\n", udp_bind_adr, tickers).as_bytes(),
-                        )?;
-                        writer.flush()?;
-                        loop {
-                            match reader.read_line(&mut result) {
-                                Ok(0) => {
-                                    is_connected = false;
-                                    break;
-                                }
-                                Ok(_) => {
-                                    //сервер ответил сообщение ОК, коннект установлен
-                                    if result.contains("OK") {
-                                        is_connected = true;
-                                        break;
-                                    }
-                                }
-                                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                                    result.clear();
-                                    log::error!("waiting for server response...");
-                                    thread::sleep(Duration::from_millis(DURATION_WAIT_TO_CONNECT));
-                                    continue;
-                                }
-                                Err(_) => {
-                                    is_connected = false;
-                                    break;
-                                }
-                            }
-                        }
-                    }
+                let stream = match self.open_control(servers.current()) {
+                    Ok(stream) => stream,
                     Err(e) => {
-                        thread::sleep(Duration::from_secs(DURATION_WAIT_TO_CONNECT));
                         log::error!("error connect to server: {}", e);
-                        return Err(QuoteClientError::BadNetworkBindSocket(format!(
-                            "Error connect address {}, error: {}",
-                            server_adr, e.to_string()
-                        )));
+                        lost = e.to_string();
+                        continue;
+                    }
+                };
+                let (writer, mut session, multicast) = match self.start_udp_stream(stream, &socket, &tickers) {
+                    Ok(started) => started,
+                    Err(QuoteClientError::ConnectionLost(e)) => {
+                        log::error!("connection lost: {}", e);
+                        //CHALLENGE не дошел по UDP - в режиме auto переход на TCP поток, а не на другой сервер
+                        if let Some(fallback_timeout) = self.udp_fallback(is_received, started) {
+                            log::warn!("no udp data in {:?}, fallback to tcp stream", fallback_timeout);
+                            return self.get_quote_stream_tcp(servers.current(), &tickers, book_depth);
+                        }
+                        lost = e;
+                        continue;
                     }
+                    Err(e) => return Err(e),
+                };
+                //сервер ответил OK Multicast - прием рассылки групп по ключу групп
+                if let Some(reply) = multicast {
+                    let (group_socket, group_opener) = self.join_multicast(&reply, &mut session)?;
+                    socket = group_socket;
+                    session = group_opener;
+                    is_multicast = true;
                 }
+                opener = Some(session);
+                //после разрыва повторы последних сообщений отбрасываются
+                if was_connected {
+                    self.dedup.reconnected();
+                }
+                is_connected = true;
+                was_connected = true;
+                self.set_state(ConnectionState::Connected);
+                backoff.reset();
+                servers.connected();
+                lost.clear();
+                control = Some(writer);
             }
             let mut quote = [0u8; 1024];
             // читаем данные из udp сокета
             match socket.recv_from(&mut quote) {
                 Ok((0, _)) => {
                     is_connected = false;
+                    lost = "empty datagram".to_string();
                     continue;
                 }
                 //данные по котировкам
                 Ok((size, src)) => {
-                    if size > 0 {
-                        if let Some(quote) = StockQuote::from_string(
-                            String::from_utf8_lossy(&quote[..size]).as_ref(),
-                        ) {
-                            println!("{}", quote.to_json()?);
+                    self.metrics.datagrams_received.fetch_add(1, Relaxed);
+                    let payload = match &mut opener {
+                        Some(opener) => self.open_datagram(opener, &quote[..size]),
+                        None => Some(quote[..size].to_vec()),
+                    };
+                    //отброшенная датаграмма не меняет адрес PING и не считается данными потока
+                    if let Some(payload) = &payload
+                        && !payload.is_empty()
+                    {
+                        is_received = true;
+                        match MarketMessage::from_string(String::from_utf8_lossy(payload).as_ref()) {
+                            Some(quote) => {
+                                self.metrics.observe_latency(&quote);
+                                if !is_multicast
+                                    || subscriptions.iter().any(|subscription| subscription.accepts(&quote))
+                                {
+                                    self.process_message(quote, book_depth)?;
+                                }
+                            }
+                            None => {
+                                self.metrics.parse_failures.fetch_add(1, Relaxed);
+                            }
                         }
                     }
                     //определяеи адрес отправителя, чтоб отправить сообщения PING
-                    if src.to_string() != udp_src_addr {
+                    //группе многоадресной рассылки PING не нужен
+                    if payload.is_some() && !is_multicast && src.to_string() != udp_src_addr {
                         udp_src_addr = src.to_string();
                         while self.is_running_ping.load(SeqCst) {
                             self.is_running_ping.store(false, SeqCst);
@@ -164,11 +874,13 @@ impl QuoteStreamClient {
                             *server_adr = src.to_string();
                             let is_running_ping = self.is_running_ping.clone();
                             let server_adr = self.remote_add.clone();
+                            let ping_send_period = self.tuning.ping_send_period;
                             thread::spawn(move || {
                                 QuoteStreamClient::thread_ping_quote_server(
                                     udp,
                                     server_adr,
                                     is_running_ping,
+                                    ping_send_period,
                                 )
                             });
                             while !self.is_running_ping.load(SeqCst) {
@@ -178,13 +890,26 @@ impl QuoteStreamClient {
                         }
                     }
                 }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
                 Err(e) => {
                     log::error!("error read quote stream: {}", e);
                     is_connected = false;
+                    lost = format!("no quote stream: {}", e);
                 }
             }
-            if !self.is_running_ping.load(SeqCst) {
+            if let Some(portfolio) = &mut self.portfolio
+                && let Err(e) = portfolio.write_snapshot_if_due()
+            {
+                log::error!("error write portfolio snapshot: {}", e);
+            }
+            if is_connected && !is_multicast && !self.is_running_ping.load(SeqCst) {
                 is_connected = false;
+                lost = "ping stopped".to_string();
+            }
+            //UDP блокируется - переход на TCP поток
+            if let Some(fallback_timeout) = self.udp_fallback(is_received, started) {
+                log::warn!("no udp data in {:?}, fallback to tcp stream", fallback_timeout);
+                return self.get_quote_stream_tcp(servers.current(), &tickers, book_depth);
             }
         }
     }
@@ -193,11 +918,13 @@ impl QuoteStreamClient {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::net::TcpListener;
+
     #[test]
     fn test_connect() {
         //error test
         let url = "127.0.0.1:8120";
-        let test_connect = QuoteStreamClient::connect(url);
+        let test_connect = QuoteStreamClient::connect(url, None);
         assert_eq!(
             test_connect.err().unwrap(),
             QuoteClientError::BadNetworkBindSocket("Connection refused (os error 111)".to_string())
@@ -210,7 +937,9 @@ mod test {
         let url = "127.0.0.1:8120";
         let tickers = "MSFT,GOOG,AAPL".to_string();
         let mut test_client = QuoteStreamClient::default();
-        let test_connect = test_client.get_quote_stream(url, url, tickers);
+        //без переподключения: ошибка подключения возвращается сразу
+        test_client.set_reconnect(ReconnectPolicy { max_attempts: Some(0), ..ReconnectPolicy::default() });
+        let test_connect = test_client.get_quote_stream(url, url, tickers, 5);
         assert_eq!(
             test_connect.err().unwrap(),
             QuoteClientError::BadNetworkBindSocket(
@@ -218,4 +947,91 @@ mod test {
             )
         );
     }
+
+    #[test]
+    fn test_failover_silent_server() {
+        //основной сервер принимает соединение и не отвечает - клиент переходит на резервный
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let backup = TcpListener::bind("127.0.0.1:0").unwrap();
+        let primary = silent.local_addr().unwrap().to_string();
+        let failover = backup.local_addr().unwrap().to_string();
+        let client = thread::spawn(move || {
+            let mut test_client = QuoteStreamClient::default();
+            test_client.set_failover(vec![failover]);
+            test_client.set_reconnect(ReconnectPolicy { max_attempts: Some(0), ..ReconnectPolicy::default() });
+            let result = test_client.get_quote_stream("127.0.0.1:0", &primary, "AAPL".to_string(), 5);
+            (result, test_client.metrics.failovers.load(Relaxed))
+        });
+        let (_primary_stream, _) = silent.accept().unwrap();
+        //резервный сервер закрывает соединение - круг серверов завершен, попыток больше нет
+        drop(backup.accept().unwrap());
+        let (result, failovers) = client.join().unwrap();
+        assert_eq!(failovers, 1);
+        assert!(matches!(result, Err(QuoteClientError::BadNetworkBindSocket(_))));
+    }
+
+    #[test]
+    fn test_auto_fallback_without_challenge() {
+        //сервер отвечает OK Challenge, но UDP не доходит - в режиме auto клиент переходит на TCP поток
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap().to_string();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let client_shutdown = shutdown.clone();
+        let client = thread::spawn(move || {
+            let mut test_client = QuoteStreamClient::default();
+            test_client.set_transport(StreamTransport::Auto(Duration::from_secs(1)));
+            test_client.set_shutdown(client_shutdown);
+            let result = test_client.get_quote_stream("127.0.0.1:0", &server_addr, "A".to_string(), 5);
+            (result, test_client.metrics.failovers.load(Relaxed))
+        });
+        let (udp_control, _) = server.accept().unwrap();
+        let mut reader = BufReader::new(udp_control.try_clone().unwrap());
+        let mut writer = udp_control;
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let client_public = line.trim().strip_prefix("KEY ").unwrap().to_string();
+        let exchange = KeyExchange::new().unwrap();
+        writer.write_all(format!("OK Key {}\n", exchange.public_hex()).as_bytes()).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("STREAM udp://"), "{}", line);
+        assert!(exchange.derive(&client_public, true).is_ok());
+        writer.write_all(b"OK Challenge\n").unwrap();
+
+        //второе соединение - поток котировок по TCP
+        let (tcp_control, _) = server.accept().unwrap();
+        let mut reader = BufReader::new(tcp_control.try_clone().unwrap());
+        let mut writer = tcp_control;
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "STREAM tcp A\n");
+        writer.write_all(b"OK Stream\nT|A|1|10|100|R|1000\n").unwrap();
+        shutdown.store(true, SeqCst);
+        let (result, failovers) = client.join().unwrap();
+        assert!(result.is_ok());
+        assert_eq!(failovers, 0);
+    }
+
+    #[test]
+    fn test_handle_book_messages() {
+        let mut test_client = QuoteStreamClient::default();
+        let snapshot = MarketMessage::from_string("B|A|1|1000|10:100;9.99:200|10.01:300").unwrap();
+        let line = test_client.handle_message(snapshot, 1).unwrap().unwrap();
+        assert!(line.contains("\"bids\": [[10, 100]]"));
+        let update = MarketMessage::from_string("U|A|1|3|2000|b:10:0").unwrap();
+        let line = test_client.handle_message(update, 1).unwrap().unwrap();
+        assert!(line.contains("\"bids\": [[9.99, 200]]"));
+        //пропуск обновления - стакан сбрасывается до следующего снимка
+        let gap = MarketMessage::from_string("U|A|5|6|3000|a:10.01:0").unwrap();
+        assert!(test_client.handle_message(gap, 1).unwrap().is_none());
+        assert!(test_client.books.is_empty());
+    }
+
+    #[test]
+    fn test_stream_transport_parse() {
+        let timeout = Duration::from_secs(10);
+        assert_eq!(StreamTransport::parse("tcp", timeout), Some(StreamTransport::Tcp));
+        assert_eq!(StreamTransport::parse("auto", timeout), Some(StreamTransport::Auto(timeout)));
+        assert!(StreamTransport::parse("quic", timeout).is_none());
+    }
 }
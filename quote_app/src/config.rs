@@ -0,0 +1,403 @@
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use std::str::FromStr;
+use toml::{Table, Value};
+
+//префикс переменных окружения: `--server-addr` - QUOTE_SERVER_ADDR, `stream.ping_read_timeout` -
+//QUOTE_STREAM_PING_READ_TIMEOUT
+const ENV_PREFIX: &str = "QUOTE_";
+//аргументы командной строки, которых нет в файле настроек
+const NOT_IN_FILE: [&str; 3] = ["config", "help", "version"];
+//ключи секций файла настроек: параметры, ранее заданные константами
+const TUNING_KEYS: [&str; 8] = [
+    "stream.udp_send_period",
+    "stream.ping_read_timeout",
+    "stream.udp_read_timeout",
+    "client.udp_read_timeout",
+    "client.ping_send_period",
+    "generator.period_millisecond",
+    "generator.low_price",
+    "generator.big_price",
+];
+//тикеры с диапазоном цены big_price
+const BIG_PRICE_TICKERS_KEY: &str = "generator.big_price_tickers";
+
+//значение настройки из файла TOML или переменной окружения
+pub(crate) trait ConfigValue: FromStr + Clone + Send + Sync + 'static {
+    fn from_toml(value: &Value) -> Option<Self>;
+    fn expected() -> &'static str;
+}
+
+impl ConfigValue for String {
+    fn from_toml(value: &Value) -> Option<Self> {
+        //номер порта можно задать числом
+        match value {
+            Value::String(value) => Some(value.clone()),
+            Value::Integer(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    fn expected() -> &'static str {
+        "string"
+    }
+}
+
+impl ConfigValue for bool {
+    fn from_toml(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+
+    fn expected() -> &'static str {
+        "boolean"
+    }
+}
+
+impl ConfigValue for f64 {
+    fn from_toml(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(value) => Some(*value),
+            Value::Integer(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    fn expected() -> &'static str {
+        "number"
+    }
+}
+
+macro_rules! config_integer {
+    ($($type:ty),*) => {$(
+        impl ConfigValue for $type {
+            fn from_toml(value: &Value) -> Option<Self> {
+                value.as_integer().and_then(|value| <$type>::try_from(value).ok())
+            }
+
+            fn expected() -> &'static str {
+                "non-negative integer"
+            }
+        }
+    )*};
+}
+
+config_integer!(u16, u32, u64, usize);
+
+//параметры секций [stream], [client] и [generator], None - значение по умолчанию модуля
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Tuning {
+    #[cfg(any(feature = "server", test))]
+    pub(crate) stream_udp_send_period: Option<u64>,
+    #[cfg(any(feature = "server", test))]
+    pub(crate) stream_ping_read_timeout: Option<u64>,
+    #[cfg(any(feature = "server", test))]
+    pub(crate) stream_udp_read_timeout: Option<u64>,
+    #[cfg(feature = "client")]
+    pub(crate) client_udp_read_timeout: Option<u64>,
+    #[cfg(feature = "client")]
+    pub(crate) client_ping_send_period: Option<u64>,
+    #[cfg(feature = "server")]
+    pub(crate) generator_period_millisecond: Option<u64>,
+    #[cfg(feature = "server")]
+    pub(crate) generator_low_price: Option<f64>,
+    #[cfg(feature = "server")]
+    pub(crate) generator_big_price: Option<f64>,
+    #[cfg(any(feature = "server", test))]
+    pub(crate) generator_big_price_tickers: Option<Vec<String>>,
+}
+
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace(['-', '.'], "_").to_uppercase())
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    //ключ секции через точку: `stream.udp_send_period`
+    match key.split_once('.') {
+        Some((section, key)) => table.get(section)?.as_table()?.get(key),
+        None => table.get(key),
+    }
+}
+
+//настройки: командная строка, затем переменные окружения, затем файл, затем значения по умолчанию
+pub(crate) struct Config<'a> {
+    matches: &'a ArgMatches,
+    path: String,
+    file: Table,
+}
+
+impl<'a> Config<'a> {
+    pub(crate) fn load(command: &Command, matches: &'a ArgMatches) -> Result<Self, String> {
+        //файл `--config` или QUOTE_CONFIG, без файла - только командная строка и окружение
+        let path = matches
+            .get_one::<String>("config")
+            .cloned()
+            .or_else(|| std::env::var(env_name("config")).ok());
+        let Some(path) = path else {
+            return Ok(Self { matches, path: String::new(), file: Table::new() });
+        };
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let file = text.parse::<Table>().map_err(|e| format!("{}: {}", path, e))?;
+        let config = Self { matches, path, file };
+        config.check_keys(command)?;
+        Ok(config)
+    }
+
+    fn check_keys(&self, command: &Command) -> Result<(), String> {
+        //неизвестный ключ - ошибка, а не молча пропущенная опечатка
+        let arguments: Vec<String> = command
+            .get_arguments()
+            .map(|arg| arg.get_id().as_str())
+            .filter(|id| !NOT_IN_FILE.contains(id))
+            .map(|id| id.replace('-', "_"))
+            .collect();
+        for (key, value) in &self.file {
+            match value.as_table() {
+                Some(section) => {
+                    for name in section.keys() {
+                        let key = format!("{}.{}", key, name);
+                        if !TUNING_KEYS.contains(&key.as_str()) && key != BIG_PRICE_TICKERS_KEY {
+                            return Err(format!("{}: unknown key `{}`", self.path, key));
+                        }
+                    }
+                }
+                None if !arguments.contains(key) => {
+                    return Err(format!("{}: unknown key `{}`", self.path, key));
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn from_env<T: ConfigValue>(key: &str) -> Result<Option<T>, String> {
+        let name = env_name(key);
+        match std::env::var(&name) {
+            Ok(value) => value
+                .parse::<T>()
+                .map(Some)
+                .map_err(|_| format!("{}: expected {}, got `{}`", name, T::expected(), value)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn file_value<T: ConfigValue>(&self, key: &str) -> Result<Option<T>, String> {
+        let file_key = key.replace('-', "_");
+        match lookup(&self.file, &file_key) {
+            Some(value) => T::from_toml(value).map(Some).ok_or_else(|| {
+                format!("{}: key `{}`: expected {}, got `{}`", self.path, file_key, T::expected(), value)
+            }),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn value<T: ConfigValue>(&self, id: &str) -> Result<Option<T>, String> {
+        //значение аргумента: тип совпадает с value_parser аргумента
+        if self.matches.value_source(id) == Some(ValueSource::CommandLine) {
+            return Ok(self.matches.get_one::<T>(id).cloned());
+        }
+        if let Some(value) = Config::from_env::<T>(id)? {
+            return Ok(Some(value));
+        }
+        if let Some(value) = self.file_value::<T>(id)? {
+            return Ok(Some(value));
+        }
+        Ok(self.matches.get_one::<T>(id).cloned())
+    }
+
+    pub(crate) fn required<T: ConfigValue>(&self, id: &str) -> Result<T, String> {
+        self.value(id)?.ok_or_else(|| {
+            format!(
+                "missing {}: use --{}, {} or `{}` in the config file",
+                id,
+                id,
+                env_name(id),
+                id.replace('-', "_")
+            )
+        })
+    }
+
+    pub(crate) fn values(&self, id: &str) -> Result<Vec<String>, String> {
+        //список: повторяемый аргумент, значения окружения через запятую или массив строк в файле
+        if self.matches.value_source(id) == Some(ValueSource::CommandLine) {
+            return Ok(self.matches.get_many::<String>(id).into_iter().flatten().cloned().collect());
+        }
+        if let Ok(value) = std::env::var(env_name(id)) {
+            return Ok(value.split(',').map(|value| value.trim().to_string()).collect());
+        }
+        self.strings(&id.replace('-', "_")).map(Option::unwrap_or_default)
+    }
+
+    fn strings(&self, key: &str) -> Result<Option<Vec<String>>, String> {
+        let Some(value) = lookup(&self.file, key) else {
+            return Ok(None);
+        };
+        value
+            .as_array()
+            .and_then(|values| values.iter().map(|value| value.as_str().map(String::from)).collect())
+            .map(Some)
+            .ok_or_else(|| format!("{}: key `{}`: expected array of strings", self.path, key))
+    }
+
+    fn tuning_value<T: ConfigValue>(&self, key: &str) -> Result<Option<T>, String> {
+        match Config::from_env::<T>(key)? {
+            Some(value) => Ok(Some(value)),
+            None => self.file_value::<T>(key),
+        }
+    }
+
+    fn period(&self, key: &str) -> Result<Option<u64>, String> {
+        //периоды и таймауты больше нуля
+        match self.tuning_value::<u64>(key)? {
+            Some(0) => Err(format!("{}: must be greater than 0", key)),
+            value => Ok(value),
+        }
+    }
+
+    fn price(&self, key: &str) -> Result<Option<f64>, String> {
+        match self.tuning_value::<f64>(key)? {
+            Some(price) if !(price > 0.0 && price.is_finite()) => {
+                Err(format!("{}: must be a positive price, got {}", key, price))
+            }
+            value => Ok(value),
+        }
+    }
+
+    pub(crate) fn tuning(&self) -> Result<Tuning, String> {
+        let generator_big_price_tickers = match std::env::var(env_name(BIG_PRICE_TICKERS_KEY)) {
+            Ok(value) => Some(value.split(',').map(|value| value.trim().to_string()).collect()),
+            Err(_) => self.strings(BIG_PRICE_TICKERS_KEY)?,
+        };
+        //секции проверяются в обоих приложениях, сохраняются только используемые приложением
+        let stream_udp_send_period = self.period("stream.udp_send_period")?;
+        let stream_ping_read_timeout = self.period("stream.ping_read_timeout")?;
+        let stream_udp_read_timeout = self.period("stream.udp_read_timeout")?;
+        let client_udp_read_timeout = self.period("client.udp_read_timeout")?;
+        let client_ping_send_period = self.period("client.ping_send_period")?;
+        let generator_period_millisecond = self.period("generator.period_millisecond")?;
+        let generator_low_price = self.price("generator.low_price")?;
+        let generator_big_price = self.price("generator.big_price")?;
+        Ok(Tuning {
+            #[cfg(any(feature = "server", test))]
+            stream_udp_send_period,
+            #[cfg(any(feature = "server", test))]
+            stream_ping_read_timeout,
+            #[cfg(any(feature = "server", test))]
+            stream_udp_read_timeout,
+            #[cfg(feature = "client")]
+            client_udp_read_timeout,
+            #[cfg(feature = "client")]
+            client_ping_send_period,
+            #[cfg(feature = "server")]
+            generator_period_millisecond,
+            #[cfg(feature = "server")]
+            generator_low_price,
+            #[cfg(feature = "server")]
+            generator_big_price,
+            #[cfg(any(feature = "server", test))]
+            generator_big_price_tickers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Arg;
+
+    fn command() -> Command {
+        Command::new("test")
+            .arg(Arg::new("config").long("config"))
+            .arg(Arg::new("server-addr").long("server-addr"))
+            .arg(Arg::new("metrics-addr").long("metrics-addr"))
+            .arg(Arg::new("log-level").long("log-level").default_value("INFO"))
+            .arg(
+                Arg::new("book-depth")
+                    .long("book-depth")
+                    .default_value("5")
+                    .value_parser(clap::value_parser!(usize)),
+            )
+    }
+
+    fn config_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("quote-config-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let path = config_file(
+            "precedence",
+            "server_addr = \"127.0.0.1:8210\"\nlog_level = \"DEBUG\"\nbook_depth = 10\n\
+             [stream]\nping_read_timeout = 9\n[generator]\nbig_price_tickers = [\"NVDA\"]\n",
+        );
+        let command = command();
+        let matches = command
+            .clone()
+            .get_matches_from(["test", "--config", &path, "--server-addr", "127.0.0.1:9000"]);
+        let config = Config::load(&command, &matches).unwrap();
+        //командная строка, затем файл, затем значение по умолчанию
+        assert_eq!(config.value::<String>("server-addr").unwrap().unwrap(), "127.0.0.1:9000");
+        assert_eq!(config.value::<String>("log-level").unwrap().unwrap(), "DEBUG");
+        assert_eq!(config.value::<usize>("book-depth").unwrap(), Some(10));
+        let tuning = config.tuning().unwrap();
+        assert_eq!(tuning.stream_ping_read_timeout, Some(9));
+        assert_eq!(tuning.stream_udp_send_period, None);
+        assert_eq!(tuning.generator_big_price_tickers, Some(vec!["NVDA".to_string()]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_env_precedence() {
+        let path = config_file("env", "metrics_addr = \"127.0.0.1:9100\"\n[stream]\nudp_read_timeout = 3\n");
+        //переменные окружения, которые не читают другие тесты модуля
+        unsafe {
+            std::env::set_var("QUOTE_METRICS_ADDR", "127.0.0.1:9200");
+            std::env::set_var("QUOTE_STREAM_UDP_READ_TIMEOUT", "7");
+        }
+        let command = command();
+        let matches = command.clone().get_matches_from(["test", "--config", &path]);
+        let config = Config::load(&command, &matches).unwrap();
+        //окружение важнее файла
+        assert_eq!(config.value::<String>("metrics-addr").unwrap().unwrap(), "127.0.0.1:9200");
+        assert_eq!(config.tuning().unwrap().stream_udp_read_timeout, Some(7));
+        //командная строка важнее окружения
+        let matches = command
+            .clone()
+            .get_matches_from(["test", "--config", &path, "--metrics-addr", "127.0.0.1:9300"]);
+        let config = Config::load(&command, &matches).unwrap();
+        assert_eq!(config.value::<String>("metrics-addr").unwrap().unwrap(), "127.0.0.1:9300");
+        unsafe {
+            std::env::remove_var("QUOTE_METRICS_ADDR");
+            std::env::remove_var("QUOTE_STREAM_UDP_READ_TIMEOUT");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let command = command();
+        for (name, text, error) in [
+            ("unknown", "sever_addr = \"x\"\n", "unknown key `sever_addr`"),
+            ("section", "[stream]\nping_timeout = 1\n", "unknown key `stream.ping_timeout`"),
+            ("type", "book_depth = \"ten\"\n", "key `book_depth`: expected non-negative integer"),
+            ("zero", "[client]\nping_send_period = 0\n", "client.ping_send_period: must be greater than 0"),
+            ("syntax", "book_depth = \n", "TOML parse error"),
+        ] {
+            let path = config_file(name, text);
+            let matches = command.clone().get_matches_from(["test", "--config", &path]);
+            let error_text = Config::load(&command, &matches)
+                .and_then(|config| config.value::<usize>("book-depth").and(config.tuning()).map(|_| ()))
+                .unwrap_err();
+            assert!(error_text.contains(error), "{}: {}", name, error_text);
+            std::fs::remove_file(&path).unwrap();
+        }
+        let matches = command.clone().get_matches_from(["test"]);
+        let config = Config::load(&command, &matches).unwrap();
+        assert_eq!(
+            config.required::<String>("server-addr").unwrap_err(),
+            "missing server-addr: use --server-addr, QUOTE_SERVER_ADDR or `server_addr` in the config file"
+        );
+    }
+}
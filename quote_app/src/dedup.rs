@@ -0,0 +1,117 @@
+use quote_lib::quote::marketmessage::{MarketMessage, TAG_BOOK, TAG_QUOTE, TAG_STATS, TAG_TRADE};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+//сообщение старше последнего доставленного не более чем на окно - повтор после переподключения,
+//старше окна - часы другого сервера, сообщение доставляется
+const DEDUP_WINDOW_MILLISECOND: u64 = 10_000;
+//повторы отбрасываются только после переподключения: без разрыва поток выводится как есть, сек
+const DEDUP_AFTER_RECONNECT_SECOND: u64 = 10;
+
+//де-дупликация потока: повторы при переподключении и переходе на резервный сервер не выводятся
+#[derive(Default)]
+pub(crate) struct StreamDedup {
+    //версия и метка времени последнего доставленного сообщения по тикеру и тегу типа,
+    //снимок и обновление стакана - под общим тегом
+    sequences: HashMap<(String, &'static str), (u64, u64)>,
+    //время повторной подписки после разрыва, None - соединение не прерывалось
+    reconnected_at: Option<Instant>,
+}
+
+fn is_older(timestamp: u64, last_timestamp: u64) -> bool {
+    timestamp < last_timestamp && last_timestamp - timestamp <= DEDUP_WINDOW_MILLISECOND
+}
+
+fn dedup_tag(message: &MarketMessage) -> &'static str {
+    match message {
+        MarketMessage::Trade(_) => TAG_TRADE,
+        MarketMessage::Quote(_) => TAG_QUOTE,
+        MarketMessage::Stats(_) => TAG_STATS,
+        MarketMessage::Book(_) | MarketMessage::BookUpdate(_) => TAG_BOOK,
+    }
+}
+
+impl StreamDedup {
+    //подписка восстановлена после разрыва или перехода на резервный сервер
+    pub(crate) fn reconnected(&mut self) {
+        self.reconnected_at = Some(Instant::now());
+    }
+
+    fn after_reconnect(&self) -> bool {
+        self.reconnected_at
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(DEDUP_AFTER_RECONNECT_SECOND))
+    }
+
+    //последние доставленные версии запоминаются всегда, отбрасываются - только после переподключения:
+    //периодическая повторная отправка сервером последних сообщений выводится как прежде
+    pub(crate) fn accept(&mut self, message: &MarketMessage) -> bool {
+        let key = (message.ticker().to_string(), dedup_tag(message));
+        let (sequence, timestamp) = (message.sequence(), message.timestamp());
+        //версия не новее и время не новее - уже доставленное сообщение;
+        //меньшая версия с новым временем - сообщение другого сервера
+        if self.after_reconnect()
+            && let Some((last_sequence, last_timestamp)) = self.sequences.get(&key)
+            && sequence <= *last_sequence
+            && (timestamp == *last_timestamp || is_older(timestamp, *last_timestamp))
+        {
+            return false;
+        }
+        self.sequences.insert(key, (sequence, timestamp));
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(s: &str) -> MarketMessage {
+        MarketMessage::from_string(s).unwrap()
+    }
+
+    #[test]
+    fn test_replay_dropped() {
+        let mut dedup = StreamDedup::default();
+        assert!(dedup.accept(&message("T|AAPL|7|10|100|R|20000")));
+        assert!(dedup.accept(&message("S|AAPL|7|10|10|10|10|100|1|20000")));
+        dedup.reconnected();
+        //последние сообщения, повторно отправленные сервером после переподключения
+        assert!(!dedup.accept(&message("T|AAPL|7|10|100|R|20000")));
+        assert!(!dedup.accept(&message("T|AAPL|6|9.9|100|R|19000")));
+        assert!(!dedup.accept(&message("S|AAPL|7|10|10|10|10|100|1|20000")));
+        //та же миллисекунда, следующая сделка
+        assert!(dedup.accept(&message("T|AAPL|8|10|100|R|20000")));
+        assert!(dedup.accept(&message("Q|AAPL|6|10|100|11|200|19000")));
+        assert!(dedup.accept(&message("T|MSFT|1|10|100|R|19000")));
+        //сделка резервного сервера: меньшая версия, более позднее время
+        assert!(dedup.accept(&message("T|AAPL|2|10|100|R|20100")));
+        //часы резервного сервера отстают больше окна
+        assert!(dedup.accept(&message("T|AAPL|1|10|100|R|5000")));
+    }
+
+    #[test]
+    fn test_book_sequence() {
+        let mut dedup = StreamDedup::default();
+        assert!(dedup.accept(&message("B|A|5|20000|10:100|10.01:300")));
+        assert!(dedup.accept(&message("U|A|5|6|20100|b:10:0")));
+        dedup.reconnected();
+        assert!(!dedup.accept(&message("U|A|5|6|20100|b:10:0")));
+        assert!(!dedup.accept(&message("B|A|5|20000|10:100|10.01:300")));
+        //снимок резервного сервера: меньшая версия, более позднее время
+        assert!(dedup.accept(&message("B|A|2|20200|10:100|10.01:300")));
+        assert!(dedup.accept(&message("U|A|2|3|20300|b:10:0")));
+    }
+
+    #[test]
+    fn test_no_reconnect() {
+        //без разрыва повторная отправка последних сообщений сервером выводится
+        let mut dedup = StreamDedup::default();
+        assert!(dedup.accept(&message("T|AAPL|7|10|100|R|20000")));
+        assert!(dedup.accept(&message("T|AAPL|7|10|100|R|20000")));
+        assert!(dedup.accept(&message("B|A|5|20000|10:100|10.01:300")));
+        assert!(dedup.accept(&message("B|A|5|20000|10:100|10.01:300")));
+        //окно после переподключения истекло
+        dedup.reconnected_at = Instant::now().checked_sub(Duration::from_secs(DEDUP_AFTER_RECONNECT_SECOND));
+        assert!(dedup.accept(&message("T|AAPL|7|10|100|R|20000")));
+    }
+}
@@ -11,6 +11,21 @@ pub(crate) enum QuoteClientError {
     AddressParseError(String),
     #[error("Error parse value from quote: {0}")]
     ParseQuoteError(String),
+    #[error("Bad positions file: {0}")]
+    BadPositionsFile(String),
+    #[error("Bad alert rule: {0}")]
+    BadAlertRule(String),
+    #[error("Authentication error: {0}")]
+    AuthError(String),
+    #[error("Connection lost: {0}")]
+    ConnectionLost(String),
+}
+
+impl QuoteClientError {
+    //ошибка ввода-вывода установленного соединения - разрыв, клиент переподключается
+    pub(crate) fn lost(err: io::Error) -> Self {
+        QuoteClientError::ConnectionLost(err.to_string())
+    }
 }
 
 impl From<io::Error> for QuoteClientError {
@@ -17,6 +17,14 @@ pub(crate) enum QuoteStreamServerError {
     ChangeThreadStateError(String),
     #[error("Receive from channel error: {0}")]
     ReceiveQuoteError(String),
+    #[error("WebSocket error: {0}")]
+    WebSocketError(String),
+    #[error("Bad credentials file: {0}")]
+    CredentialsError(String),
+    #[error("Bad entitlements file: {0}")]
+    EntitlementsError(String),
+    #[error("Bad tickers file: {0}")]
+    TickersError(String),
 }
 
 impl From<io::Error> for QuoteStreamServerError {
@@ -30,3 +38,9 @@ impl From<SystemTimeError> for QuoteStreamServerError {
         QuoteStreamServerError::BadSetSystemTimeError(err.to_string())
     }
 }
+
+impl From<tungstenite::Error> for QuoteStreamServerError {
+    fn from(err: tungstenite::Error) -> Self {
+        QuoteStreamServerError::WebSocketError(err.to_string())
+    }
+}
@@ -0,0 +1,216 @@
+
+use quote_lib::quote::marketmessage::MarketMessage;
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//таймаут чтения запроса к порту метрик
+const METRICS_READ_TIMEOUT_SECOND: u64 = 5;
+//границы корзин гистограммы задержки, секунды
+#[cfg(feature = "client")]
+const LATENCY_BUCKETS_SECOND: [f64; 9] = [0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+//метрики в текстовом формате Prometheus
+#[derive(Default)]
+pub(crate) struct MetricsText {
+    text: String,
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl MetricsText {
+    pub(crate) fn metric(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        self.text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+        self
+    }
+
+    pub(crate) fn sample<V: Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) -> &mut Self {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+            .collect();
+        if labels.is_empty() {
+            self.text.push_str(&format!("{} {}\n", name, value));
+        } else {
+            self.text.push_str(&format!("{}{{{}}} {}\n", name, labels.join(","), value));
+        }
+        self
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) -> &mut Self {
+        //корзины накопительные: наблюдения не больше границы
+        self.metric(name, "histogram", help);
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS_SECOND.iter().zip(histogram.buckets.iter()) {
+            cumulative += bucket.load(Relaxed);
+            self.sample(&format!("{}_bucket", name), &[("le", &bound.to_string())], cumulative);
+        }
+        let count = histogram.count.load(Relaxed);
+        self.sample(&format!("{}_bucket", name), &[("le", "+Inf")], count);
+        self.sample(&format!("{}_sum", name), &[], histogram.sum_micros.load(Relaxed) as f64 / 1e6);
+        self.sample(&format!("{}_count", name), &[], count)
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.text
+    }
+}
+
+//гистограмма длительностей с корзинами LATENCY_BUCKETS_SECOND
+#[cfg(feature = "client")]
+#[derive(Default)]
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_SECOND.len()],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+#[cfg(feature = "client")]
+impl Histogram {
+    pub(crate) fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(index) = LATENCY_BUCKETS_SECOND.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index].fetch_add(1, Relaxed);
+        }
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Relaxed);
+        self.count.fetch_add(1, Relaxed);
+    }
+}
+
+//метрики клиента
+#[cfg(feature = "client")]
+#[derive(Default)]
+pub(crate) struct ClientMetrics {
+    pub(crate) datagrams_received: AtomicU64,
+    pub(crate) parse_failures: AtomicU64,
+    pub(crate) reconnects: AtomicU64,
+    //переходы на следующий сервер списка
+    pub(crate) failovers: AtomicU64,
+    //повторы, отброшенные после переподключения или перехода на другой сервер
+    pub(crate) duplicates_dropped: AtomicU64,
+    //1 - поток котировок получен, 0 - подключение или переподключение
+    pub(crate) connected: AtomicU64,
+    //отброшенные датаграммы: не прошли проверку ключом сессии или повторены
+    pub(crate) forged_datagrams: AtomicU64,
+    pub(crate) replayed_datagrams: AtomicU64,
+    //задержка: время получения минус метка времени сообщения сервера
+    pub(crate) latency: Histogram,
+}
+
+#[cfg(feature = "client")]
+impl ClientMetrics {
+    pub(crate) fn observe_latency(&self, message: &MarketMessage) {
+        let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) else {
+            return;
+        };
+        let latency = (now.as_millis() as u64).saturating_sub(message.timestamp());
+        self.latency.observe(Duration::from_millis(latency));
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut text = MetricsText::default();
+        text.metric("quote_client_datagrams_received_total", "counter", "Datagrams received from the quote stream")
+            .sample("quote_client_datagrams_received_total", &[], self.datagrams_received.load(Relaxed));
+        text.metric("quote_client_parse_failures_total", "counter", "Stream messages that failed to parse")
+            .sample("quote_client_parse_failures_total", &[], self.parse_failures.load(Relaxed));
+        text.metric("quote_client_reconnects_total", "counter", "Reconnects to the quote server")
+            .sample("quote_client_reconnects_total", &[], self.reconnects.load(Relaxed));
+        text.metric("quote_client_failovers_total", "counter", "Switches to the next quote server in the list")
+            .sample("quote_client_failovers_total", &[], self.failovers.load(Relaxed));
+        text.metric("quote_client_duplicates_dropped_total", "counter", "Messages repeated after a reconnect or failover")
+            .sample("quote_client_duplicates_dropped_total", &[], self.duplicates_dropped.load(Relaxed));
+        text.metric("quote_client_connected", "gauge", "Whether the client is connected to the quote server")
+            .sample("quote_client_connected", &[], self.connected.load(Relaxed));
+        text.metric("quote_client_dropped_datagrams_total", "counter", "Datagrams dropped by the session key check")
+            .sample("quote_client_dropped_datagrams_total", &[("reason", "forged")], self.forged_datagrams.load(Relaxed))
+            .sample(
+                "quote_client_dropped_datagrams_total",
+                &[("reason", "replayed")],
+                self.replayed_datagrams.load(Relaxed),
+            );
+        text.histogram(
+            "quote_client_latency_seconds",
+            "Receive time minus server message timestamp",
+            &self.latency,
+        );
+        text.finish()
+    }
+}
+
+fn handle_metrics_client<F: Fn() -> String>(mut stream: TcpStream, render: &F) -> std::io::Result<()> {
+    //GET /metrics, заголовки запроса пропускаются
+    stream.set_read_timeout(Some(Duration::from_secs(METRICS_READ_TIMEOUT_SECOND)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", render()),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+pub(crate) fn run_metrics_server<F: Fn() -> String>(metrics_bind: &str, render: F) -> std::io::Result<()> {
+    //порт метрик Prometheus: запросы обрабатываются последовательно
+    let listener = TcpListener::bind(metrics_bind)?;
+    log::info!("metrics listening on: {}", metrics_bind);
+    for stream in listener.incoming() {
+        if let Err(e) = handle_metrics_client(stream?, &render) {
+            log::error!("metrics client error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics_text() {
+        let mut text = MetricsText::default();
+        text.metric("m_total", "counter", "Help")
+            .sample("m_total", &[("peer", "a\"b")], 3);
+        assert_eq!(text.finish(), "# HELP m_total Help\n# TYPE m_total counter\nm_total{peer=\"a\\\"b\"} 3\n");
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_client_metrics_render() {
+        let metrics = ClientMetrics::default();
+        metrics.datagrams_received.fetch_add(2, Relaxed);
+        metrics.forged_datagrams.fetch_add(1, Relaxed);
+        metrics.connected.store(1, Relaxed);
+        metrics.latency.observe(Duration::from_millis(20));
+        metrics.latency.observe(Duration::from_secs(20));
+        let text = metrics.render();
+        assert!(text.contains("quote_client_datagrams_received_total 2\n"));
+        assert!(text.contains("quote_client_dropped_datagrams_total{reason=\"forged\"} 1\n"));
+        assert!(text.contains("quote_client_connected 1\n"));
+        assert!(text.contains("quote_client_dropped_datagrams_total{reason=\"replayed\"} 0\n"));
+        assert!(text.contains("quote_client_latency_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(text.contains("quote_client_latency_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(text.contains("quote_client_latency_seconds_bucket{le=\"10\"} 1\n"));
+        assert!(text.contains("quote_client_latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("quote_client_latency_seconds_sum 20.02\n"));
+    }
+}
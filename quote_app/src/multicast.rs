@@ -0,0 +1,177 @@
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+//TTL (hop limit) многоадресных датаграмм по умолчанию - только локальный сегмент сети
+pub(crate) const DEFAULT_MULTICAST_TTL: u32 = 1;
+
+//группа многоадресной рассылки и тикеры, публикуемые в нее (None - все тикеры)
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MulticastGroup {
+    pub(crate) addr: SocketAddr,
+    pub(crate) tickers: Option<Vec<String>>,
+}
+
+impl MulticastGroup {
+    pub(crate) fn parse(spec: &str) -> Option<Self> {
+        //<group:port> или <group:port>=<ticker>,<ticker>, IPv6: [ff02::1]:56000
+        let (addr, tickers) = match spec.split_once('=') {
+            Some((addr, tickers)) => {
+                let tickers: Vec<String> = tickers
+                    .split(',')
+                    .map(|ticker| ticker.trim().to_string())
+                    .filter(|ticker| !ticker.is_empty())
+                    .collect();
+                if tickers.is_empty() {
+                    return None;
+                }
+                (addr, Some(tickers))
+            }
+            None => (spec, None),
+        };
+        let addr = addr.trim().parse::<SocketAddr>().ok()?;
+        if !addr.ip().is_multicast() {
+            return None;
+        }
+        Some(Self { addr, tickers })
+    }
+
+    #[cfg(any(feature = "server", test))]
+    pub(crate) fn publishes(&self, ticker: &str) -> bool {
+        match &self.tickers {
+            Some(tickers) => tickers.iter().any(|group_ticker| group_ticker == ticker),
+            None => true,
+        }
+    }
+}
+
+//параметры сокетов многоадресной рассылки
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MulticastOptions {
+    pub(crate) ttl: u32,
+    pub(crate) loopback: bool,
+    //IPv4 адрес интерфейса или индекс интерфейса IPv6
+    pub(crate) interface: Option<String>,
+}
+
+impl Default for MulticastOptions {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_MULTICAST_TTL,
+            loopback: true,
+            interface: None,
+        }
+    }
+}
+
+impl MulticastOptions {
+    fn interface_v4(&self) -> io::Result<Ipv4Addr> {
+        match &self.interface {
+            Some(interface) => interface.parse::<Ipv4Addr>().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("bad IPv4 interface {}", interface))
+            }),
+            None => Ok(Ipv4Addr::UNSPECIFIED),
+        }
+    }
+
+    fn interface_v6(&self) -> io::Result<u32> {
+        match &self.interface {
+            Some(interface) => interface.parse::<u32>().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("bad IPv6 interface index {}", interface))
+            }),
+            None => Ok(0),
+        }
+    }
+
+    #[cfg(any(feature = "server", test))]
+    pub(crate) fn sender_socket(&self, group: &SocketAddr) -> io::Result<UdpSocket> {
+        //сокет сервера для отправки в группу
+        let socket = Socket::new(Domain::for_address(*group), Type::DGRAM, Some(Protocol::UDP))?;
+        match group.ip() {
+            IpAddr::V4(_) => {
+                socket.set_multicast_ttl_v4(self.ttl)?;
+                socket.set_multicast_loop_v4(self.loopback)?;
+                socket.set_multicast_if_v4(&self.interface_v4()?)?;
+                socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into())?;
+            }
+            IpAddr::V6(_) => {
+                socket.set_multicast_hops_v6(self.ttl)?;
+                socket.set_multicast_loop_v6(self.loopback)?;
+                socket.set_multicast_if_v6(self.interface_v6()?)?;
+                socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)).into())?;
+            }
+        }
+        Ok(socket.into())
+    }
+
+    #[cfg(any(feature = "client", test))]
+    pub(crate) fn receiver_socket(&self, groups: &[SocketAddr]) -> io::Result<UdpSocket> {
+        //сокет клиента привязывается к порту первой группы и вступает во все группы с этим портом
+        let Some(first) = groups.first() else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no multicast groups"));
+        };
+        let socket = Socket::new(Domain::for_address(*first), Type::DGRAM, Some(Protocol::UDP))?;
+        //несколько клиентов на одном хосте слушают один порт группы
+        socket.set_reuse_address(true)?;
+        match first.ip() {
+            IpAddr::V4(_) => {
+                socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, first.port())).into())?
+            }
+            IpAddr::V6(_) => {
+                socket.set_only_v6(true)?;
+                socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, first.port())).into())?
+            }
+        }
+        for group in groups {
+            match group.ip() {
+                IpAddr::V4(ip) if first.is_ipv4() && group.port() == first.port() => {
+                    socket.join_multicast_v4(&ip, &self.interface_v4()?)?
+                }
+                IpAddr::V6(ip) if first.is_ipv6() && group.port() == first.port() => {
+                    socket.join_multicast_v6(&ip, self.interface_v6()?)?
+                }
+                _ => log::warn!("skip multicast group {}: address family or port differs from {}", group, first),
+            }
+        }
+        Ok(socket.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_group() {
+        let group = MulticastGroup::parse("239.1.1.1:56000=AAPL, MSFT").unwrap();
+        assert_eq!(group.addr, "239.1.1.1:56000".parse().unwrap());
+        assert!(group.publishes("MSFT"));
+        assert!(!group.publishes("GOOGL"));
+        let group = MulticastGroup::parse("[ff02::1234]:56000").unwrap();
+        assert!(group.publishes("GOOGL"));
+        //не многоадресный адрес
+        assert!(MulticastGroup::parse("127.0.0.1:56000").is_none());
+        assert!(MulticastGroup::parse("239.1.1.1:56000=").is_none());
+    }
+
+    #[test]
+    fn test_loopback_delivery() {
+        let options = MulticastOptions {
+            interface: Some("127.0.0.1".to_string()),
+            ..Default::default()
+        };
+        let group: SocketAddr = "239.255.42.99:56731".parse().unwrap();
+        //в окружении без маршрута многоадресной рассылки тест пропускается
+        let Ok(receiver) = options.receiver_socket(&[group]) else {
+            return;
+        };
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(1))).unwrap();
+        let sender = options.sender_socket(&group).unwrap();
+        sender.send_to(b"T|A|1|10|100|R|1000\n", group).unwrap();
+        let mut data = [0u8; 64];
+        if let Ok((size, _)) = receiver.recv_from(&mut data) {
+            assert_eq!(&data[..size], b"T|A|1|10|100|R|1000\n");
+        }
+    }
+}
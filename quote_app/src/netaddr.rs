@@ -0,0 +1,139 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs, UdpSocket};
+
+//очередь ожидающих соединений сервера
+#[cfg(any(feature = "server", test))]
+const LISTEN_BACKLOG: i32 = 128;
+
+//адрес `ip:port`, `[ipv6]:port` или `host:port` - имя хоста разрешается в список адресов
+pub(crate) fn resolve(addr: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", addr)));
+    }
+    Ok(addrs)
+}
+
+//хост без порта и квадратных скобок IPv6: `[::1]:8210` - `::1`
+pub(crate) fn host(addr: &str) -> Option<&str> {
+    let (host, port) = addr.rsplit_once(':')?;
+    port.parse::<u16>().ok()?;
+    let host = match host.strip_prefix('[') {
+        Some(host) => host.strip_suffix(']')?,
+        //IPv6 адрес с портом записывается только в скобках
+        None if host.contains(':') => return None,
+        None => host,
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+//адрес из хоста и порта, IPv6 в квадратных скобках
+pub(crate) fn with_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+//адрес "любой интерфейс" того же семейства, что и адрес сервера
+#[cfg(feature = "client")]
+pub(crate) fn unspecified(like: &SocketAddr, port: u16) -> SocketAddr {
+    match like {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port),
+    }
+}
+
+fn socket(addr: &SocketAddr, kind: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(*addr), kind, Some(protocol))?;
+    //`[::]` принимает и IPv4 клиентов независимо от net.ipv6.bindv6only
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    Ok(socket)
+}
+
+pub(crate) fn bind_udp(addr: &str) -> io::Result<UdpSocket> {
+    let mut error = None;
+    for addr in resolve(addr)? {
+        match socket(&addr, Type::DGRAM, Protocol::UDP).and_then(|socket| {
+            socket.bind(&addr.into())?;
+            Ok(socket)
+        }) {
+            Ok(socket) => return Ok(socket.into()),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable)))
+}
+
+#[cfg(any(feature = "server", test))]
+pub(crate) fn listen_tcp(addr: &str) -> io::Result<TcpListener> {
+    let mut error = None;
+    for addr in resolve(addr)? {
+        match socket(&addr, Type::STREAM, Protocol::TCP).and_then(|socket| {
+            socket.set_reuse_address(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(LISTEN_BACKLOG)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => return Ok(socket.into()),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable)))
+}
+
+//адрес получателя для сокета: IPv4 на сокете IPv6 - `::ffff:a.b.c.d`, и наоборот
+#[cfg(any(feature = "server", test))]
+pub(crate) fn for_socket(target: SocketAddr, local: &SocketAddr) -> SocketAddr {
+    match (target, local) {
+        (SocketAddr::V4(v4), SocketAddr::V6(_)) => {
+            SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port())
+        }
+        (SocketAddr::V6(v6), SocketAddr::V4(_)) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), v6.port()),
+            None => target,
+        },
+        _ => target,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        assert_eq!(host("127.0.0.1:8210"), Some("127.0.0.1"));
+        assert_eq!(host("[::1]:8210"), Some("::1"));
+        assert_eq!(host("quotes.example.com:8210"), Some("quotes.example.com"));
+        assert_eq!(host("::1:8210"), None);
+        assert_eq!(host("127.0.0.1"), None);
+        assert_eq!(with_port("::1", 55500), "[::1]:55500");
+        assert_eq!(with_port("localhost", 55500), "localhost:55500");
+        assert!(resolve("localhost:8210").unwrap().iter().all(|addr| addr.port() == 8210));
+    }
+
+    #[test]
+    fn test_dual_stack() {
+        //сокет `[::]` получает датаграммы IPv4 и отправляет на адрес IPv4 в виде ::ffff:a.b.c.d
+        let receiver = bind_udp("[::]:0").unwrap();
+        let port = receiver.local_addr().unwrap().port();
+        let sender = bind_udp("127.0.0.1:0").unwrap();
+        sender.send_to(b"PING", ("127.0.0.1", port)).unwrap();
+        let mut data = [0u8; 16];
+        let (size, src) = receiver.recv_from(&mut data).unwrap();
+        assert_eq!(&data[..size], b"PING");
+        assert_eq!(src.ip().to_canonical(), sender.local_addr().unwrap().ip());
+        let target = for_socket(sender.local_addr().unwrap(), &receiver.local_addr().unwrap());
+        assert!(target.is_ipv6());
+        receiver.send_to(b"PONG", target).unwrap();
+        let (size, _) = sender.recv_from(&mut data).unwrap();
+        assert_eq!(&data[..size], b"PONG");
+        let listener = listen_tcp("[::]:0").unwrap();
+        assert!(std::net::TcpStream::connect(("127.0.0.1", listener.local_addr().unwrap().port())).is_ok());
+    }
+}
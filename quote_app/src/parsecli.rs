@@ -1,14 +1,88 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 use log::LevelFilter;
 use std::path::Path;
+use crate::config::{Config, Tuning};
+use crate::multicast::{MulticastGroup, MulticastOptions};
+use crate::netaddr;
 
 
 pub(crate) struct CliArgs{
     pub server_addr: String,
-    pub udp_addr: String,
+    //локальные адреса TCP и UDP и адрес потока UDP клиента, None - адрес по умолчанию
+    pub tcp_local: Option<String>,
+    pub udp_local: Option<String>,
+    #[cfg(feature = "client")]
+    pub udp_remote: Option<String>,
+    pub udp_port: Option<u16>,
+    //резервные серверы клиента в порядке перехода
+    #[cfg(feature = "client")]
+    pub failover_addrs: Vec<String>,
+    //переподключение клиента: попытки (None - без ограничения) и задержки, мс
+    #[cfg(feature = "client")]
+    pub reconnect_attempts: Option<u32>,
+    #[cfg(feature = "client")]
+    pub reconnect_delay: u64,
+    #[cfg(feature = "client")]
+    pub reconnect_max_delay: u64,
     pub tickers_file: String,
     pub log_level: LevelFilter,
-    pub file_log: String
+    pub file_log: String,
+    pub book_depth: usize,
+    #[cfg(feature = "client")]
+    pub positions_file: Option<String>,
+    #[cfg(feature = "client")]
+    pub pnl_snapshot_file: Option<String>,
+    #[cfg(feature = "client")]
+    pub pnl_snapshot_period: u64,
+    #[cfg(feature = "client")]
+    pub alert_rules_file: Option<String>,
+    #[cfg(feature = "client")]
+    pub alert_output: String,
+    #[cfg(feature = "server")]
+    pub multicast_groups: Vec<MulticastGroup>,
+    pub multicast_options: MulticastOptions,
+    #[cfg(feature = "client")]
+    pub transport: String,
+    #[cfg(feature = "client")]
+    pub fallback_timeout: u64,
+    #[cfg(feature = "server")]
+    pub ws_addr: Option<String>,
+    #[cfg(feature = "server")]
+    pub http_addr: Option<String>,
+    pub metrics_addr: Option<String>,
+    #[cfg(feature = "server")]
+    pub admin_addr: Option<String>,
+    #[cfg(feature = "server")]
+    pub admin_allow_remote: bool,
+    #[cfg(feature = "server")]
+    pub credentials_file: Option<String>,
+    #[cfg(feature = "server")]
+    pub entitlements_file: Option<String>,
+    //аргумент команды AUTH клиента: `<token>` или `<user> <password>`
+    #[cfg(feature = "client")]
+    pub auth: Option<String>,
+    #[cfg(feature = "server")]
+    pub allow_any_udp_target: bool,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_ca: Option<String>,
+    #[cfg(feature = "client")]
+    pub tls_server_name: Option<String>,
+    //ограничения сервера, None - без ограничения
+    #[cfg(feature = "server")]
+    pub max_sessions: Option<usize>,
+    #[cfg(feature = "server")]
+    pub max_sessions_per_ip: Option<usize>,
+    #[cfg(feature = "server")]
+    pub max_tickers: Option<usize>,
+    #[cfg(feature = "server")]
+    pub max_commands_per_second: Option<u32>,
+    #[cfg(feature = "server")]
+    pub max_datagram_rate: Option<u32>,
+    #[cfg(feature = "server")]
+    pub watch_tickers: bool,
+    //параметры потока, клиента и генератора из секций файла настроек
+    pub tuning: Tuning
 }
 
 impl CliArgs{
@@ -17,29 +91,59 @@ impl CliArgs{
         let file_log = "quote-client.log";
         #[cfg(feature="server")]
         let file_log = "quote-server.log";
-        let matches = Command::new(env!("CARGO_PKG_NAME"))
+        let command = Command::new(env!("CARGO_PKG_NAME"))
             .version(env!("CARGO_PKG_VERSION"))
             .about(format!("Demo quote stream {}", env!("CARGO_PKG_NAME")))
+            .arg(
+                Arg::new("config")
+                    .short('c')
+                    .long("config")
+                    .help("TOML config file: options without `--` and with `_` (server_addr = ...), \
+                           sections [stream], [client], [generator]; precedence: options, QUOTE_* environment, file")
+                    .required(false)
+            )
             .arg(
                 Arg::new("server-addr")
                     .short('s')
                     .long("server-addr")
-                    .help("Destination host address: host:port")
-                    .required(true)
+                    .help("Destination host address: host:port, ip:port or [ipv6]:port")
+                    .required(false)
             )
             .arg(
                 Arg::new("udp-port")
                     .short('u')
                     .long("udp-port")
                     .help("Client udp port: port")
-                    .required(true)
+                    .value_parser(clap::value_parser!(u16))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("tcp-local")
+                    .long("tcp-local")
+                    .help("Local TCP address: server listen address instead of server-addr, \
+                           client source address of the control connection")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("udp-local")
+                    .long("udp-local")
+                    .help("Local UDP address: host:port, [::]:port for dual-stack \
+                           (default: server - listen host and udp-port, client - any address and udp-port)")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("udp-remote")
+                    .long("udp-remote")
+                    .help("Client UDP address sent to the server in STREAM, for NAT: host:port \
+                           (default: local address of the control connection and udp port)")
+                    .required(false)
             )
             .arg(
                 Arg::new("tickers-file")
                     .short('t')
                     .long("tickers-file")
                     .help("File path tickers file")
-                    .required(true)
+                    .required(false)
             )
             .arg(
                 Arg::new("log-level")
@@ -57,40 +161,464 @@ impl CliArgs{
                     .default_value(file_log)
                     .required(false)
             )
-            .get_matches();
-        let server_addr = matches.get_one::<String>("server-addr");
-        let udp_port = matches.get_one::<String>("udp-port");
-        let tickers_file = matches.get_one::<String>("tickers-file");
-        let log_level = matches.get_one::<String>("log-level");
-        let log_file = matches.get_one::<String>("log-file");
-        if let Some(server_addr) = server_addr &&
-            let Some(udp_port) = udp_port && let Some(tickers_file) = tickers_file &&
-            let Some(log_level) = log_level && let Some(log_file) = log_file{
-            if !Path::new(tickers_file).exists() {
-                eprintln!("File {} not exists", tickers_file);
+            .arg(
+                Arg::new("book-depth")
+                    .short('d')
+                    .long("book-depth")
+                    .help("Order book depth: levels generated by server, levels printed by client")
+                    .default_value("5")
+                    .value_parser(clap::value_parser!(usize))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("positions-file")
+                    .short('p')
+                    .long("positions-file")
+                    .help("Client positions file: ticker,quantity,cost_basis")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("pnl-snapshot-file")
+                    .long("pnl-snapshot-file")
+                    .help("Client file for periodic portfolio P&L snapshots")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("pnl-snapshot-period")
+                    .long("pnl-snapshot-period")
+                    .help("Client portfolio snapshot period, seconds")
+                    .default_value("60")
+                    .value_parser(clap::value_parser!(u64))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("alert-rules")
+                    .long("alert-rules")
+                    .help("Client alert rules file")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("alert-output")
+                    .long("alert-output")
+                    .help("Client alert output: stderr | file:<path> | command:<command>")
+                    .default_value("stderr")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("multicast-group")
+                    .long("multicast-group")
+                    .help("Server multicast group: group:port[=TICKER,TICKER], repeatable")
+                    .action(ArgAction::Append)
+                    .required(false)
+            )
+            .arg(
+                Arg::new("multicast-ttl")
+                    .long("multicast-ttl")
+                    .help("Server multicast TTL (IPv6 hop limit)")
+                    .default_value("1")
+                    .value_parser(clap::value_parser!(u32))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("multicast-loopback")
+                    .long("multicast-loopback")
+                    .help("Server multicast loopback to local host: true | false")
+                    .default_value("true")
+                    .value_parser(clap::value_parser!(bool))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("multicast-interface")
+                    .long("multicast-interface")
+                    .help("Multicast interface: IPv4 address or IPv6 interface index")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("transport")
+                    .long("transport")
+                    .help("Client stream transport: udp | tcp | auto (udp, tcp if no udp data)")
+                    .default_value("auto")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("failover-addr")
+                    .long("failover-addr")
+                    .help("Client backup server: host:port, repeatable; servers are tried in order after server-addr")
+                    .action(ArgAction::Append)
+                    .required(false)
+            )
+            .arg(
+                Arg::new("reconnect-attempts")
+                    .long("reconnect-attempts")
+                    .help("Client reconnect attempts after a failed or lost connection, 0 - exit at once \
+                           (default: unlimited)")
+                    .value_parser(clap::value_parser!(u32))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("reconnect-delay")
+                    .long("reconnect-delay")
+                    .help("Client first reconnect delay, doubled on each attempt with random jitter, ms")
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("500")
+            )
+            .arg(
+                Arg::new("reconnect-max-delay")
+                    .long("reconnect-max-delay")
+                    .help("Client maximum reconnect delay, ms")
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("30000")
+            )
+            .arg(
+                Arg::new("fallback-timeout")
+                    .long("fallback-timeout")
+                    .help("Client timeout without udp data before tcp fallback, seconds")
+                    .default_value("10")
+                    .value_parser(clap::value_parser!(u64))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("ws-addr")
+                    .long("ws-addr")
+                    .help("Server WebSocket gateway address for browsers: host:port")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("http-addr")
+                    .long("http-addr")
+                    .help("Server HTTP API address: host:port")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("credentials")
+                    .long("credentials")
+                    .help("Server credentials file: lines `token <name> <secret>` or `user <name> <password>`")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("entitlements")
+                    .long("entitlements")
+                    .help("Server entitlements file: lines `token|user <name> <patterns>` or `default <patterns>`")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("auth-token")
+                    .long("auth-token")
+                    .help("Client token for AUTH")
+                    .conflicts_with("auth-user")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("auth-user")
+                    .long("auth-user")
+                    .help("Client user name for AUTH")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("auth-password")
+                    .long("auth-password")
+                    .help("Client password for AUTH")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("allow-any-udp-target")
+                    .long("allow-any-udp-target")
+                    .help("Server streams UDP to any address, not only to the client TCP address")
+                    .action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("tls-cert")
+                    .long("tls-cert")
+                    .help("TLS certificate PEM: server certificate, or client certificate for server client auth")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("tls-key")
+                    .long("tls-key")
+                    .help("TLS private key PEM for --tls-cert")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("tls-ca")
+                    .long("tls-ca")
+                    .help("TLS CA PEM: client verifies server, server requires client certificates")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("tls-server-name")
+                    .long("tls-server-name")
+                    .help("Client TLS server name, default server address host")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("watch-tickers")
+                    .long("watch-tickers")
+                    .help("Server reloads the tickers file when it changes (also on SIGHUP and admin RELOAD)")
+                    .action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("max-sessions")
+                    .long("max-sessions")
+                    .help("Server maximum concurrent control connections")
+                    .value_parser(clap::value_parser!(usize))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("max-sessions-per-ip")
+                    .long("max-sessions-per-ip")
+                    .help("Server maximum concurrent control connections from one IP address")
+                    .value_parser(clap::value_parser!(usize))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("max-tickers")
+                    .long("max-tickers")
+                    .help("Server maximum tickers per STREAM command")
+                    .value_parser(clap::value_parser!(usize))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("max-commands-per-second")
+                    .long("max-commands-per-second")
+                    .help("Server maximum commands per second per control connection")
+                    .value_parser(clap::value_parser!(u32))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("max-datagram-rate")
+                    .long("max-datagram-rate")
+                    .help("Server maximum outbound datagrams per second, all UDP and multicast streams")
+                    .value_parser(clap::value_parser!(u32))
+                    .required(false)
+            )
+            .arg(
+                Arg::new("admin-addr")
+                    .long("admin-addr")
+                    .help("Server admin commands address (LIST, STATUS, SESSIONS, KICK): host:port")
+                    .required(false)
+            )
+            .arg(
+                Arg::new("admin-allow-remote")
+                    .long("admin-allow-remote")
+                    .help("Server accepts a non-loopback admin address: admin commands have no authentication")
+                    .action(ArgAction::SetTrue)
+            )
+            .arg(
+                Arg::new("metrics-addr")
+                    .long("metrics-addr")
+                    .help("Prometheus metrics address (GET /metrics): host:port")
+                    .required(false)
+            );
+        let matches = command.clone().get_matches();
+        //значения аргументов дополняются переменными окружения QUOTE_* и файлом настроек
+        let config = match Config::load(&command, &matches) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Error config: {}", e);
                 return None;
             }
-            let level = {
-                match log_level.as_ref() {
-                    "DEBUG" => LevelFilter::Debug,
-                    "ERROR" => LevelFilter::Error,
-                    "WARN" => LevelFilter::Warn,
-                    _ => LevelFilter::Info,
-                }
+        };
+        match Self::from_config(&config) {
+            Ok(args) => Some(args),
+            Err(e) => {
+                eprintln!("Error config: {}", e);
+                None
+            }
+        }
+    }
+
+    fn from_config(config: &Config) -> Result<Self, String> {
+        let server_addr = config.required::<String>("server-addr")?;
+        let tcp_local = config.value::<String>("tcp-local")?;
+        let udp_local = config.value::<String>("udp-local")?;
+        let udp_remote = config.value::<String>("udp-remote")?;
+        //порт UDP не нужен, если локальный адрес UDP задан явно
+        let udp_port = match udp_local {
+            Some(_) => config.value::<u16>("udp-port")?,
+            None => Some(config.required::<u16>("udp-port")?),
+        };
+        let tickers_file = config.required::<String>("tickers-file")?;
+        let log_level = config.required::<String>("log-level")?;
+        let log_file = config.required::<String>("log-file")?;
+        let book_depth = config.value::<usize>("book-depth")?.unwrap_or(5);
+        let positions_file = config.value::<String>("positions-file")?;
+        let pnl_snapshot_file = config.value::<String>("pnl-snapshot-file")?;
+        let pnl_snapshot_period = config.value::<u64>("pnl-snapshot-period")?.unwrap_or(60);
+        let alert_rules_file = config.value::<String>("alert-rules")?;
+        let alert_output = config.value::<String>("alert-output")?.unwrap_or_default();
+        let ws_addr = config.value::<String>("ws-addr")?;
+        let http_addr = config.value::<String>("http-addr")?;
+        let metrics_addr = config.value::<String>("metrics-addr")?;
+        let admin_addr = config.value::<String>("admin-addr")?;
+        let admin_allow_remote = config.value::<bool>("admin-allow-remote")?.unwrap_or_default();
+        let credentials_file = config.value::<String>("credentials")?;
+        let entitlements_file = config.value::<String>("entitlements")?;
+        let allow_any_udp_target = config.value::<bool>("allow-any-udp-target")?.unwrap_or_default();
+        let tls_cert = config.value::<String>("tls-cert")?;
+        let tls_key = config.value::<String>("tls-key")?;
+        let tls_ca = config.value::<String>("tls-ca")?;
+        let tls_server_name = config.value::<String>("tls-server-name")?;
+        let max_sessions = config.value::<usize>("max-sessions")?;
+        let max_sessions_per_ip = config.value::<usize>("max-sessions-per-ip")?;
+        let max_tickers = config.value::<usize>("max-tickers")?;
+        let max_commands_per_second = config.value::<u32>("max-commands-per-second")?;
+        let max_datagram_rate = config.value::<u32>("max-datagram-rate")?;
+        let watch_tickers = config.value::<bool>("watch-tickers")?.unwrap_or_default();
+        let tuning = config.tuning()?;
+        //пары аргументов проверяются после объединения источников настроек
+        if tls_cert.is_some() != tls_key.is_some() {
+            return Err("tls-cert and tls-key must be set together".to_string());
+        }
+        let auth = match (config.value::<String>("auth-token")?, config.value::<String>("auth-user")?,
+                          config.value::<String>("auth-password")?) {
+            (Some(token), _, _) => Some(token),
+            (None, Some(user), Some(password)) => Some(format!("{} {}", user, password)),
+            (None, None, None) => None,
+            _ => return Err("auth-user and auth-password must be set together".to_string()),
+        };
+        let transport = config.value::<String>("transport")?.unwrap_or_default();
+        let fallback_timeout = config.value::<u64>("fallback-timeout")?.unwrap_or(10);
+        let failover_addrs = config.values("failover-addr")?;
+        let reconnect_attempts = config.value::<u32>("reconnect-attempts")?;
+        let reconnect_delay = config.value::<u64>("reconnect-delay")?.unwrap_or(500);
+        let reconnect_max_delay = config.value::<u64>("reconnect-max-delay")?.unwrap_or(30000);
+        if reconnect_delay == 0 || reconnect_max_delay < reconnect_delay {
+            return Err("reconnect-delay must be greater than 0 and not above reconnect-max-delay".to_string());
+        }
+        let mut multicast_groups = Vec::new();
+        for spec in config.values("multicast-group")? {
+            let Some(group) = MulticastGroup::parse(&spec) else {
+                return Err(format!("bad multicast group {}", spec));
             };
-            let host_port: Vec<&str> = server_addr.split(":").collect();
-            if host_port.len() < 2{
-                eprintln!("Error address server");
+            multicast_groups.push(group);
+        }
+        let multicast_options = MulticastOptions {
+            ttl: config.value::<u32>("multicast-ttl")?.unwrap_or(1),
+            loopback: config.value::<bool>("multicast-loopback")?.unwrap_or(true),
+            interface: config.value::<String>("multicast-interface")?,
+        };
+        for file in [&positions_file, &alert_rules_file, &credentials_file, &entitlements_file,
+            &tls_cert, &tls_key, &tls_ca].into_iter().flatten().chain([&tickers_file]) {
+            if !Path::new(file).exists() {
+                return Err(format!("file {} not exists", file));
             }
-            let udp_addr = host_port[0].to_owned()  + ":" + udp_port;
-            return Some(Self{
-                server_addr: server_addr.to_owned(),
-                udp_addr: udp_addr.to_owned(),
-                tickers_file: tickers_file.to_string(),
-                log_level: level,
-                file_log: log_file.to_owned()
-            })
         }
-        None
+        let level = {
+            match log_level.as_ref() {
+                "DEBUG" => LevelFilter::Debug,
+                "ERROR" => LevelFilter::Error,
+                "WARN" => LevelFilter::Warn,
+                _ => LevelFilter::Info,
+            }
+        };
+        for addr in [&tcp_local, &udp_local, &udp_remote].into_iter().flatten()
+            .chain([&server_addr]).chain(&failover_addrs) {
+            if netaddr::host(addr).is_none() {
+                return Err(format!("bad address {}: use host:port, ip:port or [ipv6]:port", addr));
+            }
+        }
+        Ok(Self{
+            server_addr,
+            tcp_local,
+            udp_local,
+            #[cfg(feature = "client")]
+            udp_remote,
+            udp_port,
+            #[cfg(feature = "client")]
+            failover_addrs,
+            #[cfg(feature = "client")]
+            reconnect_attempts,
+            #[cfg(feature = "client")]
+            reconnect_delay,
+            #[cfg(feature = "client")]
+            reconnect_max_delay,
+            tickers_file,
+            log_level: level,
+            file_log: log_file,
+            book_depth,
+            #[cfg(feature = "client")]
+            positions_file,
+            #[cfg(feature = "client")]
+            pnl_snapshot_file,
+            #[cfg(feature = "client")]
+            pnl_snapshot_period,
+            #[cfg(feature = "client")]
+            alert_rules_file,
+            #[cfg(feature = "client")]
+            alert_output,
+            #[cfg(feature = "server")]
+            multicast_groups,
+            multicast_options,
+            #[cfg(feature = "client")]
+            transport,
+            #[cfg(feature = "client")]
+            fallback_timeout,
+            #[cfg(feature = "server")]
+            ws_addr,
+            #[cfg(feature = "server")]
+            http_addr,
+            metrics_addr,
+            #[cfg(feature = "server")]
+            admin_addr,
+            #[cfg(feature = "server")]
+            admin_allow_remote,
+            #[cfg(feature = "server")]
+            credentials_file,
+            #[cfg(feature = "server")]
+            entitlements_file,
+            #[cfg(feature = "client")]
+            auth,
+            #[cfg(feature = "server")]
+            allow_any_udp_target,
+            tls_cert,
+            tls_key,
+            tls_ca,
+            #[cfg(feature = "client")]
+            tls_server_name,
+            #[cfg(feature = "server")]
+            max_sessions,
+            #[cfg(feature = "server")]
+            max_sessions_per_ip,
+            #[cfg(feature = "server")]
+            max_tickers,
+            #[cfg(feature = "server")]
+            max_commands_per_second,
+            #[cfg(feature = "server")]
+            max_datagram_rate,
+            #[cfg(feature = "server")]
+            watch_tickers,
+            tuning
+        })
+    }
+}
+
+impl CliArgs {
+    //адрес прослушивания TCP сервера
+    #[cfg(feature = "server")]
+    pub fn server_tcp_addr(&self) -> &str {
+        self.tcp_local.as_deref().unwrap_or(&self.server_addr)
+    }
+
+    //локальный адрес UDP сервера: по умолчанию хост адреса прослушивания и порт udp-port
+    #[cfg(feature = "server")]
+    pub fn server_udp_addr(&self) -> String {
+        match (&self.udp_local, netaddr::host(self.server_tcp_addr())) {
+            (Some(udp_local), _) => udp_local.clone(),
+            (None, Some(host)) => netaddr::with_port(host, self.udp_port.unwrap_or_default()),
+            (None, None) => self.server_tcp_addr().to_string(),
+        }
+    }
+
+    //локальный адрес UDP клиента: по умолчанию любой интерфейс семейства адреса сервера и порт udp-port
+    #[cfg(feature = "client")]
+    pub fn client_udp_addr(&self) -> String {
+        if let Some(udp_local) = &self.udp_local {
+            return udp_local.clone();
+        }
+        let port = self.udp_port.unwrap_or_default();
+        match netaddr::resolve(&self.server_addr) {
+            Ok(addrs) => netaddr::unspecified(&addrs[0], port).to_string(),
+            Err(_) => netaddr::with_port("0.0.0.0", port),
+        }
     }
 }
@@ -0,0 +1,218 @@
+
+use crate::error::clienterror::QuoteClientError;
+use quote_lib::quote::marketmessage::MarketMessage;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+//позиция портфеля: количество и средняя цена покупки одной акции
+#[derive(Debug, Clone, PartialEq)]
+struct PortfolioPosition {
+    ticker: String,
+    quantity: i64,
+    cost_basis: f64,
+    mark_price: Option<f64>,
+}
+
+impl PortfolioPosition {
+    fn unrealized_pnl(&self) -> f64 {
+        self.mark_price
+            .map(|price| (price - self.cost_basis) * self.quantity as f64)
+            .unwrap_or(0.0)
+    }
+
+    fn to_json(&self, total_unrealized: f64) -> String {
+        let price = self
+            .mark_price
+            .map(|price| price.to_string())
+            .unwrap_or("null".to_string());
+        format!(
+            "{{\"type\": \"pnl\", \"ticker\": \"{}\", \"quantity\": {}, \"cost_basis\": {}, \
+             \"price\": {}, \"unrealized\": {:.2}, \"total_unrealized\": {:.2}}}",
+            self.ticker,
+            self.quantity,
+            self.cost_basis,
+            price,
+            self.unrealized_pnl(),
+            total_unrealized
+        )
+    }
+}
+
+//периодическая запись снимков портфеля в файл
+struct PortfolioSnapshot {
+    file: String,
+    period: Duration,
+    last: Instant,
+}
+
+#[derive(Default)]
+pub(crate) struct Portfolio {
+    positions: Vec<PortfolioPosition>,
+    snapshot: Option<PortfolioSnapshot>,
+}
+
+impl Portfolio {
+    pub(crate) fn from_reader<R: Read>(r: &mut R) -> Result<Self, QuoteClientError> {
+        //файл позиций: строки `<ticker>,<quantity>,<cost_basis>`, `#` - комментарий
+        let mut data = String::new();
+        r.read_to_string(&mut data)?;
+        let mut portfolio = Portfolio::default();
+        for (number, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split(',').map(|part| part.trim()).collect();
+            let position = match parts.as_slice() {
+                [ticker, quantity, cost_basis] if !ticker.is_empty() => {
+                    match (quantity.parse::<i64>(), cost_basis.parse::<f64>()) {
+                        (Ok(quantity), Ok(cost_basis)) => Some(PortfolioPosition {
+                            ticker: ticker.to_string(),
+                            quantity,
+                            cost_basis,
+                            mark_price: None,
+                        }),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match position {
+                Some(position) => portfolio.positions.push(position),
+                None => {
+                    return Err(QuoteClientError::BadPositionsFile(format!(
+                        "line {}: {}",
+                        number + 1,
+                        line
+                    )));
+                }
+            }
+        }
+        Ok(portfolio)
+    }
+
+    pub(crate) fn set_snapshot_file(&mut self, file: &str, period: Duration) {
+        self.snapshot = Some(PortfolioSnapshot {
+            file: file.to_string(),
+            period,
+            last: Instant::now(),
+        });
+    }
+
+    fn total_unrealized(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(|position| position.unrealized_pnl())
+            .sum()
+    }
+
+    pub(crate) fn mark(&mut self, message: &MarketMessage) -> Vec<String> {
+        //переоценка позиций по цене сделки, середине котировки или последней цене сессии
+        let price = match message {
+            MarketMessage::Trade(_) | MarketMessage::Quote(_) | MarketMessage::Stats(_) => {
+                message.mark_price()
+            }
+            _ => None,
+        };
+        let Some(price) = price else {
+            return Vec::new();
+        };
+        let mut marked = false;
+        for position in self
+            .positions
+            .iter_mut()
+            .filter(|position| position.ticker == message.ticker())
+        {
+            position.mark_price = Some(price);
+            marked = true;
+        }
+        if !marked {
+            return Vec::new();
+        }
+        let total = self.total_unrealized();
+        self.positions
+            .iter()
+            .filter(|position| position.ticker == message.ticker())
+            .map(|position| position.to_json(total))
+            .collect()
+    }
+
+    fn snapshot_json(&self, timestamp: &str) -> String {
+        let positions: Vec<String> = self
+            .positions
+            .iter()
+            .map(|position| {
+                format!(
+                    "{{\"ticker\": \"{}\", \"quantity\": {}, \"unrealized\": {:.2}}}",
+                    position.ticker,
+                    position.quantity,
+                    position.unrealized_pnl()
+                )
+            })
+            .collect();
+        format!(
+            "{{\"type\": \"portfolio\", \"timestamp\": \"{}\", \"positions\": [{}], \
+             \"total_unrealized\": {:.2}}}",
+            timestamp,
+            positions.join(", "),
+            self.total_unrealized()
+        )
+    }
+
+    pub(crate) fn write_snapshot_if_due(&mut self) -> Result<(), QuoteClientError> {
+        //снимок портфеля дописывается в файл строкой json
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(());
+        };
+        if snapshot.last.elapsed() < snapshot.period {
+            return Ok(());
+        }
+        let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+        let line = self.snapshot_json(&timestamp);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&snapshot.file)?;
+        writeln!(file, "{}", line)?;
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.last = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_from_reader() {
+        let mut reader = Cursor::new("# ticker,quantity,cost\nAAPL,100,200.5\n\nMSFT, -50, 210\n");
+        let portfolio = Portfolio::from_reader(&mut reader).unwrap();
+        assert_eq!(portfolio.positions.len(), 2);
+        assert_eq!(portfolio.positions[1].quantity, -50);
+        let mut reader = Cursor::new("AAPL,100\n");
+        assert_eq!(
+            Portfolio::from_reader(&mut reader).err().unwrap(),
+            QuoteClientError::BadPositionsFile("line 1: AAPL,100".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mark() {
+        let mut reader = Cursor::new("AAPL,100,200\nMSFT,-10,100\n");
+        let mut portfolio = Portfolio::from_reader(&mut reader).unwrap();
+        let quote = MarketMessage::from_string("Q|AAPL|1|209|100|211|100|1000").unwrap();
+        let lines = portfolio.mark(&quote);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("\"unrealized\": 1000.00"));
+        let trade = MarketMessage::from_string("T|MSFT|1|110|10|R|1000").unwrap();
+        let lines = portfolio.mark(&trade);
+        assert!(lines[0].contains("\"unrealized\": -100.00, \"total_unrealized\": 900.00"));
+        let other = MarketMessage::from_string("T|GOOG|1|110|10|R|1000").unwrap();
+        assert!(portfolio.mark(&other).is_empty());
+        assert!(portfolio.snapshot_json("t").contains("\"total_unrealized\": 900.00"));
+    }
+}
@@ -5,3 +5,42 @@ pub(crate) mod volume_generator;
 #[cfg(feature = "server")]
 #[path="../src/quote/quote_stream.rs"]
 pub(crate) mod quote_stream;
+#[cfg(feature = "server")]
+#[path="../src/quote/market_state.rs"]
+pub(crate) mod market_state;
+#[cfg(feature = "server")]
+#[path="../src/quote/paper_trading.rs"]
+pub(crate) mod paper_trading;
+#[cfg(feature = "server")]
+#[path="../src/quote/server_alerts.rs"]
+pub(crate) mod server_alerts;
+#[cfg(feature = "server")]
+#[path="../src/quote/ws_gateway.rs"]
+pub(crate) mod ws_gateway;
+#[cfg(feature = "server")]
+#[path="../src/quote/sessions.rs"]
+pub(crate) mod sessions;
+#[cfg(feature = "server")]
+#[path="../src/quote/http_api.rs"]
+pub(crate) mod http_api;
+#[cfg(feature = "server")]
+#[path="../src/quote/server_metrics.rs"]
+pub(crate) mod server_metrics;
+#[cfg(feature = "server")]
+#[path="../src/quote/admin.rs"]
+pub(crate) mod admin;
+#[cfg(feature = "server")]
+#[path="../src/quote/auth.rs"]
+pub(crate) mod auth;
+#[cfg(feature = "server")]
+#[path="../src/quote/udp_target.rs"]
+pub(crate) mod udp_target;
+#[cfg(feature = "server")]
+#[path="../src/quote/entitlements.rs"]
+pub(crate) mod entitlements;
+#[cfg(feature = "server")]
+#[path="../src/quote/limits.rs"]
+pub(crate) mod limits;
+#[cfg(feature = "server")]
+#[path="../src/quote/universe.rs"]
+pub(crate) mod universe;
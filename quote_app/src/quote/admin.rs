@@ -0,0 +1,203 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use crate::quote::market_state::MarketState;
+use crate::quote::sessions::{SessionInfo, SessionRegistry};
+use crate::quote::universe::{TickerReloader, TickerUniverse};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use crate::netaddr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+//пауза после ошибки приема соединения администратора, мс
+const ADMIN_ACCEPT_RETRY_MILLISECOND: u64 = 100;
+
+//административные команды: список тикеров, состояние сервера, сессии, отключение сессии
+//и перезагрузка файла тикеров
+pub(crate) struct AdminServer {
+    tickers: TickerUniverse,
+    reloader: TickerReloader,
+    market: MarketState,
+    sessions: SessionRegistry,
+    started: Instant,
+}
+
+fn session_line(session: &SessionInfo, now: u64) -> String {
+    //SESSION|id|peer|state|transport|target|tickers|last_ping_sec|bytes_sent
+    let last_ping = match session.counters.last_ping.load(Relaxed) {
+        0 => "-".to_string(),
+        last_ping => now.saturating_sub(last_ping).to_string(),
+    };
+    format!(
+        "SESSION|{}|{}|{}|{}|{}|{}|{}|{}\n",
+        session.id,
+        session.peer,
+        session.state(),
+        session.transport.as_deref().unwrap_or("-"),
+        if session.target.is_empty() { "-" } else { &session.target },
+        session.tickers.join(","),
+        last_ping,
+        session.counters.bytes_sent.load(Relaxed)
+    )
+}
+
+impl AdminServer {
+    pub(crate) fn new(
+        tickers: TickerUniverse,
+        reloader: TickerReloader,
+        market: MarketState,
+        sessions: SessionRegistry,
+    ) -> Self {
+        Self {
+            tickers,
+            reloader,
+            market,
+            sessions,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn handle_command(&self, input: &str) -> String {
+        let mut parts = input.split_whitespace();
+        match parts.next() {
+            Some("LIST") => {
+                let tickers = self.tickers.list();
+                format!("{}\nOK List {}\n", tickers.join("\n"), tickers.len())
+            }
+            Some("STATUS") => format!(
+                "STATUS|{}|{:.1}|{}\nOK Status\n",
+                self.started.elapsed().as_secs(),
+                self.market.rate(),
+                self.sessions.list().len()
+            ),
+            Some("SESSIONS") => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|now| now.as_secs())
+                    .unwrap_or_default();
+                let sessions = self.sessions.list();
+                let lines: String = sessions.iter().map(|session| session_line(session, now)).collect();
+                format!("{}OK Sessions {}\n", lines, sessions.len())
+            }
+            Some("KICK") => match parts.next().and_then(|id| id.parse::<u64>().ok()) {
+                Some(id) if self.sessions.kick(id) => {
+                    log::info!("admin: kick session {}", id);
+                    format!("OK Kick {}\n", id)
+                }
+                Some(id) => format!("Error kick: session {} not found\n", id),
+                None => "Error command kick\n".to_string(),
+            },
+            //ADDED|<tickers> и REMOVED|<tickers> - только непустые списки
+            Some("RELOAD") => match self.reloader.reload() {
+                Ok((added, removed)) => {
+                    let mut response = String::new();
+                    if !added.is_empty() {
+                        response.push_str(&format!("ADDED|{}\n", added.join(",")));
+                    }
+                    if !removed.is_empty() {
+                        response.push_str(&format!("REMOVED|{}\n", removed.join(",")));
+                    }
+                    format!("{}OK Reload {}\n", response, self.tickers.list().len())
+                }
+                Err(e) => format!("Error reload: {}\n", e),
+            },
+            _ => "Error command\n".to_string(),
+        }
+    }
+
+    fn handle_client(&self, stream: TcpStream) -> Result<(), QuoteStreamServerError> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let input = line.trim();
+            if input.is_empty() {
+                continue;
+            }
+            writer.write_all(self.handle_command(input).as_bytes())?;
+            writer.flush()?;
+        }
+    }
+
+    //команды доступны любому подключившемуся без аутентификации: адрес не localhost
+    //допускается только при явном allow_remote
+    pub(crate) fn check_bind(admin_bind: &str, allow_remote: bool) -> Result<(), QuoteStreamServerError> {
+        if allow_remote {
+            return Ok(());
+        }
+        let addrs = netaddr::resolve(admin_bind)?;
+        if addrs.iter().all(|addr| addr.ip().is_loopback()) {
+            Ok(())
+        } else {
+            Err(QuoteStreamServerError::BadNetworkBindSocket(format!(
+                "admin address {} is not loopback, use --admin-allow-remote to listen on it",
+                admin_bind
+            )))
+        }
+    }
+
+    pub(crate) fn run(self, admin_bind: &str) -> Result<(), QuoteStreamServerError> {
+        let listener = TcpListener::bind(admin_bind)?;
+        log::info!("admin listening on: {}", admin_bind);
+        let admin = Arc::new(self);
+        for stream in listener.incoming() {
+            //ошибка приема одного соединения не останавливает порт администратора
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::error!("admin accept error: {}", e);
+                    thread::sleep(Duration::from_millis(ADMIN_ACCEPT_RETRY_MILLISECOND));
+                    continue;
+                }
+            };
+            let admin = admin.clone();
+            thread::spawn(move || {
+                if let Err(e) = admin.handle_client(stream) {
+                    log::error!("admin client error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handle_command() {
+        let sessions = SessionRegistry::default();
+        let id = sessions.register("127.0.0.1:5000");
+        sessions.start_stream(id, "udp", "127.0.0.1:55500", vec!["AAPL".to_string()], None);
+        sessions.counters(id).bytes_sent.fetch_add(42, Relaxed);
+        let tickers = TickerUniverse::new(vec!["AAPL".to_string(), "MSFT".to_string()]);
+        let reloader = TickerReloader::new(None, tickers.clone(), sessions.clone());
+        let admin = AdminServer::new(tickers, reloader, MarketState::default(), sessions);
+        assert_eq!(admin.handle_command("LIST"), "AAPL\nMSFT\nOK List 2\n");
+        assert_eq!(admin.handle_command("STATUS"), "STATUS|0|0.0|1\nOK Status\n");
+        assert_eq!(
+            admin.handle_command("SESSIONS"),
+            "SESSION|1|127.0.0.1:5000|streaming|udp|127.0.0.1:55500|AAPL|-|42\nOK Sessions 1\n"
+        );
+        assert_eq!(admin.handle_command("KICK 1"), "OK Kick 1\n");
+        assert_eq!(admin.handle_command("KICK 7"), "Error kick: session 7 not found\n");
+        assert_eq!(admin.handle_command("KICK"), "Error command kick\n");
+        assert_eq!(admin.handle_command("RELOAD"), "Error reload: Bad tickers file: no tickers file\n");
+    }
+
+    #[test]
+    fn test_check_bind() {
+        assert!(AdminServer::check_bind("127.0.0.1:8213", false).is_ok());
+        assert!(AdminServer::check_bind("[::1]:8213", false).is_ok());
+        assert!(AdminServer::check_bind("0.0.0.0:8213", false).is_err());
+        assert!(AdminServer::check_bind("[::]:8213", false).is_err());
+        assert!(AdminServer::check_bind("0.0.0.0:8213", true).is_ok());
+    }
+}
@@ -0,0 +1,154 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+//неудачных попыток AUTH с одного IP адреса за окно, после которых AUTH отклоняется до конца окна
+const AUTH_MAX_FAILURES: u32 = 5;
+const AUTH_FAILURE_WINDOW_SECOND: u64 = 60;
+
+fn sha256(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+fn parse_secret(secret: &str) -> Option<[u8; 32]> {
+    //секрет открытым текстом или хеш `sha256:<hex>`
+    match secret.strip_prefix("sha256:") {
+        Some(hash) => hex::decode(hash).ok()?.try_into().ok(),
+        None => Some(sha256(secret)),
+    }
+}
+
+//учетные данные сервера: токены и пользователи с паролями, хранятся хеши sha256
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Credentials {
+    tokens: Vec<(String, [u8; 32])>,
+    users: HashMap<String, [u8; 32]>,
+}
+
+impl Credentials {
+    pub(crate) fn from_reader<R: BufRead>(reader: &mut R) -> Result<Self, QuoteStreamServerError> {
+        //строки `token <name> <secret>` и `user <name> <password>`, `#` - комментарий
+        let mut credentials = Credentials::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let entry = match parts.as_slice() {
+                ["token", name, secret] | ["user", name, secret] => parse_secret(secret).map(|hash| (*name, hash)),
+                _ => None,
+            };
+            let Some((name, hash)) = entry else {
+                return Err(QuoteStreamServerError::CredentialsError(format!(
+                    "line {}: {}",
+                    number + 1,
+                    parts.first().unwrap_or(&"")
+                )));
+            };
+            if parts[0] == "token" {
+                credentials.tokens.push((name.to_string(), hash));
+            } else {
+                credentials.users.insert(name.to_string(), hash);
+            }
+        }
+        Ok(credentials)
+    }
+
+    fn check(&self, args: &[&str]) -> Option<String> {
+        //AUTH <token> | AUTH <user> <password>, результат - имя токена или пользователя
+        match args {
+            [token] => {
+                let hash = sha256(token);
+                self.tokens
+                    .iter()
+                    .find(|(_, token_hash)| *token_hash == hash)
+                    .map(|(name, _)| format!("token {}", name))
+            }
+            [user, password] => self
+                .users
+                .get(*user)
+                .filter(|hash| **hash == sha256(password))
+                .map(|_| format!("user {}", user)),
+            _ => None,
+        }
+    }
+}
+
+//проверка AUTH с ограничением неудачных попыток по IP адресу, общая для всех сессий
+#[derive(Debug)]
+pub(crate) struct Authenticator {
+    credentials: Credentials,
+    failures: Arc<Mutex<HashMap<IpAddr, (u32, Instant)>>>,
+}
+
+impl Authenticator {
+    pub(crate) fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(crate) fn authenticate(&self, ip: IpAddr, args: &[&str]) -> Result<String, String> {
+        let Ok(mut failures) = self.failures.lock() else {
+            return Err("internal error".to_string());
+        };
+        let window = Duration::from_secs(AUTH_FAILURE_WINDOW_SECOND);
+        failures.retain(|_, (_, started)| started.elapsed() < window);
+        if failures.get(&ip).is_some_and(|(count, _)| *count >= AUTH_MAX_FAILURES) {
+            log::warn!("auth from {} rejected: too many attempts", ip);
+            return Err("too many attempts".to_string());
+        }
+        match self.credentials.check(args) {
+            Some(identity) => {
+                failures.remove(&ip);
+                log::info!("auth from {}: {}", ip, identity);
+                Ok(identity)
+            }
+            None => {
+                failures.entry(ip).or_insert((0, Instant::now())).0 += 1;
+                log::warn!("auth failed from {}", ip);
+                Err("invalid credentials".to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_credentials() {
+        let hash = hex::encode(sha256("s3cret"));
+        let mut reader = Cursor::new(format!("# ci\ntoken ci abc\nuser alice sha256:{}\n", hash));
+        let credentials = Credentials::from_reader(&mut reader).unwrap();
+        assert_eq!(credentials.check(&["abc"]), Some("token ci".to_string()));
+        assert_eq!(credentials.check(&["alice", "s3cret"]), Some("user alice".to_string()));
+        assert_eq!(credentials.check(&["alice", "abc"]), None);
+        assert_eq!(credentials.check(&["s3cret"]), None);
+        let mut reader = Cursor::new("token ci\n");
+        assert!(Credentials::from_reader(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut reader = Cursor::new("token ci abc\n");
+        let auth = Authenticator::new(Credentials::from_reader(&mut reader).unwrap());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        for _ in 0..AUTH_MAX_FAILURES {
+            assert_eq!(auth.authenticate(ip, &["bad"]), Err("invalid credentials".to_string()));
+        }
+        //верный токен отклоняется до конца окна
+        assert_eq!(auth.authenticate(ip, &["abc"]), Err("too many attempts".to_string()));
+        assert!(auth.authenticate("10.0.0.2".parse().unwrap(), &["abc"]).is_ok());
+    }
+}
@@ -0,0 +1,136 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use quote_lib::quote::subscription::TickerSubscription;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+fn matches(pattern: &str, ticker: &str) -> bool {
+    //шаблон тикера: `*` - любая последовательность символов, `?` - один символ
+    match pattern.chars().next() {
+        None => ticker.is_empty(),
+        Some('*') => (0..=ticker.len())
+            .filter(|index| ticker.is_char_boundary(*index))
+            .any(|index| matches(&pattern[1..], &ticker[index..])),
+        Some(first) => {
+            let mut chars = ticker.chars();
+            match chars.next() {
+                Some(current) if first == '?' || first == current => {
+                    matches(&pattern[first.len_utf8()..], chars.as_str())
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+//разрешенные тикеры по токенам и пользователям AUTH, default - для соединений без AUTH
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Entitlements {
+    rules: HashMap<String, Vec<String>>,
+    default: Vec<String>,
+}
+
+impl Entitlements {
+    pub(crate) fn from_reader<R: BufRead>(reader: &mut R) -> Result<Self, QuoteStreamServerError> {
+        //строки `token <name> <patterns>`, `user <name> <patterns>`, `default <patterns>`,
+        //шаблоны через запятую, строки одного имени объединяются, `#` - комментарий
+        let mut entitlements = Entitlements::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let (patterns, target) = match parts.as_slice() {
+                [kind @ ("token" | "user"), name, patterns] => {
+                    (patterns, entitlements.rules.entry(format!("{} {}", kind, name)).or_default())
+                }
+                ["default", patterns] => (patterns, &mut entitlements.default),
+                _ => {
+                    return Err(QuoteStreamServerError::EntitlementsError(format!(
+                        "line {}: {}",
+                        number + 1,
+                        parts.first().unwrap_or(&"")
+                    )));
+                }
+            };
+            target.extend(
+                patterns
+                    .split(',')
+                    .map(|pattern| pattern.trim())
+                    .filter(|pattern| !pattern.is_empty())
+                    .map(|pattern| pattern.to_string()),
+            );
+        }
+        Ok(entitlements)
+    }
+
+    //identity - результат AUTH (`token <name>` или `user <name>`), None - без AUTH
+    pub(crate) fn allows(&self, identity: Option<&str>, ticker: &str) -> bool {
+        let patterns = identity
+            .and_then(|identity| self.rules.get(identity))
+            .unwrap_or(&self.default);
+        patterns.iter().any(|pattern| matches(pattern, ticker))
+    }
+
+    pub(crate) fn split(
+        &self,
+        identity: Option<&str>,
+        subscriptions: Vec<TickerSubscription>,
+    ) -> (Vec<TickerSubscription>, Vec<String>) {
+        //разрешенные подписки и тикеры запрещенных
+        let (allowed, denied): (Vec<_>, Vec<_>) = subscriptions
+            .into_iter()
+            .partition(|subscription| self.allows(identity, &subscription.ticker));
+        (allowed, denied.into_iter().map(|subscription| subscription.ticker).collect())
+    }
+}
+
+//без файла прав доступа разрешены все тикеры
+pub(crate) fn split_entitled(
+    entitlements: Option<&Entitlements>,
+    identity: Option<&str>,
+    subscriptions: Vec<TickerSubscription>,
+) -> (Vec<TickerSubscription>, Vec<String>) {
+    match entitlements {
+        Some(entitlements) => entitlements.split(identity, subscriptions),
+        None => (subscriptions, Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*", "AAPL"));
+        assert!(matches("AA*", "AAPL"));
+        assert!(matches("*L", "AAPL"));
+        assert!(matches("A?PL", "AAPL"));
+        assert!(!matches("AA", "AAPL"));
+        assert!(!matches("MS*", "AAPL"));
+    }
+
+    #[test]
+    fn test_entitlements() {
+        let mut reader = Cursor::new("# teams\ntoken ci AAPL,MS*\nuser alice *\ndefault SPY\ntoken ci GOOG\n");
+        let entitlements = Entitlements::from_reader(&mut reader).unwrap();
+        assert!(entitlements.allows(Some("token ci"), "MSFT"));
+        assert!(entitlements.allows(Some("token ci"), "GOOG"));
+        assert!(!entitlements.allows(Some("token ci"), "SPY"));
+        assert!(entitlements.allows(Some("user alice"), "TSLA"));
+        //неизвестное имя и соединение без AUTH - правило default
+        assert!(entitlements.allows(Some("user bob"), "SPY"));
+        assert!(!entitlements.allows(None, "AAPL"));
+        let (allowed, denied) =
+            entitlements.split(Some("token ci"), TickerSubscription::parse_list("AAPL:trades,TSLA,MSFT"));
+        assert_eq!(allowed.len(), 2);
+        assert!(allowed[0].trades && !allowed[0].quotes);
+        assert_eq!(denied, vec!["TSLA".to_string()]);
+        let mut reader = Cursor::new("team ci AAPL\n");
+        assert!(Entitlements::from_reader(&mut reader).is_err());
+    }
+}
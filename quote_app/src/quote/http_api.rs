@@ -0,0 +1,452 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use crate::quote::entitlements::{split_entitled, Entitlements};
+use crate::quote::market_state::{MarketState, TickerState};
+use crate::quote::sessions::SessionRegistry;
+use quote_lib::quote::marketmessage::MarketMessage;
+use quote_lib::quote::subscription::TickerSubscription;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+//таймаут чтения запроса HTTP
+const HTTP_READ_TIMEOUT_SECOND: u64 = 5;
+//период опроса истории событий рынка для потока SSE, мс
+const SSE_POLL_PERIOD_MILLISECOND: u64 = 200;
+//период комментария-пульса потока SSE: обнаружение отключившегося клиента
+const SSE_KEEPALIVE_SECOND: u64 = 15;
+//задержка переподключения, передаваемая браузеру, мс
+const SSE_RETRY_MILLISECOND: u64 = 3000;
+
+//запрос HTTP: метод, путь, параметры строки запроса и заголовки (имена в нижнем регистре)
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct HttpRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) query: HashMap<String, String>,
+    pub(crate) headers: HashMap<String, String>,
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+impl HttpRequest {
+    pub(crate) fn read<R: BufRead>(reader: &mut R) -> Option<Self> {
+        //строка запроса `GET /quotes?tickers=AAPL HTTP/1.1`, заголовки до пустой строки
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).ok()?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+            .collect();
+        let mut headers = HashMap::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).ok()? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+        Some(Self {
+            method,
+            path: percent_decode(path),
+            query,
+            headers,
+        })
+    }
+}
+
+pub(crate) fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+//строка json в кавычках: путь, параметры запроса и тикеры клиента экранируются
+pub(crate) fn json_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or("\"\"".to_string())
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"error\": {}}}", json_string(message))
+}
+
+fn ticker_json(ticker: &str, state: &TickerState) -> String {
+    //последние сообщения тикера, отсутствующие - null
+    let field = |message: Option<MarketMessage>| {
+        message
+            .and_then(|message| message.to_json().ok())
+            .unwrap_or("null".to_string())
+    };
+    format!(
+        "{{\"ticker\": {}, \"trade\": {}, \"quote\": {}, \"stats\": {}, \"book\": {}}}",
+        json_string(ticker),
+        field(state.trade.clone().map(MarketMessage::Trade)),
+        field(state.quote.clone().map(MarketMessage::Quote)),
+        field(state.stats.clone().map(MarketMessage::Stats)),
+        field(state.book.clone().map(MarketMessage::Book))
+    )
+}
+
+//поток Server-Sent Events: номер события SSE - номер сообщения в истории рынка
+pub(crate) struct SseStream {
+    subscriptions: Vec<TickerSubscription>,
+    //номер последнего отправленного события, None - поток начинается со снимка
+    last_id: Option<u64>,
+}
+
+fn sse_event(id: u64, message: &MarketMessage) -> Option<String> {
+    Some(format!("id: {}\ndata: {}\n\n", id, message.to_json().ok()?))
+}
+
+impl SseStream {
+    pub(crate) fn new(subscriptions: Vec<TickerSubscription>, last_event_id: Option<&String>) -> Self {
+        Self {
+            subscriptions,
+            last_id: last_event_id.and_then(|id| id.trim().parse::<u64>().ok()),
+        }
+    }
+
+    fn snapshot(&mut self, market: &MarketState) -> Vec<String> {
+        //последние сообщения подписки с текущим номером события
+        let id = market.sequence();
+        self.last_id = Some(id);
+        self.subscriptions
+            .iter()
+            .filter_map(|subscription| market.get(&subscription.ticker).map(|state| (subscription, state)))
+            .flat_map(|(subscription, state)| {
+                state
+                    .messages()
+                    .into_iter()
+                    .filter(|message| subscription.accepts(message))
+            })
+            .filter_map(|message| sse_event(id, &message))
+            .collect()
+    }
+
+    pub(crate) fn poll(&mut self, market: &MarketState) -> Vec<String> {
+        //номер больше текущего - сервер перезапущен, клиент получает снимок
+        let Some(last_id) = self.last_id.filter(|last_id| *last_id <= market.sequence()) else {
+            return self.snapshot(market);
+        };
+        //номер читается до выборки - подходящие события, добавленные позже, войдут в выборку
+        let sequence = market.sequence();
+        //пропущенные события вытеснены из истории - снимок вместо повтора
+        let Some(events) = market.events_since(last_id, |message| {
+            self.subscriptions
+                .iter()
+                .any(|subscription| subscription.accepts(message))
+        }) else {
+            return self.snapshot(market);
+        };
+        self.last_id = Some(events.last().map(|(id, _)| *id).unwrap_or(sequence).max(sequence));
+        events
+            .iter()
+            .filter_map(|(id, message)| sse_event(*id, message))
+            .collect()
+    }
+}
+
+pub(crate) struct HttpApi {
+    market: MarketState,
+    sessions: SessionRegistry,
+    //запросы HTTP без AUTH - действует правило default файла прав доступа
+    entitlements: Option<Arc<Entitlements>>,
+}
+
+impl HttpApi {
+    pub(crate) fn new(market: MarketState, sessions: SessionRegistry, entitlements: Option<Arc<Entitlements>>) -> Self {
+        Self {
+            market,
+            sessions,
+            entitlements,
+        }
+    }
+
+    fn entitled(&self, ticker: &str) -> bool {
+        self.entitlements
+            .as_ref()
+            .is_none_or(|entitlements| entitlements.allows(None, ticker))
+    }
+
+    pub(crate) fn route(&self, request: &HttpRequest) -> (u16, String) {
+        if request.method != "GET" {
+            return (405, error_json("method not allowed"));
+        }
+        let path: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match path.as_slice() {
+            ["tickers"] => {
+                let tickers: Vec<String> = self
+                    .market
+                    .tickers()
+                    .iter()
+                    .filter(|ticker| self.entitled(ticker))
+                    .map(|ticker| json_string(ticker))
+                    .collect();
+                (200, format!("{{\"tickers\": [{}]}}", tickers.join(", ")))
+            }
+            ["quotes", ticker] if !self.entitled(ticker) => {
+                (403, error_json(&format!("not entitled {}", ticker)))
+            }
+            ["quotes", ticker] => match self.market.get(ticker) {
+                Some(state) => (200, ticker_json(ticker, &state)),
+                None => (404, error_json(&format!("unknown ticker {}", ticker))),
+            },
+            ["quotes"] => {
+                let Some(tickers) = request.query.get("tickers") else {
+                    return (400, error_json("tickers parameter required"));
+                };
+                let mut quotes = Vec::new();
+                let mut unknown = Vec::new();
+                let mut denied = Vec::new();
+                for ticker in tickers.split(',').map(|ticker| ticker.trim()).filter(|ticker| !ticker.is_empty()) {
+                    if !self.entitled(ticker) {
+                        denied.push(json_string(ticker));
+                        continue;
+                    }
+                    match self.market.get(ticker) {
+                        Some(state) => quotes.push(ticker_json(ticker, &state)),
+                        None => unknown.push(json_string(ticker)),
+                    }
+                }
+                //поле denied - только при исключенных по правам доступа тикерах
+                let denied = if denied.is_empty() {
+                    String::new()
+                } else {
+                    format!(", \"denied\": [{}]", denied.join(", "))
+                };
+                (
+                    200,
+                    format!(
+                        "{{\"quotes\": [{}], \"unknown\": [{}]{}}}",
+                        quotes.join(", "),
+                        unknown.join(", "),
+                        denied
+                    ),
+                )
+            }
+            ["sessions"] => {
+                let sessions: Vec<String> = self
+                    .sessions
+                    .list()
+                    .iter()
+                    .map(|session| session.to_json())
+                    .collect();
+                (200, format!("{{\"sessions\": [{}]}}", sessions.join(", ")))
+            }
+            _ => (404, error_json("not found")),
+        }
+    }
+
+    fn stream_events(&self, stream: &mut TcpStream, request: &HttpRequest) -> Result<(), QuoteStreamServerError> {
+        //GET /stream?tickers=AAPL,MSFT, переподключение с заголовком Last-Event-ID
+        let subscriptions = request
+            .query
+            .get("tickers")
+            .map(|tickers| TickerSubscription::parse_list(tickers))
+            .unwrap_or_default();
+        if subscriptions.is_empty() {
+            write_response(stream, 400, &error_json("tickers parameter required"))?;
+            return Ok(());
+        }
+        let (subscriptions, denied) = split_entitled(self.entitlements.as_deref(), None, subscriptions);
+        if subscriptions.is_empty() {
+            write_response(stream, 403, &error_json(&format!("not entitled {}", denied.join(","))))?;
+            return Ok(());
+        }
+        let mut sse = SseStream::new(subscriptions, request.headers.get("last-event-id"));
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+             Connection: keep-alive\r\n\r\nretry: {}\n\n",
+            SSE_RETRY_MILLISECOND
+        )?;
+        //исключенные по правам доступа тикеры - событие denied до событий рынка
+        if !denied.is_empty() {
+            let denied = serde_json::to_string(&denied).unwrap_or("[]".to_string());
+            write!(stream, "event: denied\ndata: {{\"denied\": {}}}\n\n", denied)?;
+        }
+        stream.flush()?;
+        let mut keepalive = Instant::now();
+        loop {
+            let events = sse.poll(&self.market);
+            let data = if !events.is_empty() {
+                events.concat()
+            } else if keepalive.elapsed() >= Duration::from_secs(SSE_KEEPALIVE_SECOND) {
+                ":\n\n".to_string()
+            } else {
+                String::new()
+            };
+            if !data.is_empty() {
+                //ошибка записи - клиент отключился
+                if let Err(e) = stream.write_all(data.as_bytes()).and_then(|_| stream.flush()) {
+                    log::info!("sse client disconnected: {}", e);
+                    return Ok(());
+                }
+                keepalive = Instant::now();
+            }
+            thread::sleep(Duration::from_millis(SSE_POLL_PERIOD_MILLISECOND));
+        }
+    }
+
+    fn handle_client(&self, mut stream: TcpStream) -> Result<(), QuoteStreamServerError> {
+        stream.set_read_timeout(Some(Duration::from_secs(HTTP_READ_TIMEOUT_SECOND)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let Some(request) = HttpRequest::read(&mut reader) else {
+            write_response(&mut stream, 400, &error_json("bad request"))?;
+            return Ok(());
+        };
+        if request.method == "GET" && request.path.trim_end_matches('/') == "/stream" {
+            return self.stream_events(&mut stream, &request);
+        }
+        let (status, body) = self.route(&request);
+        write_response(&mut stream, status, &body)?;
+        Ok(())
+    }
+
+    pub(crate) fn run(self, http_bind: &str) -> Result<(), QuoteStreamServerError> {
+        //HTTP API: снимки котировок, список тикеров и сессий в формате json, поток SSE
+        let listener = TcpListener::bind(http_bind)?;
+        log::info!("http api listening on: {}", http_bind);
+        let api = std::sync::Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let api = api.clone();
+            thread::spawn(move || {
+                if let Err(e) = api.handle_client(stream) {
+                    log::error!("http client error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_request() {
+        let mut reader = Cursor::new("GET /quotes?tickers=AAPL%2CMSFT HTTP/1.1\r\nHost: x\r\nLast-Event-ID: 7\r\n\r\n");
+        let request = HttpRequest::read(&mut reader).unwrap();
+        assert_eq!(request.path, "/quotes");
+        assert_eq!(request.query.get("tickers").unwrap(), "AAPL,MSFT");
+        assert_eq!(request.headers.get("last-event-id").unwrap(), "7");
+    }
+
+    #[test]
+    fn test_route() {
+        let market = MarketState::default();
+        market.update(&MarketMessage::from_string("T|AAPL|1|10|100|R|1000").unwrap());
+        let api = HttpApi::new(market, SessionRegistry::default(), None);
+        let get = |path: &str| {
+            let mut reader = Cursor::new(format!("GET {} HTTP/1.1\r\n\r\n", path));
+            api.route(&HttpRequest::read(&mut reader).unwrap())
+        };
+        assert_eq!(get("/tickers"), (200, "{\"tickers\": [\"AAPL\"]}".to_string()));
+        let (status, body) = get("/quotes/AAPL");
+        assert_eq!(status, 200);
+        assert!(body.contains("\"quote\": null"));
+        assert_eq!(get("/quotes/TSLA").0, 404);
+        let (_, body) = get("/quotes?tickers=AAPL,TSLA");
+        assert!(body.ends_with("\"unknown\": [\"TSLA\"]}"));
+        assert_eq!(get("/quotes").0, 400);
+        assert_eq!(get("/sessions"), (200, "{\"sessions\": []}".to_string()));
+        //кавычка в пути и параметрах экранируется, ответ остается корректным json
+        let (status, body) = get("/quotes/a%22b");
+        assert_eq!(status, 404);
+        assert_eq!(body, "{\"error\": \"unknown ticker a\\\"b\"}");
+        assert!(serde_json::from_str::<serde_json::Value>(&body).is_ok());
+        let (_, body) = get("/quotes?tickers=a%22%2C%22x%22:1");
+        let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(body["unknown"], serde_json::json!(["a\"", "\"x\":1"]));
+        //правило default для запросов без AUTH
+        let mut reader = Cursor::new("default AAPL\n");
+        let entitlements = Entitlements::from_reader(&mut reader).unwrap();
+        let market = MarketState::default();
+        market.update(&MarketMessage::from_string("T|AAPL|1|10|100|R|1000").unwrap());
+        market.update(&MarketMessage::from_string("T|MSFT|1|20|100|R|1000").unwrap());
+        let api = HttpApi::new(market, SessionRegistry::default(), Some(Arc::new(entitlements)));
+        let get = |path: &str| {
+            let mut reader = Cursor::new(format!("GET {} HTTP/1.1\r\n\r\n", path));
+            api.route(&HttpRequest::read(&mut reader).unwrap())
+        };
+        assert_eq!(get("/tickers"), (200, "{\"tickers\": [\"AAPL\"]}".to_string()));
+        assert_eq!(get("/quotes/MSFT").0, 403);
+        let (_, body) = get("/quotes?tickers=AAPL,MSFT");
+        assert!(body.ends_with("\"unknown\": [], \"denied\": [\"MSFT\"]}"));
+    }
+
+    #[test]
+    fn test_sse_resume() {
+        let market = MarketState::default();
+        market.update(&MarketMessage::from_string("T|AAPL|1|10|100|R|1000").unwrap());
+        market.update(&MarketMessage::from_string("T|MSFT|1|20|100|R|1000").unwrap());
+        let subscriptions = TickerSubscription::parse_list("AAPL");
+        //новый поток начинается со снимка
+        let mut sse = SseStream::new(subscriptions.clone(), None);
+        let events = sse.poll(&market);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].starts_with("id: 2\ndata: {\"type\": \"trade\""));
+        assert!(sse.poll(&market).is_empty());
+        market.update(&MarketMessage::from_string("T|AAPL|2|11|100|R|2000").unwrap());
+        market.update(&MarketMessage::from_string("T|AAPL|3|12|100|R|3000").unwrap());
+        //переподключение после события 3 - повтор пропущенного события 4
+        let mut sse = SseStream::new(subscriptions.clone(), Some(&"3".to_string()));
+        let events = sse.poll(&market);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].starts_with("id: 4\n"));
+        //номер события из будущего - снимок
+        let mut sse = SseStream::new(subscriptions, Some(&"100".to_string()));
+        assert!(sse.poll(&market)[0].starts_with("id: 4\n"));
+    }
+}
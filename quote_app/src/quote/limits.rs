@@ -0,0 +1,158 @@
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+//ограничения сервера, None - без ограничения
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ServerLimits {
+    pub(crate) max_sessions: Option<usize>,
+    pub(crate) max_sessions_per_ip: Option<usize>,
+    pub(crate) max_tickers: Option<usize>,
+    pub(crate) max_commands_per_second: Option<u32>,
+    pub(crate) max_datagram_rate: Option<u32>,
+}
+
+//ограничения сессии: передаются в поток каждого соединения
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SessionLimits {
+    pub(crate) max_tickers: Option<usize>,
+    pub(crate) max_commands_per_second: Option<u32>,
+    //общий для всех потоков UDP бюджет датаграмм в секунду
+    pub(crate) datagram_budget: Option<Arc<RateLimiter>>,
+}
+
+impl ServerLimits {
+    pub(crate) fn session_limits(&self) -> SessionLimits {
+        SessionLimits {
+            max_tickers: self.max_tickers,
+            max_commands_per_second: self.max_commands_per_second,
+            datagram_budget: self.max_datagram_rate.map(|rate| Arc::new(RateLimiter::new(rate))),
+        }
+    }
+}
+
+//ведро токенов: rate токенов в секунду, запас не больше rate
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            state: Mutex::new((rate as f64, Instant::now())),
+        }
+    }
+
+    pub(crate) fn try_acquire(&self) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        let (tokens, updated) = &mut *state;
+        *tokens = (*tokens + updated.elapsed().as_secs_f64() * self.rate).min(self.rate);
+        *updated = Instant::now();
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+//число управляющих соединений всего и по IP адресу
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionLimiter {
+    max_sessions: Option<usize>,
+    max_sessions_per_ip: Option<usize>,
+    active: Mutex<HashMap<IpAddr, usize>>,
+}
+
+//соединение учитывается, пока жив guard
+pub(crate) struct ConnectionGuard {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(limits: &ServerLimits) -> Self {
+        Self {
+            max_sessions: limits.max_sessions,
+            max_sessions_per_ip: limits.max_sessions_per_ip,
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn acquire(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionGuard, String> {
+        let Ok(mut active) = self.active.lock() else {
+            return Err("internal error".to_string());
+        };
+        if let Some(max) = self.max_sessions
+            && active.values().sum::<usize>() >= max
+        {
+            return Err(format!("too many sessions (max {})", max));
+        }
+        let count = active.entry(ip).or_default();
+        if let Some(max) = self.max_sessions_per_ip
+            && *count >= max
+        {
+            return Err(format!("too many sessions from {} (max {})", ip, max));
+        }
+        *count += 1;
+        Ok(ConnectionGuard {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.limiter.active.lock()
+            && let Some(count) = active.get_mut(&self.ip)
+        {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_connection_limiter() {
+        let limits = ServerLimits {
+            max_sessions: Some(3),
+            max_sessions_per_ip: Some(2),
+            ..Default::default()
+        };
+        let limiter = Arc::new(ConnectionLimiter::new(&limits));
+        let (first, second): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let a = limiter.acquire(first).unwrap();
+        let _b = limiter.acquire(first).unwrap();
+        assert_eq!(
+            limiter.acquire(first).err(),
+            Some("too many sessions from 10.0.0.1 (max 2)".to_string())
+        );
+        let _c = limiter.acquire(second).unwrap();
+        assert_eq!(limiter.acquire(second).err(), Some("too many sessions (max 3)".to_string()));
+        //закрытое соединение освобождает место
+        drop(a);
+        assert!(limiter.acquire(first).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(5);
+        assert_eq!((0..10).filter(|_| limiter.try_acquire()).count(), 5);
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        assert!(limiter.try_acquire());
+    }
+}
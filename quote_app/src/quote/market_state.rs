@@ -0,0 +1,201 @@
+
+use quote_lib::quote::marketmessage::{MarketMessage, Quote, SessionStats, Trade};
+use quote_lib::quote::orderbook::OrderBook;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+//окно расчета скорости генератора, мс
+const RATE_WINDOW_MILLISECOND: u64 = 1000;
+//количество последних сообщений, хранимых для возобновления потока по номеру события,
+//около 25 секунд работы генератора по 109 тикерам из tickers.txt
+const EVENT_HISTORY_SIZE: usize = 100000;
+
+//последние сообщения генератора по тикеру
+#[derive(Clone, Default)]
+pub(crate) struct TickerState {
+    pub(crate) trade: Option<Trade>,
+    pub(crate) quote: Option<Quote>,
+    pub(crate) stats: Option<SessionStats>,
+    pub(crate) book: Option<OrderBook>,
+}
+
+impl TickerState {
+    fn update(&mut self, message: &MarketMessage) {
+        match message {
+            MarketMessage::Trade(trade) => self.trade = Some(trade.clone()),
+            MarketMessage::Quote(quote) => self.quote = Some(quote.clone()),
+            MarketMessage::Stats(stats) => self.stats = Some(stats.clone()),
+            MarketMessage::Book(book) => self.book = Some(book.clone()),
+            MarketMessage::BookUpdate(_) => {}
+        }
+    }
+
+    pub(crate) fn messages(&self) -> Vec<MarketMessage> {
+        //последние сообщения каждого типа
+        let mut messages = Vec::new();
+        if let Some(trade) = &self.trade {
+            messages.push(MarketMessage::Trade(trade.clone()));
+        }
+        if let Some(quote) = &self.quote {
+            messages.push(MarketMessage::Quote(quote.clone()));
+        }
+        if let Some(stats) = &self.stats {
+            messages.push(MarketMessage::Stats(stats.clone()));
+        }
+        if let Some(book) = &self.book {
+            messages.push(MarketMessage::Book(book.clone()));
+        }
+        messages
+    }
+
+    pub(crate) fn last_price(&self) -> Option<f64> {
+        //цена последней сделки, иначе середина котировки
+        match (&self.trade, &self.quote) {
+            (Some(trade), _) => Some(trade.price),
+            (None, Some(quote)) => Some((quote.bid + quote.ask) / 2.0),
+            _ => None,
+        }
+    }
+}
+
+//сообщения генератора с номерами событий
+#[derive(Default)]
+struct MarketEvents {
+    sequence: u64,
+    history: VecDeque<(u64, MarketMessage)>,
+    //начало текущего окна и номер последнего сообщения перед ним
+    rate_window: Option<(Instant, u64)>,
+    //сообщений в секунду за последнее завершенное окно
+    rate: f64,
+}
+
+//текущее состояние рынка сервера, общее для генератора и всех сессий
+#[derive(Clone, Default)]
+pub(crate) struct MarketState {
+    tickers: Arc<Mutex<HashMap<String, TickerState>>>,
+    events: Arc<Mutex<MarketEvents>>,
+}
+
+impl MarketState {
+    pub(crate) fn update(&self, message: &MarketMessage) {
+        if let Ok(mut tickers) = self.tickers.lock() {
+            tickers
+                .entry(message.ticker().to_string())
+                .or_default()
+                .update(message);
+        }
+        if let Ok(mut events) = self.events.lock() {
+            events.sequence += 1;
+            let sequence = events.sequence;
+            events.history.push_back((sequence, message.clone()));
+            if events.history.len() > EVENT_HISTORY_SIZE {
+                events.history.pop_front();
+            }
+            match events.rate_window {
+                Some((start, first)) if start.elapsed() >= Duration::from_millis(RATE_WINDOW_MILLISECOND) => {
+                    events.rate = (sequence - first) as f64 / start.elapsed().as_secs_f64();
+                    events.rate_window = Some((Instant::now(), sequence));
+                }
+                Some(_) => {}
+                None => events.rate_window = Some((Instant::now(), sequence)),
+            }
+        }
+    }
+
+    pub(crate) fn remove(&self, tickers: &[String]) {
+        //тикеры, удаленные из генератора при перезагрузке, исключаются из снимков
+        if let Ok(mut state) = self.tickers.lock() {
+            tickers.iter().for_each(|ticker| {
+                state.remove(ticker);
+            });
+        }
+    }
+
+    pub(crate) fn rate(&self) -> f64 {
+        self.events.lock().map(|events| events.rate).unwrap_or_default()
+    }
+
+    pub(crate) fn sequence(&self) -> u64 {
+        self.events.lock().map(|events| events.sequence).unwrap_or_default()
+    }
+
+    pub(crate) fn events_since<F: Fn(&MarketMessage) -> bool>(
+        &self,
+        sequence: u64,
+        filter: F,
+    ) -> Option<Vec<(u64, MarketMessage)>> {
+        //сообщения с номером больше указанного, None - часть сообщений уже вытеснена из истории
+        let events = self.events.lock().ok()?;
+        let oldest = events.history.front().map(|(oldest, _)| *oldest).unwrap_or(events.sequence + 1);
+        if sequence + 1 < oldest {
+            return None;
+        }
+        //номера событий в истории идут подряд
+        let start = (sequence + 1 - oldest) as usize;
+        Some(
+            events
+                .history
+                .range(start.min(events.history.len())..)
+                .filter(|(_, message)| filter(message))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub(crate) fn tickers(&self) -> Vec<String> {
+        let mut tickers: Vec<String> = self
+            .tickers
+            .lock()
+            .map(|tickers| tickers.keys().cloned().collect())
+            .unwrap_or_default();
+        tickers.sort();
+        tickers
+    }
+
+    pub(crate) fn get(&self, ticker: &str) -> Option<TickerState> {
+        self.tickers.lock().ok()?.get(ticker).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quote_lib::quote::marketmessage::TradeCondition;
+
+    #[test]
+    fn test_market_state_update() {
+        let market = MarketState::default();
+        assert!(market.get("A").is_none());
+        market.update(&MarketMessage::Trade(Trade {
+            ticker: "A".to_string(),
+            sequence: 1,
+            price: 10.5,
+            size: 100,
+            condition: TradeCondition::Regular,
+            timestamp: 1,
+        }));
+        let state = market.get("A").unwrap();
+        assert_eq!(state.last_price(), Some(10.5));
+        assert!(state.book.is_none());
+        assert_eq!(state.messages().len(), 1);
+    }
+
+    #[test]
+    fn test_events_since() {
+        let market = MarketState::default();
+        assert_eq!(market.events_since(0, |_| true), Some(Vec::new()));
+        for timestamp in 0..EVENT_HISTORY_SIZE as u64 + 2 {
+            let line = format!("T|A|{}|10|100|R|{}", timestamp, timestamp);
+            market.update(&MarketMessage::from_string(&line).unwrap());
+        }
+        assert_eq!(market.sequence(), EVENT_HISTORY_SIZE as u64 + 2);
+        let events = market.events_since(EVENT_HISTORY_SIZE as u64, |_| true).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0, EVENT_HISTORY_SIZE as u64 + 1);
+        assert!(market.events_since(EVENT_HISTORY_SIZE as u64, |message| message.ticker() == "B").unwrap().is_empty());
+        //события 1 и 2 вытеснены из истории
+        assert!(market.events_since(2, |_| true).is_some());
+        assert!(market.events_since(1, |_| true).is_none());
+    }
+}
@@ -0,0 +1,406 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use crate::quote::market_state::MarketState;
+use crate::server::QuoteServerThreadState;
+use crate::tls::ControlStream;
+use quote_lib::quote::execution::{ExecutionReport, ExecutionStatus, OrderSide};
+use quote_lib::quote::orderbook::OrderBook;
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::SplitWhitespace;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//период проверки лимитных заявок на исполнение
+const MATCHING_PERIOD_MILLISECOND: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OrderType {
+    Market,
+    Limit(f64),
+}
+
+#[derive(Clone, Debug)]
+struct PaperOrder {
+    id: u64,
+    ticker: String,
+    side: OrderSide,
+    order_type: OrderType,
+    quantity: u32,
+    leaves: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Position {
+    //количество акций, отрицательное - короткая позиция
+    quantity: i64,
+    avg_price: f64,
+    realized_pnl: f64,
+}
+
+impl Position {
+    fn apply_fill(&mut self, side: OrderSide, quantity: u32, price: f64) {
+        let fill = match side {
+            OrderSide::Buy => quantity as i64,
+            OrderSide::Sell => -(quantity as i64),
+        };
+        if self.quantity == 0 || self.quantity.signum() == fill.signum() {
+            //увеличение позиции - пересчет средней цены
+            let total = self.quantity.abs() + fill.abs();
+            self.avg_price = (self.avg_price * self.quantity.abs() as f64
+                + price * fill.abs() as f64)
+                / total as f64;
+        } else {
+            //сокращение позиции - фиксация прибыли/убытка
+            let closed = fill.abs().min(self.quantity.abs());
+            self.realized_pnl +=
+                (price - self.avg_price) * closed as f64 * self.quantity.signum() as f64;
+            if fill.abs() > self.quantity.abs() {
+                //разворот позиции по цене сделки
+                self.avg_price = price;
+            }
+        }
+        self.quantity += fill;
+        if self.quantity == 0 {
+            self.avg_price = 0.0;
+        }
+    }
+
+    fn unrealized_pnl(&self, mark_price: f64) -> f64 {
+        (mark_price - self.avg_price) * self.quantity as f64
+    }
+}
+
+//счет бумажной торговли сессии: активные заявки и позиции
+#[derive(Default)]
+pub(crate) struct PaperAccount {
+    next_order_id: u64,
+    orders: Vec<PaperOrder>,
+    positions: HashMap<String, Position>,
+}
+
+fn timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl PaperAccount {
+    pub(crate) fn parse_order(
+        parts: &mut SplitWhitespace,
+    ) -> Option<(OrderSide, String, u32, OrderType)> {
+        //ORDER <BUY|SELL> <ticker> <quantity> [MARKET | LIMIT <price>]
+        let side = OrderSide::from_code(parts.next()?)?;
+        let ticker = parts.next()?.to_string();
+        let quantity = parts.next()?.parse::<u32>().ok()?;
+        let order_type = match parts.next().map(|s| s.to_uppercase()).as_deref() {
+            None | Some("MARKET") => OrderType::Market,
+            Some("LIMIT") => OrderType::Limit(parts.next()?.parse::<f64>().ok()?),
+            _ => return None,
+        };
+        Some((side, ticker, quantity, order_type))
+    }
+
+    fn fill_order(order: &mut PaperOrder, book: &OrderBook) -> Vec<(u32, f64)> {
+        //исполнение по уровням противоположной стороны стакана с учетом лимитной цены
+        let levels = match order.side {
+            OrderSide::Buy => &book.asks,
+            OrderSide::Sell => &book.bids,
+        };
+        let mut fills = Vec::new();
+        for level in levels {
+            if order.leaves == 0 {
+                break;
+            }
+            if let OrderType::Limit(limit) = order.order_type {
+                let acceptable = match order.side {
+                    OrderSide::Buy => level.price <= limit,
+                    OrderSide::Sell => level.price >= limit,
+                };
+                if !acceptable {
+                    break;
+                }
+            }
+            let fill = order.leaves.min(level.size);
+            order.leaves -= fill;
+            fills.push((fill, level.price));
+        }
+        fills
+    }
+
+    fn execute(&mut self, order: &mut PaperOrder, book: &OrderBook) -> Vec<ExecutionReport> {
+        let timestamp = timestamp_millis();
+        let mut reports = Vec::new();
+        let mut leaves = order.leaves;
+        for (quantity, price) in PaperAccount::fill_order(order, book) {
+            leaves -= quantity;
+            self.positions
+                .entry(order.ticker.clone())
+                .or_default()
+                .apply_fill(order.side, quantity, price);
+            reports.push(ExecutionReport {
+                order_id: order.id,
+                ticker: order.ticker.clone(),
+                side: order.side,
+                quantity,
+                price,
+                leaves,
+                status: if leaves == 0 {
+                    ExecutionStatus::Filled
+                } else {
+                    ExecutionStatus::PartiallyFilled
+                },
+                timestamp,
+            });
+        }
+        reports
+    }
+
+    fn cancel_report(order: &PaperOrder) -> ExecutionReport {
+        ExecutionReport {
+            order_id: order.id,
+            ticker: order.ticker.clone(),
+            side: order.side,
+            quantity: 0,
+            price: 0.0,
+            leaves: 0,
+            status: ExecutionStatus::Canceled,
+            timestamp: timestamp_millis(),
+        }
+    }
+
+    pub(crate) fn place_order(
+        &mut self,
+        side: OrderSide,
+        ticker: &str,
+        quantity: u32,
+        order_type: OrderType,
+        market: &MarketState,
+    ) -> Result<(u64, Vec<ExecutionReport>), String> {
+        if quantity == 0 {
+            return Err("quantity must be positive".to_string());
+        }
+        if let OrderType::Limit(price) = order_type
+            && price <= 0.0
+        {
+            return Err("limit price must be positive".to_string());
+        }
+        let Some(book) = market.get(ticker).and_then(|state| state.book) else {
+            return Err(format!("unknown ticker {}", ticker));
+        };
+        self.next_order_id += 1;
+        let mut order = PaperOrder {
+            id: self.next_order_id,
+            ticker: ticker.to_string(),
+            side,
+            order_type,
+            quantity,
+            leaves: quantity,
+        };
+        let mut reports = self.execute(&mut order, &book);
+        if order.leaves > 0 {
+            match order.order_type {
+                //остаток рыночной заявки сверх глубины стакана снимается
+                OrderType::Market => reports.push(PaperAccount::cancel_report(&order)),
+                OrderType::Limit(_) => self.orders.push(order.clone()),
+            }
+        }
+        Ok((order.id, reports))
+    }
+
+    pub(crate) fn cancel_order(&mut self, order_id: u64) -> Option<ExecutionReport> {
+        let index = self.orders.iter().position(|order| order.id == order_id)?;
+        let order = self.orders.remove(index);
+        Some(PaperAccount::cancel_report(&order))
+    }
+
+    pub(crate) fn match_orders(&mut self, market: &MarketState) -> Vec<ExecutionReport> {
+        //проверка активных лимитных заявок по текущему стакану
+        let mut orders = std::mem::take(&mut self.orders);
+        let mut reports = Vec::new();
+        for order in orders.iter_mut() {
+            if let Some(book) = market.get(&order.ticker).and_then(|state| state.book) {
+                reports.extend(self.execute(order, &book));
+            }
+        }
+        orders.retain(|order| order.leaves > 0);
+        self.orders = orders;
+        reports
+    }
+
+    pub(crate) fn report(&self, market: &MarketState) -> String {
+        //активные заявки, позиции и итоговый P&L сессии
+        let mut report = String::new();
+        for order in &self.orders {
+            let limit = match order.order_type {
+                OrderType::Limit(price) => price,
+                OrderType::Market => 0.0,
+            };
+            report += &format!(
+                "ORDER|{}|{}|{}|{}|{}|{}\n",
+                order.id,
+                order.ticker,
+                order.side.code(),
+                order.quantity,
+                order.leaves,
+                limit
+            );
+        }
+        let mut realized_total = 0.0;
+        let mut unrealized_total = 0.0;
+        let mut tickers: Vec<&String> = self.positions.keys().collect();
+        tickers.sort();
+        for ticker in tickers {
+            let position = &self.positions[ticker];
+            let mark_price = market
+                .get(ticker)
+                .and_then(|state| state.last_price())
+                .unwrap_or(position.avg_price);
+            let unrealized = position.unrealized_pnl(mark_price);
+            realized_total += position.realized_pnl;
+            unrealized_total += unrealized;
+            report += &format!(
+                "POSITION|{}|{}|{:.4}|{:.2}|{:.2}\n",
+                ticker, position.quantity, position.avg_price, position.realized_pnl, unrealized
+            );
+        }
+        report += &format!(
+            "PNL|{:.2}|{:.2}|{:.2}\n",
+            realized_total,
+            unrealized_total,
+            realized_total + unrealized_total
+        );
+        report
+    }
+
+    pub(crate) fn thread_matching(
+        account: Arc<Mutex<PaperAccount>>,
+        market: MarketState,
+        writer: Arc<Mutex<ControlStream>>,
+        thread_state: Arc<Mutex<QuoteServerThreadState>>,
+    ) -> Result<(), QuoteStreamServerError> {
+        //поток исполнения лимитных заявок - отчеты отправляются клиенту по TCP
+        if let Ok(mut state) = thread_state.lock() {
+            //сессия закрыта раньше запуска потока
+            if *state == QuoteServerThreadState::Cancelled {
+                return Ok(());
+            }
+            *state = QuoteServerThreadState::Running;
+        } else {
+            return Err(QuoteStreamServerError::ChangeThreadStateError(
+                "Error change thread matching state".to_string(),
+            ));
+        }
+        log::debug!("thread matching orders: run");
+        loop {
+            let reports = match account.lock() {
+                Ok(mut account) => account.match_orders(&market),
+                Err(_) => Vec::new(),
+            };
+            if !reports.is_empty()
+                && let Ok(mut writer) = writer.lock()
+            {
+                for report in reports {
+                    writer.write_all(format!("{}\n", report).as_bytes())?;
+                }
+                writer.flush()?;
+            }
+            if let Ok(state) = thread_state.lock()
+                && (*state == QuoteServerThreadState::Cancelled
+                    || *state == QuoteServerThreadState::Stopped)
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(MATCHING_PERIOD_MILLISECOND));
+        }
+        log::debug!("thread matching orders: stop");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quote_lib::quote::marketmessage::MarketMessage;
+    use quote_lib::quote::orderbook::BookLevel;
+
+    fn test_market(bid: f64, ask: f64) -> MarketState {
+        let market = MarketState::default();
+        let mut book = OrderBook::new("A");
+        book.bids = vec![
+            BookLevel { price: bid, size: 100 },
+            BookLevel { price: bid - 0.01, size: 100 },
+        ];
+        book.asks = vec![
+            BookLevel { price: ask, size: 100 },
+            BookLevel { price: ask + 0.01, size: 100 },
+        ];
+        market.update(&MarketMessage::Book(book));
+        market
+    }
+
+    #[test]
+    fn test_parse_order() {
+        let mut parts = "BUY AAPL 100 LIMIT 210.5".split_whitespace();
+        let order = PaperAccount::parse_order(&mut parts).unwrap();
+        assert_eq!(order, (OrderSide::Buy, "AAPL".to_string(), 100, OrderType::Limit(210.5)));
+        let mut parts = "sell AAPL 10".split_whitespace();
+        assert_eq!(PaperAccount::parse_order(&mut parts).unwrap().3, OrderType::Market);
+        let mut parts = "BUY AAPL ten".split_whitespace();
+        assert!(PaperAccount::parse_order(&mut parts).is_none());
+    }
+
+    #[test]
+    fn test_market_order_walks_book() {
+        let market = test_market(10.0, 10.01);
+        let mut account = PaperAccount::default();
+        let (id, reports) = account
+            .place_order(OrderSide::Buy, "A", 250, OrderType::Market, &market)
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].status, ExecutionStatus::PartiallyFilled);
+        assert_eq!((reports[1].quantity, reports[1].price), (100, 10.02));
+        assert_eq!(reports[2].status, ExecutionStatus::Canceled);
+        assert!(account.orders.is_empty());
+        assert!(account
+            .place_order(OrderSide::Buy, "B", 1, OrderType::Market, &market)
+            .is_err());
+    }
+
+    #[test]
+    fn test_limit_order_rests_and_fills() {
+        let market = test_market(10.0, 10.01);
+        let mut account = PaperAccount::default();
+        let (id, reports) = account
+            .place_order(OrderSide::Sell, "A", 50, OrderType::Limit(10.5), &market)
+            .unwrap();
+        assert!(reports.is_empty());
+        assert!(account.match_orders(&market).is_empty());
+        //рынок дошел до лимитной цены
+        let market = test_market(10.5, 10.51);
+        let reports = account.match_orders(&market);
+        assert_eq!(reports[0].order_id, id);
+        assert_eq!(reports[0].status, ExecutionStatus::Filled);
+        assert!(account.cancel_order(id).is_none());
+        assert_eq!(account.positions["A"].quantity, -50);
+    }
+
+    #[test]
+    fn test_position_pnl() {
+        let mut position = Position::default();
+        position.apply_fill(OrderSide::Buy, 100, 10.0);
+        position.apply_fill(OrderSide::Buy, 100, 12.0);
+        assert_eq!(position.avg_price, 11.0);
+        position.apply_fill(OrderSide::Sell, 150, 13.0);
+        assert_eq!(position.quantity, 50);
+        assert_eq!(position.realized_pnl, 300.0);
+        assert_eq!(position.unrealized_pnl(12.0), 50.0);
+        position.apply_fill(OrderSide::Sell, 100, 14.0);
+        assert_eq!(position.quantity, -50);
+        assert_eq!(position.avg_price, 14.0);
+        assert_eq!(position.realized_pnl, 450.0);
+    }
+}
@@ -1,10 +1,24 @@
 
+use crate::config::Tuning;
 use crate::error::servererror::QuoteStreamServerError;
+use crate::multicast::{MulticastGroup, MulticastOptions};
+use crate::quote::limits::RateLimiter;
+use crate::quote::market_state::MarketState;
+use crate::quote::server_metrics::ServerMetrics;
+use crate::quote::sessions::StreamCounters;
 use crate::server::QuoteServerThreadState;
+use crate::tls::ControlStream;
+use crate::udp_seal::{GroupKey, UdpSealer};
 use crossbeam_channel::Receiver;
 use log;
-use quote_lib::quote::stockquote::StockQuote;
+use quote_lib::quote::marketmessage::MarketMessage;
+use quote_lib::quote::orderbook::OrderBook;
+use quote_lib::quote::subscription::TickerSubscription;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::mem;
 use std::net::UdpSocket;
+use std::sync::atomic::Ordering::Relaxed;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -17,9 +31,174 @@ pub(crate) struct QuoteStream {
 pub(crate) enum QuoteStreamResult {
     Canceled,
 }
+
+//подписка клиента на тикер: сделки с последней отправки и последние сообщения прочих типов
+pub(crate) struct SubscribedTicker {
+    subscription: TickerSubscription,
+    //котировка, статистика и стакан - состояние, повторяется каждый цикл отправки
+    last_messages: Vec<MarketMessage>,
+    //сделки - события, каждая отправляется один раз
+    trades: VecDeque<MarketMessage>,
+}
+
+impl SubscribedTicker {
+    pub(crate) fn new(subscription: TickerSubscription) -> Self {
+        Self {
+            subscription,
+            last_messages: Vec::new(),
+            trades: VecDeque::new(),
+        }
+    }
+
+    fn update(&mut self, message: &MarketMessage) {
+        if !self.subscription.accepts(message) {
+            return;
+        }
+        //сделка в очередь, при переполнении (клиент не успевает) отбрасывается самая старая
+        if let MarketMessage::Trade(_) = message {
+            if self.trades.len() >= MAX_PENDING_TRADES {
+                self.trades.pop_front();
+            }
+            self.trades.push_back(message.clone());
+            return;
+        }
+        //заменяем последнее сообщение того же типа
+        match self
+            .last_messages
+            .iter_mut()
+            .find(|last| mem::discriminant(*last) == mem::discriminant(message))
+        {
+            Some(last) => *last = message.clone(),
+            None => self.last_messages.push(message.clone()),
+        }
+    }
+
+    fn has_messages(&self) -> bool {
+        !self.trades.is_empty() || !self.last_messages.is_empty()
+    }
+
+    //сообщения цикла отправки: накопленные сделки и текущее состояние тикера
+    fn take_messages(&mut self) -> Vec<MarketMessage> {
+        self.trades
+            .drain(..)
+            .chain(self.last_messages.iter().cloned())
+            .collect()
+    }
+}
+
 const UDP_READ_TIMEOUT_SECOND: u64 = 6;
 const UDP_SEND_PERIOD: u64 = 2;
 const PING_READ_TIMEOUT: u64 = 5;
+//период отправки новых сообщений по TCP, мс
+const TCP_SEND_PERIOD_MILLISECOND: u64 = 500;
+//период отправки полного снимка стакана (в циклах отправки), между снимками - обновления
+const BOOK_SNAPSHOT_PERIOD: u64 = 5;
+//сделок тикера в очереди между циклами отправки UDP
+const MAX_PENDING_TRADES: usize = 1000;
+
+//параметры потока UDP из секции [stream] файла настроек, сек
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StreamTuning {
+    pub(crate) udp_send_period: u64,
+    pub(crate) ping_read_timeout: u64,
+    pub(crate) udp_read_timeout: u64,
+}
+
+impl Default for StreamTuning {
+    fn default() -> Self {
+        Self {
+            udp_send_period: UDP_SEND_PERIOD,
+            ping_read_timeout: PING_READ_TIMEOUT,
+            udp_read_timeout: UDP_READ_TIMEOUT_SECOND,
+        }
+    }
+}
+
+impl StreamTuning {
+    pub(crate) fn from_tuning(tuning: &Tuning) -> Self {
+        let default = StreamTuning::default();
+        Self {
+            udp_send_period: tuning.stream_udp_send_period.unwrap_or(default.udp_send_period),
+            ping_read_timeout: tuning.stream_ping_read_timeout.unwrap_or(default.ping_read_timeout),
+            udp_read_timeout: tuning.stream_udp_read_timeout.unwrap_or(default.udp_read_timeout),
+        }
+    }
+}
+
+//стаканы, отправленные клиенту, для вычисления инкрементальных обновлений
+#[derive(Default)]
+struct BookPublisher {
+    sent: HashMap<String, (OrderBook, u64)>,
+}
+
+impl BookPublisher {
+    fn publish(&mut self, book: &OrderBook) -> Option<MarketMessage> {
+        //снимок при первой отправке и периодически, иначе изменения с последней отправки
+        match self.sent.get_mut(&book.ticker) {
+            Some((sent, count)) if *count % BOOK_SNAPSHOT_PERIOD != 0 => {
+                *count += 1;
+                let update = sent.diff(book)?;
+                *sent = book.clone();
+                Some(MarketMessage::BookUpdate(update))
+            }
+            Some((sent, count)) => {
+                *count += 1;
+                *sent = book.clone();
+                Some(MarketMessage::Book(book.clone()))
+            }
+            None => {
+                self.sent.insert(book.ticker.clone(), (book.clone(), 1));
+                Some(MarketMessage::Book(book.clone()))
+            }
+        }
+    }
+}
+
+//новые сообщения из общего состояния рынка с последнего опроса
+pub(crate) struct MarketPoller {
+    //метка времени последнего отправленного сообщения по тикеру и типу
+    sent: HashMap<(String, mem::Discriminant<MarketMessage>), u64>,
+    //стакан: обновления между снимками или только снимки
+    book_publisher: Option<BookPublisher>,
+}
+
+impl MarketPoller {
+    pub(crate) fn new(book_updates: bool) -> Self {
+        Self {
+            sent: HashMap::new(),
+            book_publisher: book_updates.then(BookPublisher::default),
+        }
+    }
+
+    pub(crate) fn poll(
+        &mut self,
+        market: &MarketState,
+        subscriptions: &[TickerSubscription],
+    ) -> Vec<MarketMessage> {
+        let mut messages = Vec::new();
+        for subscription in subscriptions {
+            let Some(state) = market.get(&subscription.ticker) else {
+                continue;
+            };
+            for message in state.messages() {
+                if !subscription.accepts(&message) {
+                    continue;
+                }
+                let key = (subscription.ticker.clone(), mem::discriminant(&message));
+                if self.sent.get(&key) == Some(&message.timestamp()) {
+                    continue;
+                }
+                self.sent.insert(key, message.timestamp());
+                let message = match (&message, &mut self.book_publisher) {
+                    (MarketMessage::Book(book), Some(book_publisher)) => book_publisher.publish(book),
+                    _ => Some(message),
+                };
+                messages.extend(message);
+            }
+        }
+        messages
+    }
+}
 
 impl QuoteStream {
     pub fn new(udp_socket: UdpSocket) -> Result<Self, QuoteStreamServerError> {
@@ -30,8 +209,8 @@ impl QuoteStream {
     }
 
     fn thread_update_tickers(
-        r: Receiver<StockQuote>,
-        tickers: Arc<Mutex<Vec<StockQuote>>>,
+        r: Receiver<MarketMessage>,
+        tickers: Arc<Mutex<Vec<SubscribedTicker>>>,
         thread_state: Arc<Mutex<QuoteServerThreadState>>,
     ) -> Result<QuoteStreamResult, QuoteStreamServerError> {
         //Метод обновления котировок
@@ -47,13 +226,9 @@ impl QuoteStream {
         loop {
             if let Ok(quote) = r.recv() {
                 if let Ok(mut tickers_guard) = tickers.lock() {
-                    tickers_guard.iter_mut().for_each(|ticker| {
-                        if ticker.ticker == quote.ticker {
-                            ticker.price = quote.price;
-                            ticker.volume = quote.volume;
-                            ticker.timestamp = quote.timestamp;
-                        }
-                    });
+                    tickers_guard
+                        .iter_mut()
+                        .for_each(|ticker| ticker.update(&quote));
                 }
             } else {
                 return Err(QuoteStreamServerError::ReceiveQuoteError(
@@ -73,19 +248,25 @@ impl QuoteStream {
         Ok(QuoteStreamResult::Canceled)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn thread_stream(
         udp_bind_adr: UdpSocket,
         client_adr: &str,
-        receiver: Receiver<StockQuote>,
-        tickers: Arc<Mutex<Vec<StockQuote>>>,
+        receiver: Receiver<MarketMessage>,
+        tickers: Arc<Mutex<Vec<SubscribedTicker>>>,
         thread_state: Arc<Mutex<QuoteServerThreadState>>,
+        counters: Arc<StreamCounters>,
+        metrics: Arc<ServerMetrics>,
+        sealer: Arc<UdpSealer>,
+        budget: Option<Arc<RateLimiter>>,
+        tuning: StreamTuning,
     ) -> Result<QuoteStreamResult, QuoteStreamServerError> {
         //Метод стриммиинга - отправляет данные клиенту, запускает поток обновления данных
         //отсанавливает поток обновления котировк в случает не получаени данных ping от клиента
         let mut socket = Self::new(udp_bind_adr)?;
         socket
             .socket
-            .set_read_timeout(Some(Duration::from_secs(UDP_READ_TIMEOUT_SECOND)))?;
+            .set_read_timeout(Some(Duration::from_secs(tuning.udp_read_timeout)))?;
         if let Ok(mut state) = thread_state.lock() {
             *state = QuoteServerThreadState::Running;
         } else {
@@ -97,19 +278,46 @@ impl QuoteStream {
         let subscribe_tickers_update = tickers.clone();
         let thread_state_updater = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
         let thread_state_ticker_update = thread_state_updater.clone();
-        let _ = thread::spawn(move || {
-            return QuoteStream::thread_update_tickers(
+        let updater = thread::spawn(move || {
+            QuoteStream::thread_update_tickers(
                 receiver,
                 subscribe_tickers_update,
                 thread_state_ticker_update,
-            );
+            )
         });
+        //ожидание первых сообщений от потока обновления, чтобы клиент сразу получил данные
+        for _ in 0..tuning.udp_read_timeout * 10 {
+            if let Ok(tickers_guard) = tickers.lock()
+                && tickers_guard.iter().any(|ticker| ticker.has_messages())
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let mut book_publisher = BookPublisher::default();
         //основной цикл отправления данных клиенту
         loop {
-            if let Ok(tickers_guard) = tickers.lock() {
-                tickers_guard.iter().for_each(|tickers| {
-                    let _ = socket.socket.send_to(&tickers.to_bytes(), client_adr);
-                });
+            if let Ok(mut tickers_guard) = tickers.lock() {
+                tickers_guard
+                    .iter_mut()
+                    .flat_map(|ticker| ticker.take_messages())
+                    .filter_map(|message| match message {
+                        MarketMessage::Book(book) => book_publisher.publish(&book),
+                        message => Some(message),
+                    })
+                    .for_each(|message| {
+                        //общее ограничение скорости датаграмм сервера - сообщение пропускается
+                        if budget.as_ref().is_some_and(|budget| !budget.try_acquire()) {
+                            metrics.datagrams_dropped.fetch_add(1, Relaxed);
+                            return;
+                        }
+                        //датаграммы запечатываются ключом сессии - клиент отбрасывает подделки и повторы
+                        let datagram = sealer.seal(&message.to_bytes());
+                        if let Ok(size) = socket.socket.send_to(&datagram, client_adr) {
+                            counters.datagrams_sent.fetch_add(1, Relaxed);
+                            counters.bytes_sent.fetch_add(size as u64, Relaxed);
+                        }
+                    });
             }
             let mut ping = [0u8; 1024];
             match socket.socket.recv_from(&mut ping) {
@@ -120,6 +328,9 @@ impl QuoteStream {
                     {
                         socket.keep_alive_timestamp =
                             SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                        counters.pings_received.fetch_add(1, Relaxed);
+                        counters.last_ping.store(socket.keep_alive_timestamp, Relaxed);
+                        metrics.pings_received.fetch_add(1, Relaxed);
                     }
                 }
                 Err(e) => {
@@ -127,10 +338,14 @@ impl QuoteStream {
                 }
             }
             if SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() - socket.keep_alive_timestamp
-                > PING_READ_TIMEOUT
+                > tuning.ping_read_timeout
             {
-                if let Ok(mut state) = thread_state.lock() {
+                if let Ok(mut state) = thread_state.lock()
+                    && *state == QuoteServerThreadState::Running
+                {
                     *state = QuoteServerThreadState::Cancelled;
+                    metrics.ping_timeouts.fetch_add(1, Relaxed);
+                    log::info!("stream {}: ping timeout", client_adr);
                 }
             }
             if let Ok(state) = thread_state.lock() {
@@ -140,7 +355,7 @@ impl QuoteStream {
                     break;
                 }
             }
-            thread::sleep(Duration::from_secs(UDP_SEND_PERIOD));
+            thread::sleep(Duration::from_secs(tuning.udp_send_period));
         }
         //ожидание остановки потока обновления котировок
         loop {
@@ -151,39 +366,139 @@ impl QuoteStream {
             log::debug!("wait stop updater...");
             thread::sleep(Duration::from_secs(1));
         }
+        //поток обновления завершается на следующем сообщении генератора
+        let _ = updater.join();
         log::debug!("thread stream quotes: stop");
         Ok(QuoteStreamResult::Canceled)
     }
+
+    pub(crate) fn thread_stream_tcp(
+        writer: Arc<Mutex<ControlStream>>,
+        market: MarketState,
+        subscriptions: Vec<TickerSubscription>,
+        thread_state: Arc<Mutex<QuoteServerThreadState>>,
+        counters: Arc<StreamCounters>,
+    ) -> Result<QuoteStreamResult, QuoteStreamServerError> {
+        //Метод стриминга по управляющему TCP соединению - для клиентов, у которых UDP недоступен
+        //сообщения берутся из общего состояния рынка, отправляются только новые, по строке на сообщение
+        if let Ok(mut state) = thread_state.lock() {
+            *state = QuoteServerThreadState::Running;
+        } else {
+            return Err(QuoteStreamServerError::ChangeThreadStateError(
+                "Error change thread stream state".to_string(),
+            ));
+        }
+        log::debug!("thread stream quotes tcp: run");
+        let mut poller = MarketPoller::new(true);
+        loop {
+            let data: Vec<u8> = poller
+                .poll(&market, &subscriptions)
+                .iter()
+                .flat_map(|message| message.to_bytes())
+                .collect();
+            if !data.is_empty() {
+                let result = match writer.lock() {
+                    Ok(mut writer) => writer.write_all(&data).and_then(|_| writer.flush()),
+                    Err(_) => Ok(()),
+                };
+                //ошибка записи - клиент отключился
+                if let Err(e) = result {
+                    log::error!("Error send quotes tcp: {}", e);
+                    break;
+                }
+                counters.bytes_sent.fetch_add(data.len() as u64, Relaxed);
+            }
+            if let Ok(state) = thread_state.lock()
+                && (*state == QuoteServerThreadState::Cancelled
+                    || *state == QuoteServerThreadState::Stopped)
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(TCP_SEND_PERIOD_MILLISECOND));
+        }
+        log::debug!("thread stream quotes tcp: stop");
+        Ok(QuoteStreamResult::Canceled)
+    }
+
+    pub(crate) fn thread_multicast(
+        receiver: Receiver<MarketMessage>,
+        groups: Vec<MulticastGroup>,
+        options: MulticastOptions,
+        budget: Option<Arc<RateLimiter>>,
+        metrics: Arc<ServerMetrics>,
+        group_key: Arc<GroupKey>,
+    ) -> Result<QuoteStreamResult, QuoteStreamServerError> {
+        //Метод многоадресной рассылки - каждое сообщение генератора отправляется один раз
+        //в группы, публикующие тикер, независимо от числа клиентов; датаграммы запечатаны ключом групп
+        let mut publishers = Vec::new();
+        for group in groups {
+            let socket = options.sender_socket(&group.addr)?;
+            log::info!("multicast group {}: publish", group.addr);
+            publishers.push((group, socket, BookPublisher::default()));
+        }
+        loop {
+            //канал закрыт - генератор остановлен при завершении сервера
+            let Ok(message) = receiver.recv() else {
+                log::info!("multicast stream: stop");
+                return Ok(QuoteStreamResult::Canceled);
+            };
+            for (group, socket, book_publisher) in publishers.iter_mut() {
+                if !group.publishes(message.ticker()) {
+                    continue;
+                }
+                if budget.as_ref().is_some_and(|budget| !budget.try_acquire()) {
+                    metrics.datagrams_dropped.fetch_add(1, Relaxed);
+                    continue;
+                }
+                let message = match &message {
+                    MarketMessage::Book(book) => book_publisher.publish(book),
+                    message => Some(message.clone()),
+                };
+                if let Some(message) = message
+                    && let Err(e) = socket.send_to(&group_key.seal(&message.to_bytes()), group.addr)
+                {
+                    log::error!("Error send multicast group {}: {}", group.addr, e);
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crossbeam_channel::bounded;
+    use quote_lib::quote::marketmessage::{Quote, Trade, TradeCondition};
     #[test]
     fn test_thread_update_tickers() {
-        let (sender, receiver) = bounded::<StockQuote>(5);
+        let (sender, receiver) = bounded::<MarketMessage>(5);
         let thread_stop;
-        let initial_quote = StockQuote {
-            ticker: "A".to_string(),
-            price: 10.0,
-            volume: 10,
-            timestamp: 10,
-        };
-
-        let tickers = Arc::new(Mutex::new(vec![initial_quote.clone()]));
+        let subscription = TickerSubscription::from_string("A:trades").unwrap();
+        let tickers = Arc::new(Mutex::new(vec![SubscribedTicker::new(subscription)]));
         let thread_state = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
 
         let tickers_clone = tickers.clone();
         let thread_state_clone = thread_state.clone();
 
-        let updated_quote = StockQuote {
+        let updated_trade = MarketMessage::Trade(Trade {
             ticker: "A".to_string(),
+            sequence: 2,
             price: 10.0,
-            volume: 2000,
+            size: 2000,
+            condition: TradeCondition::Regular,
             timestamp: 2000,
-        };
-        sender.send(updated_quote.clone()).unwrap();
+        });
+        let updated_quote = MarketMessage::Quote(Quote {
+            ticker: "A".to_string(),
+            sequence: 2,
+            bid: 9.9,
+            bid_size: 100,
+            ask: 10.1,
+            ask_size: 100,
+            timestamp: 2000,
+        });
+        sender.send(updated_trade.clone()).unwrap();
+        sender.send(updated_quote).unwrap();
 
         let handle = thread::spawn(move || {
             QuoteStream::thread_update_tickers(receiver, tickers_clone, thread_state_clone)
@@ -193,10 +508,11 @@ mod test {
 
         // Verify update
         {
-            let tickers_guard = tickers.lock().unwrap();
-            assert_eq!(tickers_guard[0].price, 10.0);
-            assert_eq!(tickers_guard[0].volume, 2000);
-            assert_eq!(tickers_guard[0].timestamp, 2000);
+            //сделка отправляется один раз, котировка отфильтрована подпиской
+            let mut tickers_guard = tickers.lock().unwrap();
+            assert!(tickers_guard[0].last_messages.is_empty());
+            assert_eq!(tickers_guard[0].take_messages(), vec![updated_trade]);
+            assert!(tickers_guard[0].take_messages().is_empty());
         }
 
         {
@@ -212,4 +528,57 @@ mod test {
         }
         assert!(thread_stop);
     }
+
+    #[test]
+    fn test_trades_sent_once() {
+        //каждая сделка между циклами отправки - один раз, котировка - каждый цикл
+        let mut ticker = SubscribedTicker::new(TickerSubscription::from_string("A").unwrap());
+        ticker.update(&MarketMessage::from_string("T|A|1|10|100|R|1000").unwrap());
+        ticker.update(&MarketMessage::from_string("T|A|2|10.1|50|R|1001").unwrap());
+        ticker.update(&MarketMessage::from_string("Q|A|2|9.9|100|10.1|100|1001").unwrap());
+        assert_eq!(ticker.take_messages().len(), 3);
+        let messages = ticker.take_messages();
+        assert!(matches!(messages.as_slice(), [MarketMessage::Quote(_)]));
+        for i in 0..MAX_PENDING_TRADES + 1 {
+            ticker.update(&MarketMessage::from_string(&format!("T|A|{}|10|100|R|{}", 3 + i, 2000 + i)).unwrap());
+        }
+        assert_eq!(ticker.take_messages().len(), MAX_PENDING_TRADES + 1);
+    }
+
+    #[test]
+    fn test_book_publisher() {
+        let mut publisher = BookPublisher::default();
+        let mut book = OrderBook::new("A");
+        book.bids.push(quote_lib::quote::orderbook::BookLevel { price: 10.0, size: 100 });
+        assert!(matches!(publisher.publish(&book), Some(MarketMessage::Book(_))));
+        //без изменений уровней обновление не отправляется
+        book.sequence = 1;
+        assert!(publisher.publish(&book).is_none());
+        book.sequence = 2;
+        book.bids[0].size = 200;
+        let Some(MarketMessage::BookUpdate(update)) = publisher.publish(&book) else {
+            panic!("expected book update");
+        };
+        assert_eq!((update.prev_sequence, update.sequence), (0, 2));
+        for _ in 3..BOOK_SNAPSHOT_PERIOD {
+            publisher.publish(&book);
+        }
+        assert!(matches!(publisher.publish(&book), Some(MarketMessage::Book(_))));
+    }
+
+    #[test]
+    fn test_market_poller() {
+        let market = MarketState::default();
+        let subscriptions = TickerSubscription::parse_list("A:trades+book,B");
+        let mut poller = MarketPoller::new(false);
+        market.update(&MarketMessage::from_string("T|A|1|10|100|R|1000").unwrap());
+        market.update(&MarketMessage::from_string("Q|A|1|9.9|100|10.1|100|1000").unwrap());
+        market.update(&MarketMessage::from_string("B|A|1|1000|10:100|10.01:300").unwrap());
+        assert_eq!(poller.poll(&market, &subscriptions).len(), 2);
+        //без новых сообщений повторно не отправляется
+        assert!(poller.poll(&market, &subscriptions).is_empty());
+        market.update(&MarketMessage::from_string("B|A|2|2000|10:200|10.01:300").unwrap());
+        let messages = poller.poll(&market, &subscriptions);
+        assert!(matches!(messages.as_slice(), [MarketMessage::Book(_)]));
+    }
 }
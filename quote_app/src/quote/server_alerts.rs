@@ -0,0 +1,314 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use crate::quote::market_state::MarketState;
+use crate::server::QuoteServerThreadState;
+use crate::tls::ControlStream;
+use quote_lib::quote::alert::{AlertComparison, AlertKind, ServerAlert};
+use quote_lib::quote::marketmessage::Trade;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::str::SplitWhitespace;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+//период проверки условий оповещений
+const ALERT_CHECK_PERIOD_MILLISECOND: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AlertCondition {
+    //цена сделки относительно порога
+    Price(AlertComparison, f64),
+    //изменение цены по модулю в процентах за окно времени, мс
+    Move(f64, u64),
+    //объем сделки в кратности к среднему объему сделки за сессию
+    Volume(f64),
+}
+
+impl AlertCondition {
+    fn kind(&self) -> AlertKind {
+        match self {
+            AlertCondition::Price(..) => AlertKind::Price,
+            AlertCondition::Move(..) => AlertKind::Move,
+            AlertCondition::Volume(_) => AlertKind::Volume,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AlertSubscription {
+    id: u64,
+    ticker: String,
+    condition: AlertCondition,
+    //условие срабатывает один раз, пока значение не вернется за порог
+    armed: bool,
+    history: VecDeque<(u64, f64)>,
+}
+
+impl AlertSubscription {
+    fn evaluate(&mut self, trade: &Trade, average_size: Option<f64>) -> Option<ServerAlert> {
+        let value = match self.condition {
+            AlertCondition::Price(comparison, threshold) => {
+                comparison.matches(trade.price, threshold).then_some(trade.price)
+            }
+            AlertCondition::Move(percent, window) => {
+                self.history.push_back((trade.timestamp, trade.price));
+                while let Some((timestamp, _)) = self.history.front()
+                    && trade.timestamp.saturating_sub(*timestamp) > window
+                {
+                    self.history.pop_front();
+                }
+                let (_, base) = self.history.front()?;
+                let change = (trade.price - base) / base * 100.0;
+                (change.abs() >= percent).then_some((change * 100.0).round() / 100.0)
+            }
+            AlertCondition::Volume(multiplier) => {
+                //каждая крупная сделка - отдельное оповещение
+                let average_size = average_size?;
+                let ratio = trade.size as f64 / average_size;
+                return (ratio >= multiplier).then(|| self.alert(trade, (ratio * 100.0).round() / 100.0));
+            }
+        };
+        match value {
+            Some(value) if self.armed => {
+                self.armed = false;
+                Some(self.alert(trade, value))
+            }
+            Some(_) => None,
+            None => {
+                self.armed = true;
+                None
+            }
+        }
+    }
+
+    fn alert(&self, trade: &Trade, value: f64) -> ServerAlert {
+        ServerAlert {
+            alert_id: self.id,
+            ticker: self.ticker.clone(),
+            kind: self.condition.kind(),
+            value,
+            price: trade.price,
+            timestamp: trade.timestamp,
+        }
+    }
+}
+
+fn parse_duration_millis(s: &str) -> Option<u64> {
+    //длительность: `30s`, `5m`, `1h` или секунды
+    let (value, multiplier) = match s.chars().last()? {
+        's' => (&s[..s.len() - 1], 1000),
+        'm' => (&s[..s.len() - 1], 60 * 1000),
+        'h' => (&s[..s.len() - 1], 60 * 60 * 1000),
+        _ => (s, 1000),
+    };
+    value.parse::<u64>().ok().map(|value| value * multiplier)
+}
+
+//оповещения сессии, условия проверяются по сделкам генератора
+#[derive(Debug, Default)]
+pub(crate) struct ServerAlerts {
+    next_alert_id: u64,
+    alerts: Vec<AlertSubscription>,
+    //метка времени последней проверенной сделки по тикеру
+    last_trades: HashMap<String, u64>,
+}
+
+impl ServerAlerts {
+    pub(crate) fn parse_alert(cmd: &mut SplitWhitespace) -> Option<(String, AlertCondition)> {
+        //ALERT <ticker> PRICE <op> <value>
+        //ALERT <ticker> MOVE <percent> <window>
+        //ALERT <ticker> VOLUME <multiplier>
+        let ticker = cmd.next()?.to_string();
+        let kind = AlertKind::from_code(cmd.next()?)?;
+        let parts: Vec<&str> = cmd.collect();
+        let condition = match (kind, parts.as_slice()) {
+            (AlertKind::Price, [op, value]) => {
+                AlertCondition::Price(AlertComparison::parse(op)?, value.parse().ok()?)
+            }
+            (AlertKind::Move, [percent, window]) => {
+                let percent: f64 = percent.parse().ok()?;
+                let window = parse_duration_millis(window)?;
+                if percent <= 0.0 || window == 0 {
+                    return None;
+                }
+                AlertCondition::Move(percent, window)
+            }
+            (AlertKind::Volume, [multiplier]) => {
+                let multiplier: f64 = multiplier.parse().ok()?;
+                if multiplier <= 0.0 {
+                    return None;
+                }
+                AlertCondition::Volume(multiplier)
+            }
+            _ => return None,
+        };
+        Some((ticker, condition))
+    }
+
+    pub(crate) fn add_alert(
+        &mut self,
+        ticker: &str,
+        condition: AlertCondition,
+        market: &MarketState,
+    ) -> Result<u64, String> {
+        if market.get(ticker).is_none() {
+            return Err(format!("unknown ticker {}", ticker));
+        }
+        self.next_alert_id += 1;
+        self.alerts.push(AlertSubscription {
+            id: self.next_alert_id,
+            ticker: ticker.to_string(),
+            condition,
+            armed: true,
+            history: VecDeque::new(),
+        });
+        Ok(self.next_alert_id)
+    }
+
+    pub(crate) fn remove_alert(&mut self, alert_id: u64) -> bool {
+        let count = self.alerts.len();
+        self.alerts.retain(|alert| alert.id != alert_id);
+        self.alerts.len() != count
+    }
+
+    pub(crate) fn check_alerts(&mut self, market: &MarketState) -> Vec<ServerAlert> {
+        //каждая сделка тикера проверяется один раз
+        let mut fired = Vec::new();
+        let mut tickers: Vec<String> = self.alerts.iter().map(|alert| alert.ticker.clone()).collect();
+        tickers.sort();
+        tickers.dedup();
+        for ticker in tickers {
+            let Some(state) = market.get(&ticker) else {
+                continue;
+            };
+            let Some(trade) = state.trade else {
+                continue;
+            };
+            if self.last_trades.get(&ticker) == Some(&trade.timestamp) {
+                continue;
+            }
+            self.last_trades.insert(ticker.clone(), trade.timestamp);
+            let average_size = state
+                .stats
+                .filter(|stats| stats.trades > 0)
+                .map(|stats| stats.volume as f64 / stats.trades as f64);
+            for alert in self.alerts.iter_mut().filter(|alert| alert.ticker == ticker) {
+                if let Some(server_alert) = alert.evaluate(&trade, average_size) {
+                    fired.push(server_alert);
+                }
+            }
+        }
+        fired
+    }
+
+    pub(crate) fn thread_alerts(
+        alerts: Arc<Mutex<ServerAlerts>>,
+        market: MarketState,
+        writer: Arc<Mutex<ControlStream>>,
+        thread_state: Arc<Mutex<QuoteServerThreadState>>,
+    ) -> Result<(), QuoteStreamServerError> {
+        //поток проверки оповещений - сработавшие оповещения отправляются клиенту по TCP
+        if let Ok(mut state) = thread_state.lock() {
+            //сессия закрыта раньше запуска потока
+            if *state == QuoteServerThreadState::Cancelled {
+                return Ok(());
+            }
+            *state = QuoteServerThreadState::Running;
+        } else {
+            return Err(QuoteStreamServerError::ChangeThreadStateError(
+                "Error change thread alerts state".to_string(),
+            ));
+        }
+        log::debug!("thread alerts: run");
+        loop {
+            let fired = match alerts.lock() {
+                Ok(mut alerts) => alerts.check_alerts(&market),
+                Err(_) => Vec::new(),
+            };
+            if !fired.is_empty()
+                && let Ok(mut writer) = writer.lock()
+            {
+                for alert in fired {
+                    writer.write_all(format!("{}\n", alert).as_bytes())?;
+                }
+                writer.flush()?;
+            }
+            if let Ok(state) = thread_state.lock()
+                && (*state == QuoteServerThreadState::Cancelled
+                    || *state == QuoteServerThreadState::Stopped)
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(ALERT_CHECK_PERIOD_MILLISECOND));
+        }
+        log::debug!("thread alerts: stop");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quote_lib::quote::marketmessage::MarketMessage;
+
+    fn trade(market: &MarketState, price: f64, size: u32, timestamp: u64) {
+        let line = format!("T|A|{}|{}|{}|R|{}", timestamp, price, size, timestamp);
+        market.update(&MarketMessage::from_string(&line).unwrap());
+    }
+
+    #[test]
+    fn test_parse_alert() {
+        let (ticker, condition) =
+            ServerAlerts::parse_alert(&mut "AAPL MOVE 2.5 5m".split_whitespace()).unwrap();
+        assert_eq!(ticker, "AAPL");
+        assert_eq!(condition, AlertCondition::Move(2.5, 300_000));
+        assert!(ServerAlerts::parse_alert(&mut "AAPL PRICE = 200".split_whitespace()).is_none());
+        assert!(ServerAlerts::parse_alert(&mut "AAPL VOLUME -1".split_whitespace()).is_none());
+    }
+
+    #[test]
+    fn test_price_alert_fires_once() {
+        let market = MarketState::default();
+        let mut alerts = ServerAlerts::default();
+        assert!(alerts.add_alert("A", AlertCondition::Volume(2.0), &market).is_err());
+        trade(&market, 99.0, 100, 1);
+        let id = alerts
+            .add_alert("A", AlertCondition::Price(AlertComparison::Greater, 100.0), &market)
+            .unwrap();
+        assert!(alerts.check_alerts(&market).is_empty());
+        trade(&market, 101.0, 100, 2);
+        let fired = alerts.check_alerts(&market);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].alert_id, id);
+        trade(&market, 102.0, 100, 3);
+        assert!(alerts.check_alerts(&market).is_empty());
+        trade(&market, 99.0, 100, 4);
+        alerts.check_alerts(&market);
+        trade(&market, 101.0, 100, 5);
+        assert_eq!(alerts.check_alerts(&market).len(), 1);
+        assert!(alerts.remove_alert(id));
+        assert!(!alerts.remove_alert(id));
+    }
+
+    #[test]
+    fn test_move_and_volume_alert() {
+        let market = MarketState::default();
+        let mut alerts = ServerAlerts::default();
+        trade(&market, 100.0, 100, 1000);
+        alerts.add_alert("A", AlertCondition::Move(2.0, 60_000), &market).unwrap();
+        alerts.check_alerts(&market);
+        trade(&market, 97.5, 100, 2000);
+        let fired = alerts.check_alerts(&market);
+        assert_eq!(fired[0].kind, AlertKind::Move);
+        assert_eq!(fired[0].value, -2.5);
+
+        let mut alerts = ServerAlerts::default();
+        alerts.add_alert("A", AlertCondition::Volume(3.0), &market).unwrap();
+        market.update(&MarketMessage::from_string("S|A|10|100|101|97|98|1000|10|3000").unwrap());
+        trade(&market, 98.0, 400, 3000);
+        let fired = alerts.check_alerts(&market);
+        assert_eq!(fired[0].kind, AlertKind::Volume);
+        assert_eq!(fired[0].value, 4.0);
+    }
+}
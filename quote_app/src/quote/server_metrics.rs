@@ -0,0 +1,91 @@
+
+use crate::metrics::MetricsText;
+use crate::quote::market_state::MarketState;
+use crate::quote::sessions::SessionRegistry;
+use std::collections::BTreeSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+//счетчики сервера, не привязанные к сессии: сессии удаляются из реестра
+#[derive(Debug, Default)]
+pub(crate) struct ServerMetrics {
+    pub(crate) pings_received: AtomicU64,
+    pub(crate) ping_timeouts: AtomicU64,
+    //соединения, отклоненные по ограничению числа сессий
+    pub(crate) connections_rejected: AtomicU64,
+    //датаграммы, не отправленные сверх общего ограничения скорости
+    pub(crate) datagrams_dropped: AtomicU64,
+}
+
+impl ServerMetrics {
+    pub(crate) fn render(&self, market: &MarketState, sessions: &SessionRegistry) -> String {
+        let sessions = sessions.list();
+        let tickers: BTreeSet<&String> = sessions
+            .iter()
+            .filter(|session| session.transport.is_some())
+            .flat_map(|session| session.tickers.iter())
+            .collect();
+        let mut text = MetricsText::default();
+        text.metric("quote_server_active_sessions", "gauge", "Client sessions in the registry")
+            .sample("quote_server_active_sessions", &[], sessions.len());
+        text.metric("quote_server_subscribed_tickers", "gauge", "Distinct tickers of running streams")
+            .sample("quote_server_subscribed_tickers", &[], tickers.len());
+        text.metric("quote_server_datagrams_sent_total", "counter", "Datagrams sent to the session stream");
+        for session in sessions.iter() {
+            let id = session.id.to_string();
+            text.sample(
+                "quote_server_datagrams_sent_total",
+                &[("session", &id), ("peer", &session.peer)],
+                session.counters.datagrams_sent.load(Relaxed),
+            );
+        }
+        text.metric("quote_server_bytes_sent_total", "counter", "Bytes sent to the session stream");
+        for session in sessions.iter() {
+            let id = session.id.to_string();
+            text.sample(
+                "quote_server_bytes_sent_total",
+                &[("session", &id), ("peer", &session.peer)],
+                session.counters.bytes_sent.load(Relaxed),
+            );
+        }
+        text.metric("quote_server_pings_received_total", "counter", "PING datagrams received from clients")
+            .sample("quote_server_pings_received_total", &[], self.pings_received.load(Relaxed));
+        text.metric("quote_server_ping_timeouts_total", "counter", "Streams cancelled for PING timeout")
+            .sample("quote_server_ping_timeouts_total", &[], self.ping_timeouts.load(Relaxed));
+        text.metric("quote_server_connections_rejected_total", "counter", "Connections rejected by session limits")
+            .sample("quote_server_connections_rejected_total", &[], self.connections_rejected.load(Relaxed));
+        text.metric("quote_server_datagrams_dropped_total", "counter", "Datagrams dropped by the datagram rate limit")
+            .sample("quote_server_datagrams_dropped_total", &[], self.datagrams_dropped.load(Relaxed));
+        text.metric("quote_server_generated_messages_total", "counter", "Messages produced by the generator")
+            .sample("quote_server_generated_messages_total", &[], market.sequence());
+        text.metric("quote_server_generator_rate", "gauge", "Generator messages per second")
+            .sample("quote_server_generator_rate", &[], format!("{:.1}", market.rate()));
+        text.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quote_lib::quote::marketmessage::MarketMessage;
+
+    #[test]
+    fn test_render() {
+        let market = MarketState::default();
+        market.update(&MarketMessage::from_string("T|AAPL|1|10|100|R|1000").unwrap());
+        let sessions = SessionRegistry::default();
+        let id = sessions.register("127.0.0.1:5000");
+        sessions.start_stream(id, "udp", "127.0.0.1:55500", vec!["AAPL".to_string(), "MSFT".to_string()], None);
+        sessions.counters(id).bytes_sent.fetch_add(42, Relaxed);
+        let metrics = ServerMetrics::default();
+        metrics.ping_timeouts.fetch_add(1, Relaxed);
+        metrics.datagrams_dropped.fetch_add(3, Relaxed);
+        let text = metrics.render(&market, &sessions);
+        assert!(text.contains("quote_server_active_sessions 1\n"));
+        assert!(text.contains("quote_server_subscribed_tickers 2\n"));
+        assert!(text.contains("quote_server_bytes_sent_total{session=\"1\",peer=\"127.0.0.1:5000\"} 42\n"));
+        assert!(text.contains("quote_server_ping_timeouts_total 1\n"));
+        assert!(text.contains("quote_server_datagrams_dropped_total 3\n"));
+        assert!(text.contains("quote_server_generated_messages_total 1\n"));
+    }
+}
@@ -0,0 +1,357 @@
+
+use crate::quote::http_api::json_string;
+use crate::server::QuoteServerThreadState;
+use crate::tls::ControlStream;
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::time::Duration;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Mutex};
+
+//таймаут записи уведомления о завершении сервера, сек
+const SHUTDOWN_NOTICE_TIMEOUT_SECOND: u64 = 1;
+
+//счетчики потока сессии, обновляемые потоком отправки
+#[derive(Debug, Default)]
+pub(crate) struct StreamCounters {
+    pub(crate) datagrams_sent: AtomicU64,
+    pub(crate) bytes_sent: AtomicU64,
+    pub(crate) pings_received: AtomicU64,
+    //время последнего PING, секунды unix, 0 - PING не было
+    pub(crate) last_ping: AtomicU64,
+}
+
+//сессия клиента: управляющее соединение и поток котировок
+#[derive(Clone, Debug)]
+pub(crate) struct SessionInfo {
+    pub(crate) id: u64,
+    pub(crate) peer: String,
+    pub(crate) connected_at: DateTime<Local>,
+    //управляющее TCP соединение открыто
+    pub(crate) control: bool,
+    //транспорт потока: udp, tcp или multicast, None - поток не запущен
+    pub(crate) transport: Option<String>,
+    pub(crate) target: String,
+    pub(crate) tickers: Vec<String>,
+    pub(crate) counters: Arc<StreamCounters>,
+    //номер запуска потока - завершение старого потока после RESTREAM не сбрасывает новый
+    stream_generation: u64,
+    //управляющее соединение и состояние потока для отключения сессии командой KICK
+    control_stream: Option<Arc<TcpStream>>,
+    //запись в управляющее соединение (TCP или TLS) для уведомления о завершении сервера
+    control_writer: Option<Arc<Mutex<ControlStream>>>,
+    stream_state: Option<Arc<Mutex<QuoteServerThreadState>>>,
+}
+
+impl SessionInfo {
+    pub(crate) fn state(&self) -> &'static str {
+        match (self.control, &self.transport) {
+            (true, Some(_)) => "streaming",
+            (true, None) => "connected",
+            //клиент закрыл управляющее соединение, поток UDP идет до таймаута PING
+            (false, _) => "detached",
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let tickers: Vec<String> = self
+            .tickers
+            .iter()
+            .map(|ticker| json_string(ticker))
+            .collect();
+        format!(
+            "{{\"id\": {}, \"peer\": {}, \"connected_at\": \"{}\", \"control\": {}, \
+             \"transport\": {}, \"target\": {}, \"tickers\": [{}]}}",
+            self.id,
+            json_string(&self.peer),
+            self.connected_at.format("%Y-%m-%dT%H:%M:%S"),
+            self.control,
+            self.transport
+                .as_ref()
+                .map(|transport| json_string(transport))
+                .unwrap_or("null".to_string()),
+            json_string(&self.target),
+            tickers.join(", ")
+        )
+    }
+}
+
+//реестр сессий сервера, общий для обработчиков клиентов и HTTP API
+#[derive(Clone, Default)]
+pub(crate) struct SessionRegistry {
+    sessions: Arc<Mutex<BTreeMap<u64, SessionInfo>>>,
+    next_id: Arc<AtomicU64>,
+    //сервер завершается: обработчики останавливают потоки сессий при закрытии соединения
+    closing: Arc<AtomicBool>,
+}
+
+impl SessionRegistry {
+    pub(crate) fn register(&self, peer: &str) -> u64 {
+        let id = self.next_id.fetch_add(1, SeqCst) + 1;
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.insert(
+                id,
+                SessionInfo {
+                    id,
+                    peer: peer.to_string(),
+                    connected_at: Local::now(),
+                    control: true,
+                    transport: None,
+                    target: String::new(),
+                    tickers: Vec::new(),
+                    counters: Arc::new(StreamCounters::default()),
+                    stream_generation: 0,
+                    control_stream: None,
+                    control_writer: None,
+                    stream_state: None,
+                },
+            );
+        }
+        id
+    }
+
+    pub(crate) fn attach_control(&self, id: u64, stream: TcpStream, writer: Arc<Mutex<ControlStream>>) {
+        if let Ok(mut sessions) = self.sessions.lock()
+            && let Some(session) = sessions.get_mut(&id)
+        {
+            session.control_stream = Some(Arc::new(stream));
+            session.control_writer = Some(writer);
+        }
+    }
+
+    pub(crate) fn start_stream(
+        &self,
+        id: u64,
+        transport: &str,
+        target: &str,
+        tickers: Vec<String>,
+        stream_state: Option<Arc<Mutex<QuoteServerThreadState>>>,
+    ) -> u64 {
+        let Ok(mut sessions) = self.sessions.lock() else {
+            return 0;
+        };
+        let Some(session) = sessions.get_mut(&id) else {
+            return 0;
+        };
+        session.transport = Some(transport.to_string());
+        session.target = target.to_string();
+        session.tickers = tickers;
+        session.stream_state = stream_state;
+        session.stream_generation += 1;
+        session.stream_generation
+    }
+
+    pub(crate) fn counters(&self, id: u64) -> Arc<StreamCounters> {
+        self.sessions
+            .lock()
+            .ok()
+            .and_then(|sessions| sessions.get(&id).map(|session| session.counters.clone()))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn stream_stopped(&self, id: u64, generation: u64) {
+        //сессия без потока и управляющего соединения удаляется
+        if let Ok(mut sessions) = self.sessions.lock()
+            && let Some(session) = sessions.get_mut(&id)
+            && session.stream_generation == generation
+        {
+            session.transport = None;
+            if !session.control {
+                sessions.remove(&id);
+            }
+        }
+    }
+
+    pub(crate) fn disconnect(&self, id: u64) {
+        if let Ok(mut sessions) = self.sessions.lock()
+            && let Some(session) = sessions.get_mut(&id)
+        {
+            session.control = false;
+            //многоадресный поток сервер не отслеживает - клиент получает его без сессии
+            if session.transport.is_none() || session.transport.as_deref() == Some("multicast") {
+                sessions.remove(&id);
+            }
+        }
+    }
+
+    pub(crate) fn kick(&self, id: u64) -> bool {
+        //остановка потока и закрытие управляющего соединения, сессия удаляется их обработчиками
+        let Ok(sessions) = self.sessions.lock() else {
+            return false;
+        };
+        let Some(session) = sessions.get(&id) else {
+            return false;
+        };
+        SessionRegistry::close(session);
+        true
+    }
+
+    pub(crate) fn notify_removed(&self, removed: &[String]) -> usize {
+        //сессиям с подпиской на удаленные тикеры: `REMOVED|<tickers>`, подписка сохраняется,
+        //данные по тикеру возобновятся, если он снова появится в файле тикеров
+        //запись после освобождения реестра: клиент, не читающий соединение, не блокирует другие сессии
+        let notices: Vec<(Arc<Mutex<ControlStream>>, String)> = match self.sessions.lock() {
+            Ok(sessions) => sessions
+                .values()
+                .filter_map(|session| {
+                    let tickers: Vec<&str> = session
+                        .tickers
+                        .iter()
+                        .filter(|ticker| removed.contains(ticker))
+                        .map(|ticker| ticker.as_str())
+                        .collect();
+                    let writer = session.control_writer.clone()?;
+                    (!tickers.is_empty()).then(|| (writer, format!("REMOVED|{}\n", tickers.join(","))))
+                })
+                .collect(),
+            Err(_) => return 0,
+        };
+        let mut notified = 0;
+        for (writer, notice) in notices {
+            if let Ok(mut writer) = writer.lock() {
+                let _ = writer.write_all(notice.as_bytes());
+                let _ = writer.flush();
+                notified += 1;
+            }
+        }
+        notified
+    }
+
+    pub(crate) fn is_closing(&self) -> bool {
+        self.closing.load(SeqCst)
+    }
+
+    pub(crate) fn shutdown_all(&self, notice: &str) -> usize {
+        //завершение сервера: уведомление по управляющему соединению и закрытие всех сессий,
+        //включая отключенные сессии с потоком UDP
+        self.closing.store(true, SeqCst);
+        let Ok(sessions) = self.sessions.lock() else {
+            return 0;
+        };
+        for session in sessions.values() {
+            if let Some(writer) = &session.control_writer {
+                //клиент, не читающий соединение, не задерживает завершение
+                if let Some(control_stream) = &session.control_stream {
+                    let _ = control_stream.set_write_timeout(Some(Duration::from_secs(SHUTDOWN_NOTICE_TIMEOUT_SECOND)));
+                }
+                if let Ok(mut writer) = writer.lock() {
+                    let _ = writer.write_all(notice.as_bytes());
+                    let _ = writer.flush();
+                }
+            }
+            SessionRegistry::close(session);
+        }
+        sessions.len()
+    }
+
+    fn close(session: &SessionInfo) {
+        if let Some(stream_state) = &session.stream_state
+            && let Ok(mut state) = stream_state.lock()
+            && *state == QuoteServerThreadState::Running
+        {
+            *state = QuoteServerThreadState::Cancelled;
+        }
+        //клиент мог закрыть соединение раньше сервера
+        if let Some(control_stream) = &session.control_stream
+            && let Err(e) = control_stream.shutdown(Shutdown::Both)
+            && e.kind() != ErrorKind::NotConnected
+        {
+            log::error!("session {}: shutdown control error: {}", session.id, e);
+        }
+    }
+
+    pub(crate) fn list(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .lock()
+            .map(|sessions| sessions.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_lifetime() {
+        let registry = SessionRegistry::default();
+        let id = registry.register("127.0.0.1:5000");
+        let first = registry.start_stream(id, "udp", "127.0.0.1:55500", vec!["AAPL".to_string()], None);
+        let stream_state = Arc::new(Mutex::new(QuoteServerThreadState::Running));
+        let second = registry.start_stream(id, "udp", "127.0.0.1:55500", vec!["MSFT".to_string()],
+                                           Some(stream_state.clone()));
+        //завершение потока до RESTREAM не останавливает новый поток
+        registry.stream_stopped(id, first);
+        assert_eq!(registry.list()[0].transport.as_deref(), Some("udp"));
+        //клиент закрыл управляющее соединение, поток продолжается
+        registry.disconnect(id);
+        assert_eq!(registry.list().len(), 1);
+        assert_eq!(registry.list()[0].state(), "detached");
+        assert!(registry.list()[0].to_json().contains("\"tickers\": [\"MSFT\"]"));
+        assert!(registry.kick(id));
+        assert_eq!(*stream_state.lock().unwrap(), QuoteServerThreadState::Cancelled);
+        registry.stream_stopped(id, second);
+        assert!(registry.list().is_empty());
+        assert!(!registry.kick(id));
+        //тикер клиента с кавычкой экранируется в json сессии
+        let id = registry.register("127.0.0.1:5001");
+        registry.start_stream(id, "udp", "127.0.0.1:55501", vec!["A\"B".to_string()], None);
+        let json = serde_json::from_str::<serde_json::Value>(&registry.list()[0].to_json()).unwrap();
+        assert_eq!(json["tickers"][0], "A\"B");
+    }
+
+    #[test]
+    fn test_shutdown_all() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let registry = SessionRegistry::default();
+        let id = registry.register("127.0.0.1:5000");
+        let writer = Arc::new(Mutex::new(ControlStream::Plain(server.try_clone().unwrap())));
+        registry.attach_control(id, server, writer);
+        let stream_state = Arc::new(Mutex::new(QuoteServerThreadState::Running));
+        registry.start_stream(id, "udp", "127.0.0.1:55500", vec!["AAPL".to_string()], Some(stream_state.clone()));
+        assert_eq!(registry.shutdown_all("SHUTDOWN\n"), 1);
+        assert!(registry.is_closing());
+        assert_eq!(*stream_state.lock().unwrap(), QuoteServerThreadState::Cancelled);
+        let mut line = String::new();
+        let mut reader = BufReader::new(client);
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "SHUTDOWN\n");
+        line.clear();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_notify_removed() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+        use std::thread;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let registry = SessionRegistry::default();
+        let id = registry.register("127.0.0.1:5000");
+        let writer = Arc::new(Mutex::new(ControlStream::Plain(server.try_clone().unwrap())));
+        registry.attach_control(id, server, writer.clone());
+        registry.start_stream(id, "tcp", "", vec!["AAPL".to_string(), "MSFT".to_string()], None);
+        //запись в сессию заблокирована - реестр остается доступен другим потокам
+        let blocked = writer.lock().unwrap();
+        let notifier = {
+            let registry = registry.clone();
+            thread::spawn(move || registry.notify_removed(&["AAPL".to_string()]))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(registry.list().len(), 1);
+        drop(blocked);
+        assert_eq!(notifier.join().unwrap(), 1);
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line, "REMOVED|AAPL\n");
+    }
+}
@@ -0,0 +1,85 @@
+
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+//время ожидания VERIFY после отправки CHALLENGE
+const CHALLENGE_TIMEOUT_SECOND: u64 = 10;
+
+//адрес потока UDP из команды STREAM: по умолчанию только IP адрес управляющего соединения
+pub(crate) fn resolve_target(target: &str, peer_ip: IpAddr, allow_any: bool) -> Result<SocketAddr, String> {
+    let addr = target
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or(format!("bad udp address {}", target))?;
+    if !allow_any && addr.ip().to_canonical() != peer_ip.to_canonical() {
+        return Err(format!("udp address {} differs from client address {}", addr.ip(), peer_ip.to_canonical()));
+    }
+    Ok(addr)
+}
+
+//проверка владения адресом UDP: сервер отправляет nonce на адрес, клиент возвращает его командой VERIFY
+#[derive(Debug)]
+pub(crate) struct UdpChallenge {
+    pub(crate) target: SocketAddr,
+    pub(crate) tickers: String,
+    nonce: String,
+    issued: Instant,
+}
+
+impl UdpChallenge {
+    pub(crate) fn new(target: SocketAddr, tickers: String) -> Self {
+        Self {
+            target,
+            tickers,
+            nonce: format!("{:032x}", rand::random::<u128>()),
+            issued: Instant::now(),
+        }
+    }
+
+    pub(crate) fn send(&self, socket: &UdpSocket) -> std::io::Result<usize> {
+        //одна датаграмма размером с команду STREAM - усиления трафика нет
+        socket.send_to(format!("CHALLENGE|{}\n", self.nonce).as_bytes(), self.target)
+    }
+
+    pub(crate) fn verify(&self, nonce: &str) -> Result<(), String> {
+        if self.issued.elapsed() > Duration::from_secs(CHALLENGE_TIMEOUT_SECOND) {
+            return Err("challenge expired".to_string());
+        }
+        if nonce != self.nonce {
+            return Err("nonce mismatch".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_target() {
+        let peer: IpAddr = "127.0.0.1".parse().unwrap();
+        assert!(resolve_target("127.0.0.1:55500", peer, false).is_ok());
+        assert!(resolve_target("10.1.1.1:55500", peer, false).is_err());
+        assert!(resolve_target("10.1.1.1:55500", peer, true).is_ok());
+        assert!(resolve_target("127.0.0.1", peer, true).is_err());
+        //IPv4 клиент на сокете IPv6
+        let mapped: IpAddr = "::ffff:127.0.0.1".parse().unwrap();
+        assert!(resolve_target("127.0.0.1:55500", mapped, false).is_ok());
+    }
+
+    #[test]
+    fn test_challenge_round_trip() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let challenge = UdpChallenge::new(receiver.local_addr().unwrap(), "AAPL".to_string());
+        challenge.send(&sender).unwrap();
+        let mut data = [0u8; 64];
+        let (size, _) = receiver.recv_from(&mut data).unwrap();
+        let datagram = String::from_utf8_lossy(&data[..size]).to_string();
+        let nonce = datagram.trim().strip_prefix("CHALLENGE|").unwrap();
+        assert!(challenge.verify("0").is_err());
+        assert!(challenge.verify(nonce).is_ok());
+    }
+}
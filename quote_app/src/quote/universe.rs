@@ -0,0 +1,163 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use crate::quote::sessions::SessionRegistry;
+use quote_lib::quote::stockquote::StockQuote;
+use std::fs::File;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+//период проверки сигнала перезагрузки и времени изменения файла тикеров, мс
+const RELOAD_POLL_MILLISECOND: u64 = 1000;
+
+//тикеры генератора: читаются генератором на каждом цикле, заменяются при перезагрузке
+#[derive(Clone, Default)]
+pub(crate) struct TickerUniverse {
+    tickers: Arc<Mutex<Vec<String>>>,
+}
+
+impl TickerUniverse {
+    pub(crate) fn new(tickers: Vec<String>) -> Self {
+        Self {
+            tickers: Arc::new(Mutex::new(tickers)),
+        }
+    }
+
+    pub(crate) fn list(&self) -> Vec<String> {
+        self.tickers.lock().map(|tickers| tickers.clone()).unwrap_or_default()
+    }
+
+    pub(crate) fn replace(&self, tickers: Vec<String>) -> (Vec<String>, Vec<String>) {
+        //добавленные и удаленные тикеры
+        let Ok(mut current) = self.tickers.lock() else {
+            return (Vec::new(), Vec::new());
+        };
+        let added = tickers.iter().filter(|ticker| !current.contains(ticker)).cloned().collect();
+        let removed = current.iter().filter(|ticker| !tickers.contains(ticker)).cloned().collect();
+        *current = tickers;
+        (added, removed)
+    }
+}
+
+//перезагрузка файла тикеров по SIGHUP, команде RELOAD или изменению файла
+#[derive(Clone)]
+pub(crate) struct TickerReloader {
+    path: Option<String>,
+    universe: TickerUniverse,
+    sessions: SessionRegistry,
+}
+
+impl TickerReloader {
+    pub(crate) fn new(
+        path: Option<String>,
+        universe: TickerUniverse,
+        sessions: SessionRegistry,
+    ) -> Self {
+        Self {
+            path,
+            universe,
+            sessions,
+        }
+    }
+
+    pub(crate) fn reload(&self) -> Result<(Vec<String>, Vec<String>), QuoteStreamServerError> {
+        //файл с ошибкой или без тикеров не меняет текущие тикеры
+        let Some(path) = &self.path else {
+            return Err(QuoteStreamServerError::TickersError("no tickers file".to_string()));
+        };
+        let mut file = File::open(path)?;
+        let tickers: Vec<String> = StockQuote::get_tickers(&mut file)
+            .map_err(|e| QuoteStreamServerError::TickersError(e.to_string()))?
+            .iter()
+            .map(|ticker| ticker.trim().to_string())
+            .filter(|ticker| !ticker.is_empty())
+            .collect();
+        if tickers.is_empty() {
+            return Err(QuoteStreamServerError::TickersError(format!("{}: no tickers", path)));
+        }
+        //состояние рынка удаленных тикеров очищает генератор на следующем цикле
+        let (added, removed) = self.universe.replace(tickers);
+        let notified = self.sessions.notify_removed(&removed);
+        log::info!(
+            "tickers reloaded: added {:?}, removed {:?}, sessions notified {}",
+            added,
+            removed,
+            notified
+        );
+        Ok((added, removed))
+    }
+
+    pub(crate) fn run_watch(&self, reload_signal: Arc<AtomicBool>, watch_file: bool) {
+        //поток перезагрузки: флаг SIGHUP и, если задано, время изменения файла тикеров
+        let modified = || {
+            self.path
+                .as_ref()
+                .and_then(|path| std::fs::metadata(path).ok())
+                .and_then(|metadata| metadata.modified().ok())
+        };
+        let mut last_modified: Option<SystemTime> = modified();
+        loop {
+            thread::sleep(Duration::from_millis(RELOAD_POLL_MILLISECOND));
+            let mut reload = reload_signal.swap(false, SeqCst);
+            if watch_file {
+                let current = modified();
+                if current != last_modified {
+                    last_modified = current;
+                    reload = true;
+                }
+            }
+            if reload && let Err(e) = self.reload() {
+                log::error!("tickers reload error: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tls::ControlStream;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_replace() {
+        let universe = TickerUniverse::new(vec!["AAPL".to_string(), "MSFT".to_string()]);
+        let (added, removed) = universe.replace(vec!["MSFT".to_string(), "GOOG".to_string()]);
+        assert_eq!(added, vec!["GOOG".to_string()]);
+        assert_eq!(removed, vec!["AAPL".to_string()]);
+        assert_eq!(universe.list(), vec!["MSFT".to_string(), "GOOG".to_string()]);
+    }
+
+    #[test]
+    fn test_reload_notifies_sessions() {
+        let path = std::env::temp_dir().join(format!("quote-tickers-{}.txt", std::process::id()));
+        std::fs::write(&path, "MSFT\nGOOG\n").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let sessions = SessionRegistry::default();
+        let id = sessions.register("127.0.0.1:5000");
+        let writer = Arc::new(Mutex::new(ControlStream::Plain(server.try_clone().unwrap())));
+        sessions.attach_control(id, server, writer);
+        sessions.start_stream(id, "tcp", "control", vec!["AAPL".to_string(), "MSFT".to_string()], None);
+        let universe = TickerUniverse::new(vec!["AAPL".to_string(), "MSFT".to_string()]);
+        let reloader = TickerReloader::new(
+            Some(path.to_string_lossy().to_string()),
+            universe.clone(),
+            sessions,
+        );
+        let (added, removed) = reloader.reload().unwrap();
+        assert_eq!((added, removed), (vec!["GOOG".to_string()], vec!["AAPL".to_string()]));
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line, "REMOVED|AAPL\n");
+        //пустой файл не меняет тикеры
+        std::fs::write(&path, "\n").unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(universe.list().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,14 @@
 
+use crate::config::Tuning;
 use crate::error::servererror::QuoteStreamServerError;
-use crossbeam_channel::Sender;
-use quote_lib::quote::stockquote::StockQuote;
+use crate::quote::market_state::MarketState;
+use crate::quote::universe::TickerUniverse;
+use crate::server::QuoteServerThreadState;
+use crossbeam_channel::{Sender, TrySendError};
+use quote_lib::quote::marketmessage::{MarketMessage, Quote, SessionStats, Trade, TradeCondition};
+use quote_lib::quote::orderbook::{BookLevel, OrderBook};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -11,53 +18,347 @@ const BIG_PRICE: f64 = 210.0;
 const LOW_PRICE: f64 = 40.0;
 //пауза потока обновления котировок
 const WAIT_MILLISECOND_NEXT_GENERATION: u64 = 100;
+//размер сделки меньше стандартного лота
+const ODD_LOT_SIZE: u32 = 100;
+//размер блочной (крупной) сделки
+const BLOCK_TRADE_SIZE: u32 = 4000;
+//шаг цены между уровнями стакана
+const PRICE_TICK: f64 = 0.01;
+//максимальное количество лотов на уровне стакана
+const MAX_LEVEL_LOTS: f64 = 20.0;
+//тикеры с ценой около BIG_PRICE
+const BIG_PRICE_TICKERS: [&str; 3] = ["AAPL", "MSFT", "TSLA"];
 
-#[derive(Default)]
-pub(crate) struct QuoteGenerator {}
+//параметры генератора из секции [generator] файла настроек
+#[derive(Clone, Debug)]
+pub(crate) struct GeneratorTuning {
+    pub(crate) period_millisecond: u64,
+    pub(crate) low_price: f64,
+    pub(crate) big_price: f64,
+    pub(crate) big_price_tickers: Vec<String>,
+}
+
+impl Default for GeneratorTuning {
+    fn default() -> Self {
+        Self {
+            period_millisecond: WAIT_MILLISECOND_NEXT_GENERATION,
+            low_price: LOW_PRICE,
+            big_price: BIG_PRICE,
+            big_price_tickers: BIG_PRICE_TICKERS.iter().map(|ticker| ticker.to_string()).collect(),
+        }
+    }
+}
+
+impl GeneratorTuning {
+    pub(crate) fn from_tuning(tuning: &Tuning) -> Result<Self, String> {
+        let default = GeneratorTuning::default();
+        let generator = Self {
+            period_millisecond: tuning.generator_period_millisecond.unwrap_or(default.period_millisecond),
+            low_price: tuning.generator_low_price.unwrap_or(default.low_price),
+            big_price: tuning.generator_big_price.unwrap_or(default.big_price),
+            big_price_tickers: tuning.generator_big_price_tickers.clone().unwrap_or(default.big_price_tickers),
+        };
+        //диапазоны цен не пересекаются: дешевые до low_price * 1.9
+        if generator.low_price >= generator.big_price {
+            return Err(format!(
+                "generator.low_price {} must be below generator.big_price {}",
+                generator.low_price, generator.big_price
+            ));
+        }
+        Ok(generator)
+    }
+
+    //популярные акции: большая цена и больший объем сделок
+    fn is_big(&self, ticker: &str) -> bool {
+        self.big_price_tickers.iter().any(|big| big == ticker)
+    }
+
+    fn price_band(&self, ticker: &str) -> (f64, f64) {
+        if self.is_big(ticker) {
+            // Популярные акции имеют большую цену
+            (self.big_price, self.big_price * 1.05)
+        } else {
+            // Обычные акции - средняя цена
+            (self.low_price, self.low_price * 1.9)
+        }
+    }
+}
+
+//состояние рынка по тикеру: стакан и статистика сессии
+struct TickerMarket {
+    book: OrderBook,
+    stats: Option<SessionStats>,
+}
+
+pub(crate) struct QuoteGenerator {
+    markets: HashMap<String, TickerMarket>,
+    //количество уровней стакана на каждой стороне
+    book_depth: usize,
+    tuning: GeneratorTuning,
+}
+
+fn round_price(price: f64) -> f64 {
+    (price * 100.0).round() / 100.0
+}
+
+fn random_level_size() -> u32 {
+    ODD_LOT_SIZE * (1 + (rand::random::<f64>() * MAX_LEVEL_LOTS) as u32)
+}
 
 impl QuoteGenerator {
-    fn generate_quote(ticker: &str) -> Option<StockQuote> {
-        //генрация стоимости котировк
-        let last_price = match ticker {
-            // Популярные акции имеют больший объём
-            "AAPL" | "MSFT" | "TSLA" => BIG_PRICE + (rand::random::<f64>() * BIG_PRICE * 0.05),
-            // Обычные акции - средний объём
-            _ => LOW_PRICE + (rand::random::<f64>() * LOW_PRICE * 0.9),
+    #[cfg(test)]
+    pub(crate) fn new(book_depth: usize) -> Self {
+        QuoteGenerator::with_tuning(book_depth, GeneratorTuning::default())
+    }
+
+    pub(crate) fn with_tuning(book_depth: usize, tuning: GeneratorTuning) -> Self {
+        Self {
+            markets: HashMap::new(),
+            book_depth: book_depth.max(1),
+            tuning,
+        }
+    }
+
+    fn init_book(ticker: &str, (low, high): (f64, f64), depth: usize) -> OrderBook {
+        //начальный стакан вокруг случайной цены из диапазона тикера
+        let mid = low + (0.1 + rand::random::<f64>() * 0.8) * (high - low);
+        let best_bid = round_price(mid);
+        let mut book = OrderBook::new(ticker);
+        book.bids.push(BookLevel {
+            price: best_bid,
+            size: random_level_size(),
+        });
+        book.asks.push(BookLevel {
+            price: round_price(best_bid + PRICE_TICK),
+            size: random_level_size(),
+        });
+        QuoteGenerator::fill_depth(&mut book, depth);
+        book
+    }
+
+    fn fill_depth(book: &mut OrderBook, depth: usize) {
+        //добавляет глубокие уровни до нужной глубины и отбрасывает лишние
+        book.bids.truncate(depth);
+        book.asks.truncate(depth);
+        while let Some(last) = book.bids.last()
+            && book.bids.len() < depth
+        {
+            let price = round_price(last.price - PRICE_TICK);
+            book.bids.push(BookLevel {
+                price,
+                size: random_level_size(),
+            });
+        }
+        while let Some(last) = book.asks.last()
+            && book.asks.len() < depth
+        {
+            let price = round_price(last.price + PRICE_TICK);
+            book.asks.push(BookLevel {
+                price,
+                size: random_level_size(),
+            });
+        }
+    }
+
+    fn execute(book: &mut OrderBook, buy: bool, size: u32) -> Option<(f64, u32)> {
+        //исполнение рыночной заявки по уровням противоположной стороны
+        let levels = if buy { &mut book.asks } else { &mut book.bids };
+        let mut remaining = size;
+        let mut price = None;
+        while remaining > 0
+            && let Some(level) = levels.first_mut()
+        {
+            let fill = remaining.min(level.size);
+            level.size -= fill;
+            remaining -= fill;
+            price = Some(level.price);
+            if level.size == 0 {
+                levels.remove(0);
+            }
+        }
+        price.map(|price| (price, size - remaining))
+    }
+
+    fn replenish(book: &mut OrderBook, buy: bool, trade_price: f64, depth: usize) {
+        //сторона, полностью съеденная сделкой, начинается со следующего шага цены
+        if buy && book.asks.is_empty() {
+            book.asks.push(BookLevel {
+                price: round_price(trade_price + PRICE_TICK),
+                size: random_level_size(),
+            });
+        }
+        if !buy && book.bids.is_empty() {
+            book.bids.push(BookLevel {
+                price: round_price(trade_price - PRICE_TICK),
+                size: random_level_size(),
+            });
+        }
+        //восстанавливаем спред в один шаг цены, сдвигая сторону инициатора сделки
+        while let (Some(best_bid), Some(best_ask)) = (book.best_bid(), book.best_ask())
+            && best_ask.price - best_bid.price > PRICE_TICK * 1.5
+        {
+            if buy {
+                let price = round_price(best_bid.price + PRICE_TICK);
+                book.bids.insert(0, BookLevel { price, size: random_level_size() });
+            } else {
+                let price = round_price(best_ask.price - PRICE_TICK);
+                book.asks.insert(0, BookLevel { price, size: random_level_size() });
+            }
+        }
+        QuoteGenerator::fill_depth(book, depth);
+        //случайное изменение ликвидности на одном из уровней (новые заявки и отмены)
+        let levels = if rand::random::<bool>() { &mut book.bids } else { &mut book.asks };
+        if !levels.is_empty() {
+            let last = levels.len() - 1;
+            let index = (rand::random::<f64>() * levels.len() as f64) as usize;
+            levels[index.min(last)].size = random_level_size();
+        }
+    }
+
+    fn generate_trade(&mut self, ticker: &str) -> Option<Trade> {
+        let depth = self.book_depth;
+        let (low, high) = self.tuning.price_band(ticker);
+        let market = self
+            .markets
+            .entry(ticker.to_string())
+            .or_insert_with(|| TickerMarket {
+                book: QuoteGenerator::init_book(ticker, (low, high), depth),
+                stats: None,
+            });
+        //сторона инициатора сделки, цена удерживается в диапазоне тикера
+        let mid = match (market.book.best_bid(), market.book.best_ask()) {
+            (Some(bid), Some(ask)) => (bid.price + ask.price) / 2.0,
+            _ => (low + high) / 2.0,
+        };
+        let buy_probability = if mid > high {
+            0.2
+        } else if mid < low {
+            0.8
+        } else {
+            0.5
         };
-        //генерация объемов котировок
-        let volume = match ticker {
+        let buy = rand::random::<f64>() < buy_probability;
+        //генерация объема сделки
+        let size = if self.tuning.is_big(ticker) {
             // Популярные акции имеют больший объём
-            "AAPL" | "MSFT" | "TSLA" => 1000 + (rand::random::<f64>() * 5000.0) as u32,
+            1 + (rand::random::<f64>() * 5000.0) as u32
+        } else {
             // Обычные акции - средний объём
-            _ => 100 + (rand::random::<f64>() * 1000.0) as u32,
+            1 + (rand::random::<f64>() * 1000.0) as u32
+        };
+        let (price, size) = QuoteGenerator::execute(&mut market.book, buy, size)?;
+        QuoteGenerator::replenish(&mut market.book, buy, price, depth);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis() as u64;
+        market.book.sequence += 1;
+        market.book.timestamp = timestamp;
+        let condition = if market.stats.is_none() {
+            TradeCondition::Opening
+        } else if size < ODD_LOT_SIZE {
+            TradeCondition::OddLot
+        } else if size >= BLOCK_TRADE_SIZE {
+            TradeCondition::Block
+        } else {
+            TradeCondition::Regular
         };
 
-        Some(StockQuote {
+        //версия стакана после сделки - номер версии сделки, котировки и статистики тикера
+        Some(Trade {
             ticker: ticker.to_string(),
-            price: last_price,
-            volume,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .ok()?
-                .as_millis() as u64,
+            sequence: market.book.sequence,
+            price,
+            size,
+            condition,
+            timestamp,
+        })
+    }
+
+    fn generate_quote(book: &OrderBook) -> Option<Quote> {
+        //котировка bid/ask - лучшие уровни стакана
+        let bid = book.best_bid()?;
+        let ask = book.best_ask()?;
+        Some(Quote {
+            ticker: book.ticker.clone(),
+            sequence: book.sequence,
+            bid: bid.price,
+            bid_size: bid.size,
+            ask: ask.price,
+            ask_size: ask.size,
+            timestamp: book.timestamp,
         })
     }
+
+    fn generate_messages(&mut self, ticker: &str) -> Option<Vec<MarketMessage>> {
+        //сделка, котировка, обновленная статистика сессии и стакан по тикеру
+        let trade = self.generate_trade(ticker)?;
+        let market = self.markets.get_mut(ticker)?;
+        let quote = QuoteGenerator::generate_quote(&market.book)?;
+        let stats = match &mut market.stats {
+            Some(stats) => {
+                stats.apply(&trade);
+                stats.clone()
+            }
+            None => market.stats.insert(SessionStats::open(&trade)).clone(),
+        };
+        Some(vec![
+            MarketMessage::Trade(trade),
+            MarketMessage::Quote(quote),
+            MarketMessage::Stats(stats),
+            MarketMessage::Book(market.book.clone()),
+        ])
+    }
+
     pub(crate) fn thread_generate(
-        s: Sender<StockQuote>,
-        tickers: &Vec<String>,
+        &mut self,
+        s: Sender<MarketMessage>,
+        universe: &TickerUniverse,
+        market: MarketState,
+        thread_state: Arc<Mutex<QuoteServerThreadState>>,
     ) -> Result<(), QuoteStreamServerError> {
+        if let Ok(mut state) = thread_state.lock() {
+            *state = QuoteServerThreadState::Running;
+        }
+        let mut previous = universe.list();
         loop {
-            for ticker in tickers {
-                if let Some(quote) = QuoteGenerator::generate_quote(ticker) {
-                    if let Err(e) = s.send(quote) {
-                        return Err(QuoteStreamServerError::GeneratorQuoteError(format!(
-                            "Error sender quote {}",
-                            e
-                        )));
+            //генератор останавливается последним - после потоков сессий, читающих канал
+            if let Ok(mut state) = thread_state.lock()
+                && *state == QuoteServerThreadState::Cancelled
+            {
+                *state = QuoteServerThreadState::Stopped;
+                log::info!("generator: stop");
+                return Ok(());
+            }
+            //тикеры перечитываются на каждом цикле - перезагрузка файла тикеров без перезапуска
+            let tickers = universe.list();
+            let removed: Vec<String> = previous.iter().filter(|ticker| !tickers.contains(ticker)).cloned().collect();
+            if !removed.is_empty() {
+                market.remove(&removed);
+                removed.iter().for_each(|ticker| {
+                    self.markets.remove(ticker);
+                });
+            }
+            for ticker in &tickers {
+                if let Some(messages) = self.generate_messages(ticker) {
+                    for message in messages {
+                        market.update(&message);
+                        //канал заполнен - нет потоков UDP, читающих канал; состояние рынка уже
+                        //обновлено, и генерация продолжается для SSE, WebSocket и потока TCP
+                        match s.try_send(message) {
+                            Ok(()) | Err(TrySendError::Full(_)) => {}
+                            Err(e) => {
+                                return Err(QuoteStreamServerError::GeneratorQuoteError(format!(
+                                    "Error sender quote {}",
+                                    e
+                                )));
+                            }
+                        }
                     }
                 }
             }
-            thread::sleep(Duration::from_millis(WAIT_MILLISECOND_NEXT_GENERATION));
+            previous = tickers;
+            thread::sleep(Duration::from_millis(self.tuning.period_millisecond));
         }
     }
 }
@@ -69,9 +370,56 @@ mod test {
     fn test_generate_quote() {
         let tickers_a = "A";
         let tickers_b = "AAPL";
-        let test_tickers_a = QuoteGenerator::generate_quote(tickers_a).unwrap();
-        let test_tickers_b = QuoteGenerator::generate_quote(tickers_b).unwrap();
+        let mut generator = QuoteGenerator::new(5);
+        let test_tickers_a = generator.generate_trade(tickers_a).unwrap();
+        let test_tickers_b = generator.generate_trade(tickers_b).unwrap();
         assert!(test_tickers_a.price > 40.0 && test_tickers_a.price < 100.0);
         assert!(test_tickers_b.price > 120.0);
+        let quote = QuoteGenerator::generate_quote(&generator.markets["AAPL"].book).unwrap();
+        assert!(quote.bid < quote.ask);
+    }
+
+    #[test]
+    fn test_generate_messages_stats() {
+        let mut generator = QuoteGenerator::new(5);
+        let first = generator.generate_messages("A").unwrap();
+        let second = generator.generate_messages("A").unwrap();
+        let (MarketMessage::Trade(first_trade), MarketMessage::Trade(second_trade)) =
+            (&first[0], &second[0])
+        else {
+            panic!("first message must be trade");
+        };
+        assert_eq!(first_trade.condition, TradeCondition::Opening);
+        assert_ne!(second_trade.condition, TradeCondition::Opening);
+        let MarketMessage::Stats(stats) = &second[2] else {
+            panic!("third message must be stats");
+        };
+        assert_eq!(stats.trades, 2);
+        assert_eq!(stats.volume, (first_trade.size + second_trade.size) as u64);
+        //версия тикера растет с каждой сделкой и общая для сообщений одной сделки
+        assert_eq!((first_trade.sequence, second_trade.sequence), (1, 2));
+        assert!(second.iter().all(|message| message.sequence() == 2));
+    }
+
+    #[test]
+    fn test_book_consistent_with_trades() {
+        let mut generator = QuoteGenerator::new(5);
+        for _ in 0..200 {
+            let messages = generator.generate_messages("MSFT").unwrap();
+            let MarketMessage::Book(book) = &messages[3] else {
+                panic!("last message must be book");
+            };
+            assert_eq!(book.bids.len(), 5);
+            assert_eq!(book.asks.len(), 5);
+            let spread = book.best_ask().unwrap().price - book.best_bid().unwrap().price;
+            assert!(spread > 0.0 && spread < PRICE_TICK * 1.5);
+            assert!(book.bids.windows(2).all(|w| w[0].price > w[1].price));
+            assert!(book.asks.windows(2).all(|w| w[0].price < w[1].price));
+            let MarketMessage::Trade(trade) = &messages[0] else {
+                panic!("first message must be trade");
+            };
+            //сделка прошла по цене не дальше нескольких шагов от текущего стакана
+            assert!((trade.price - book.best_bid().unwrap().price).abs() < 1.0);
+        }
     }
 }
@@ -0,0 +1,237 @@
+
+use crate::error::servererror::QuoteStreamServerError;
+use crate::quote::entitlements::{split_entitled, Entitlements};
+use crate::quote::market_state::MarketState;
+use crate::quote::quote_stream::MarketPoller;
+use quote_lib::quote::subscription::TickerSubscription;
+use serde_json::Value;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Error, Message, WebSocket};
+
+//период опроса состояния рынка и ожидания команд браузера, мс
+const WS_POLL_PERIOD_MILLISECOND: u64 = 100;
+
+//сессия браузера: подписка и новые сообщения с последней отправки
+pub(crate) struct WsSession {
+    subscriptions: Vec<TickerSubscription>,
+    streaming: bool,
+    poller: MarketPoller,
+    //браузер не проходит AUTH - действует правило default файла прав доступа
+    entitlements: Option<Arc<Entitlements>>,
+}
+
+impl Default for WsSession {
+    fn default() -> Self {
+        Self {
+            subscriptions: Vec::new(),
+            streaming: false,
+            //браузеру стакан отправляется только снимками
+            poller: MarketPoller::new(false),
+            entitlements: None,
+        }
+    }
+}
+
+fn response(status: &str, message: &str) -> String {
+    format!(
+        "{{\"type\": \"response\", \"status\": \"{}\", \"message\": \"{}\"}}",
+        status,
+        message.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+fn entitled_response(message: &str, denied: &[String]) -> String {
+    //ответ с тикерами, исключенными из подписки по правам доступа
+    if denied.is_empty() {
+        return response("ok", message);
+    }
+    format!(
+        "{{\"type\": \"response\", \"status\": \"ok\", \"message\": \"{}\", \"denied\": {}}}",
+        message,
+        serde_json::to_string(denied).unwrap_or("[]".to_string())
+    )
+}
+
+fn parse_tickers(command: &Value) -> Vec<TickerSubscription> {
+    //тикеры строкой `"AAPL,MSFT:trades"` или массивом `["AAPL", "MSFT:trades"]`
+    match command.get("tickers") {
+        Some(Value::String(tickers)) => TickerSubscription::parse_list(tickers),
+        Some(Value::Array(tickers)) => tickers
+            .iter()
+            .filter_map(|ticker| ticker.as_str())
+            .filter_map(TickerSubscription::from_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl WsSession {
+    pub(crate) fn new(entitlements: Option<Arc<Entitlements>>) -> Self {
+        Self {
+            entitlements,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn handle_command(&mut self, text: &str) -> String {
+        //{"command": "STREAM" | "SUBSCRIBE" | "STOP", "tickers": ...}
+        let Ok(command) = serde_json::from_str::<Value>(text) else {
+            return response("error", "bad json");
+        };
+        let name = command
+            .get("command")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+            .to_uppercase();
+        match name.as_str() {
+            "STREAM" | "SUBSCRIBE" => {
+                let subscriptions = parse_tickers(&command);
+                if subscriptions.is_empty() {
+                    return response("error", "no tickers");
+                }
+                let (subscriptions, denied) = split_entitled(self.entitlements.as_deref(), None, subscriptions);
+                if subscriptions.is_empty() {
+                    return response("error", &format!("not entitled {}", denied.join(",")));
+                }
+                //STREAM заменяет подписку, SUBSCRIBE добавляет тикеры к текущей
+                if name == "STREAM" {
+                    self.subscriptions.clear();
+                }
+                for subscription in subscriptions {
+                    self.subscriptions
+                        .retain(|current| current.ticker != subscription.ticker);
+                    self.subscriptions.push(subscription);
+                }
+                self.streaming = true;
+                entitled_response(&name, &denied)
+            }
+            "STOP" => {
+                self.streaming = false;
+                response("ok", "STOP")
+            }
+            _ => response("error", "unknown command"),
+        }
+    }
+
+    pub(crate) fn poll(&mut self, market: &MarketState) -> Vec<String> {
+        if !self.streaming {
+            return Vec::new();
+        }
+        self.poller
+            .poll(market, &self.subscriptions)
+            .iter()
+            .filter_map(|message| message.to_json().ok())
+            .collect()
+    }
+}
+
+fn handle_ws_client(
+    socket: &mut WebSocket<TcpStream>,
+    market: &MarketState,
+    entitlements: Option<Arc<Entitlements>>,
+) -> Result<(), QuoteStreamServerError> {
+    let mut session = WsSession::new(entitlements);
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let answer = session.handle_command(text.as_str());
+                socket.send(Message::text(answer))?;
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            //таймаут чтения - отправка новых котировок
+            Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => break,
+            Err(e) => return Err(e.into()),
+        }
+        for json in session.poll(market) {
+            socket.send(Message::text(json))?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn run_ws_gateway(
+    ws_bind: &str,
+    market: MarketState,
+    entitlements: Option<Arc<Entitlements>>,
+) -> Result<(), QuoteStreamServerError> {
+    //шлюз WebSocket для браузеров: команды и котировки в формате json
+    let listener = TcpListener::bind(ws_bind)?;
+    log::info!("websocket gateway listening on: {}", ws_bind);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let market = market.clone();
+        let entitlements = entitlements.clone();
+        thread::spawn(move || {
+            let mut socket = match tungstenite::accept(stream) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    log::error!("websocket handshake error: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = socket
+                .get_ref()
+                .set_read_timeout(Some(Duration::from_millis(WS_POLL_PERIOD_MILLISECOND)))
+            {
+                log::error!("websocket set timeout error: {}", e);
+                return;
+            }
+            if let Err(e) = handle_ws_client(&mut socket, &market, entitlements) {
+                log::error!("websocket client error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quote_lib::quote::marketmessage::MarketMessage;
+
+    #[test]
+    fn test_handle_command() {
+        let mut session = WsSession::default();
+        assert!(session.handle_command("{\"command\": \"STREAM\"}").contains("\"error\""));
+        assert!(session.handle_command("STREAM AAPL").contains("bad json"));
+        let answer = session.handle_command("{\"command\": \"stream\", \"tickers\": \"A:trades\"}");
+        assert!(answer.contains("\"ok\""));
+        session.handle_command("{\"command\": \"SUBSCRIBE\", \"tickers\": [\"B\", \"A\"]}");
+        assert_eq!(session.subscriptions.len(), 2);
+        assert!(session.subscriptions.iter().all(|subscription| subscription.quotes));
+    }
+
+    #[test]
+    fn test_entitlements() {
+        let mut reader = std::io::Cursor::new("default A*\n");
+        let entitlements = Entitlements::from_reader(&mut reader).unwrap();
+        let mut session = WsSession::new(Some(Arc::new(entitlements)));
+        let answer = session.handle_command("{\"command\": \"STREAM\", \"tickers\": [\"AAPL\", \"MSFT\"]}");
+        assert!(answer.ends_with("\"message\": \"STREAM\", \"denied\": [\"MSFT\"]}"));
+        assert_eq!(session.subscriptions.len(), 1);
+        let answer = session.handle_command("{\"command\": \"SUBSCRIBE\", \"tickers\": \"MSFT\"}");
+        assert!(answer.contains("not entitled MSFT"));
+        assert_eq!(session.subscriptions.len(), 1);
+    }
+
+    #[test]
+    fn test_poll_json() {
+        let market = MarketState::default();
+        market.update(&MarketMessage::from_string("T|A|1|10|100|R|1000").unwrap());
+        let mut session = WsSession::default();
+        assert!(session.poll(&market).is_empty());
+        session.handle_command("{\"command\": \"STREAM\", \"tickers\": [\"A\"]}");
+        let frames = session.poll(&market);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].starts_with("{\"type\": \"trade\""));
+        session.handle_command("{\"command\": \"STOP\"}");
+        market.update(&MarketMessage::from_string("T|A|2|11|100|R|2000").unwrap());
+        assert!(session.poll(&market).is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::time::Duration;
+
+//задержка первой попытки переподключения и предел задержки, мс
+pub(crate) const RECONNECT_DELAY_MILLISECOND: u64 = 500;
+pub(crate) const RECONNECT_MAX_DELAY_MILLISECOND: u64 = 30_000;
+
+//параметры переподключения клиента, max_attempts None - без ограничения
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ReconnectPolicy {
+    pub(crate) delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(RECONNECT_DELAY_MILLISECOND),
+            max_delay: Duration::from_millis(RECONNECT_MAX_DELAY_MILLISECOND),
+            max_attempts: None,
+        }
+    }
+}
+
+//экспоненциальная задержка со случайным разбросом: клиенты перезапущенного сервера
+//не подключаются одновременно
+#[derive(Debug)]
+pub(crate) struct Backoff {
+    policy: ReconnectPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub(crate) fn new(policy: ReconnectPolicy) -> Self {
+        Self { policy, attempt: 0 }
+    }
+
+    //задержка перед следующей попыткой, None - попытки исчерпаны
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        if self.policy.max_attempts.is_some_and(|max_attempts| self.attempt >= max_attempts) {
+            return None;
+        }
+        let base = self
+            .policy
+            .delay
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(self.policy.max_delay);
+        self.attempt += 1;
+        //половина задержки постоянна, вторая половина случайна
+        let half = base / 2;
+        Some(half + half.mul_f64(rand::random::<f64>()))
+    }
+
+    pub(crate) fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    //соединение восстановлено - следующий разрыв снова с начальной задержкой
+    pub(crate) fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+//серверы в порядке перехода: основной, затем резервные
+#[derive(Debug)]
+pub(crate) struct ServerList {
+    servers: Vec<String>,
+    current: usize,
+    //серверы круга, к которым подключиться не удалось
+    failed: usize,
+}
+
+impl ServerList {
+    pub(crate) fn new(first: &str, failover: &[String]) -> Self {
+        let mut servers = vec![first.to_string()];
+        servers.extend(failover.iter().filter(|server| *server != first).cloned());
+        Self { servers, current: 0, failed: 0 }
+    }
+
+    pub(crate) fn current(&self) -> &str {
+        &self.servers[self.current]
+    }
+
+    //переход к следующему серверу после разрыва или ошибки подключения,
+    //true - недоступны все серверы круга, перед следующей попыткой нужна задержка
+    pub(crate) fn fail(&mut self) -> bool {
+        self.current = (self.current + 1) % self.servers.len();
+        self.failed += 1;
+        if self.failed < self.servers.len() {
+            return false;
+        }
+        self.failed = 0;
+        true
+    }
+
+    pub(crate) fn connected(&mut self) {
+        self.failed = 0;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.servers.len()
+    }
+}
+
+//состояние соединения клиента с сервером котировок
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ConnectionState {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    Reconnecting,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Reconnecting => "reconnecting",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(ReconnectPolicy {
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            max_attempts: Some(6),
+        });
+        //попытка n: задержка между половиной и полным значением min(100 * 2^n, 1000)
+        for base in [100, 200, 400, 800, 1000, 1000] {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay >= Duration::from_millis(base / 2) && delay <= Duration::from_millis(base));
+        }
+        assert_eq!(backoff.attempt(), 6);
+        assert!(backoff.next_delay().is_none());
+        backoff.reset();
+        assert!(backoff.next_delay().unwrap() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_server_list() {
+        let failover = vec!["b:1".to_string(), "a:1".to_string(), "c:1".to_string()];
+        let mut servers = ServerList::new("a:1", &failover);
+        assert_eq!(servers.len(), 3);
+        assert_eq!(servers.current(), "a:1");
+        //основной недоступен - резервные без задержки, задержка после круга
+        assert!(!servers.fail());
+        assert_eq!(servers.current(), "b:1");
+        assert!(!servers.fail());
+        assert!(servers.fail());
+        assert_eq!(servers.current(), "a:1");
+        //разрыв после успешного подключения - снова новый круг
+        assert!(!servers.fail());
+        servers.connected();
+        assert!(!servers.fail());
+        assert_eq!(servers.current(), "c:1");
+        let mut single = ServerList::new("a:1", &[]);
+        assert!(single.fail());
+        assert_eq!(single.current(), "a:1");
+    }
+
+    #[test]
+    fn test_backoff_unlimited() {
+        let mut backoff = Backoff::new(ReconnectPolicy::default());
+        for _ in 0..100 {
+            assert!(backoff.next_delay().unwrap() <= Duration::from_millis(RECONNECT_MAX_DELAY_MILLISECOND));
+        }
+        let mut disabled = Backoff::new(ReconnectPolicy { max_attempts: Some(0), ..ReconnectPolicy::default() });
+        assert!(disabled.next_delay().is_none());
+    }
+}
@@ -1,27 +1,110 @@
 
-    use std::io::{BufRead, Read};
+    use std::io::{BufRead, ErrorKind, Read};
     use std::io::BufReader;
     use std::io::Write;
-    use std::net::{TcpListener, TcpStream, UdpSocket};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
     use std::str::SplitWhitespace;
     use std::sync::{Arc, Mutex};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering::{Relaxed, SeqCst};
     use crossbeam_channel::{bounded, Receiver};
     use std::thread;
     use std::thread::JoinHandle;
+    use std::time::Duration;
+    use quote_lib::quote::marketmessage::MarketMessage;
     use quote_lib::quote::stockquote::StockQuote;
+    use quote_lib::quote::subscription::TickerSubscription;
     use crate::error::servererror::QuoteStreamServerError;
-    use crate::quote::volume_generator::{QuoteGenerator};
-    use crate::quote::quote_stream::{QuoteStream, QuoteStreamResult};
+    use crate::quote::volume_generator::{GeneratorTuning, QuoteGenerator};
+    use crate::quote::quote_stream::{QuoteStream, QuoteStreamResult, StreamTuning, SubscribedTicker};
+    use crate::quote::market_state::MarketState;
+    use crate::quote::paper_trading::PaperAccount;
+    use crate::quote::server_alerts::ServerAlerts;
+    use crate::quote::ws_gateway::run_ws_gateway;
+    use crate::quote::sessions::SessionRegistry;
+    use crate::quote::http_api::HttpApi;
+    use crate::quote::server_metrics::ServerMetrics;
+    use crate::quote::admin::AdminServer;
+    use crate::quote::auth::{Authenticator, Credentials};
+    use crate::quote::udp_target::{resolve_target, UdpChallenge};
+    use crate::quote::entitlements::Entitlements;
+    use crate::quote::universe::{TickerReloader, TickerUniverse};
+    use crate::quote::limits::{ConnectionLimiter, RateLimiter, ServerLimits, SessionLimits};
+    use crate::metrics::run_metrics_server;
+    use crate::multicast::{MulticastGroup, MulticastOptions};
+    use crate::netaddr;
+    use crate::tls::ControlStream;
+    use crate::udp_seal::{GroupKey, KeyExchange, UdpSealer};
+    use rustls::ServerConfig;
     use log;
 
+    //пауза опроса приема соединений и флага завершения, мс
+    const ACCEPT_POLL_MILLISECOND: u64 = 100;
+
     #[derive(Default)]
     pub(crate) struct QuoteServer {
         thread: Option<JoinHandle<Result<QuoteStreamResult, QuoteStreamServerError>>>,
         thread_state: Option<Arc<Mutex<QuoteServerThreadState>>>,
-        subscribe_tickers: Arc<Mutex<Vec<StockQuote>>>,
+        subscribe_tickers: Arc<Mutex<Vec<SubscribedTicker>>>,
+        market: MarketState,
+        account: Arc<Mutex<PaperAccount>>,
+        matching_state: SessionThread,
+        alerts: Arc<Mutex<ServerAlerts>>,
+        alerts_state: SessionThread,
+        //группы многоадресной рассылки, пусто - рассылка каждому клиенту
+        multicast_groups: Vec<MulticastGroup>,
+        //ключ датаграмм групп, выдается клиенту в ответе OK Multicast
+        group_key: Option<Arc<GroupKey>>,
+        sessions: SessionRegistry,
+        session_id: u64,
+        metrics: Arc<ServerMetrics>,
+        //проверка AUTH, None - сервер без учетных данных, аутентификация не требуется
+        auth: Option<Arc<Authenticator>>,
+        //имя токена или пользователя после успешной AUTH
+        identity: Option<String>,
+        //поток UDP на любой адрес, иначе только на IP адрес управляющего соединения
+        allow_any_udp_target: bool,
+        //ожидающая VERIFY проверка адреса UDP и последний подтвержденный адрес
+        challenge: Option<UdpChallenge>,
+        verified_target: Option<SocketAddr>,
+        //ключ датаграмм сессии после KEY, без него поток UDP не запускается
+        udp_sealer: Option<Arc<UdpSealer>>,
+        //разрешенные тикеры по результату AUTH, None - все тикеры
+        entitlements: Option<Arc<Entitlements>>,
+        limits: SessionLimits,
+        stream_tuning: StreamTuning,
     }
 
 
+    //параметры сервера помимо адресов TCP/UDP
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct ServerOptions {
+        pub(crate) book_depth: usize,
+        pub(crate) multicast_groups: Vec<MulticastGroup>,
+        pub(crate) multicast_options: MulticastOptions,
+        pub(crate) ws_bind: Option<String>,
+        pub(crate) http_bind: Option<String>,
+        pub(crate) metrics_bind: Option<String>,
+        pub(crate) admin_bind: Option<String>,
+        //порт администратора не на localhost
+        pub(crate) admin_allow_remote: bool,
+        pub(crate) credentials: Option<Credentials>,
+        pub(crate) allow_any_udp_target: bool,
+        //TLS управляющего соединения, None - открытый TCP
+        pub(crate) tls: Option<Arc<ServerConfig>>,
+        pub(crate) entitlements: Option<Entitlements>,
+        pub(crate) limits: ServerLimits,
+        //флаг завершения сервера по сигналу
+        pub(crate) shutdown: Arc<AtomicBool>,
+        //файл тикеров для перезагрузки, флаг SIGHUP и отслеживание изменения файла
+        pub(crate) tickers_file: Option<String>,
+        pub(crate) reload: Arc<AtomicBool>,
+        pub(crate) watch_tickers: bool,
+        //параметры потока UDP и генератора из файла настроек
+        pub(crate) stream_tuning: StreamTuning,
+        pub(crate) generator_tuning: GeneratorTuning,
+    }
+
     #[derive(Clone, Copy, Debug, PartialEq)]
     pub(crate) enum QuoteServerThreadState {
         Running,
@@ -30,7 +113,154 @@
     }
 
 
+    //состояние потока сессии, запускаемого по первой команде
+    //при удалении сервера сессии поток останавливается на любом выходе из обработчика, в том числе при панике
+    #[derive(Default)]
+    struct SessionThread(Option<Arc<Mutex<QuoteServerThreadState>>>);
+
+    impl SessionThread {
+        fn stop(&self) {
+            if let Some(thread_state) = &self.0 &&
+                let Ok(mut state) = thread_state.lock() {
+                *state = QuoteServerThreadState::Cancelled;
+            }
+        }
+    }
+
+    impl Drop for SessionThread {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+
     impl QuoteServer {
+        #[allow(clippy::too_many_arguments)]
+        fn new(market: MarketState, multicast_groups: Vec<MulticastGroup>, group_key: Option<Arc<GroupKey>>,
+               sessions: SessionRegistry, metrics: Arc<ServerMetrics>, auth: Option<Arc<Authenticator>>,
+               allow_any_udp_target: bool, entitlements: Option<Arc<Entitlements>>, limits: SessionLimits,
+               stream_tuning: StreamTuning) -> Self {
+            Self { market, multicast_groups, group_key, sessions, metrics, auth, allow_any_udp_target, entitlements,
+                limits, stream_tuning, ..Default::default() }
+        }
+
+        fn authenticate(&mut self, cmd: SplitWhitespace, ip: IpAddr) -> String {
+            //AUTH <token> | AUTH <user> <password>
+            let Some(auth) = &self.auth else {
+                return "OK Auth\n".to_string()
+            };
+            let args: Vec<&str> = cmd.collect();
+            match auth.authenticate(ip, &args) {
+                Ok(identity) => {
+                    self.identity = Some(identity);
+                    "OK Auth\n".to_string()
+                }
+                Err(e) => format!("Error auth: {}\n", e),
+            }
+        }
+
+        fn exchange_key(&mut self, mut cmd: SplitWhitespace) -> String {
+            //KEY <открытый ключ X25519 клиента, hex> - ответ открытым ключом сервера
+            let Some(client_public) = cmd.next() else {
+                return "Error command key\n".to_string()
+            };
+            let key = KeyExchange::new().and_then(|exchange| {
+                let server_public = exchange.public_hex();
+                exchange.derive(client_public, true).map(|key| (key, server_public))
+            });
+            match key {
+                Ok((key, server_public)) => {
+                    self.udp_sealer = Some(Arc::new(UdpSealer::new(key)));
+                    format!("OK Key {}\n", server_public)
+                }
+                Err(e) => format!("Error key: {}\n", e),
+            }
+        }
+
+        fn entitled_tickers(&self, tickers: &str) -> Result<(String, String), String> {
+            //тикеры команды, разрешенные сессии, и строка DENIED с запрещенными
+            let Some(entitlements) = &self.entitlements else {
+                return Ok((tickers.to_string(), String::new()))
+            };
+            //некорректные элементы остаются в списке и пропускаются при разборе подписки
+            let (allowed, denied): (Vec<&str>, Vec<&str>) = tickers.split(',')
+                .partition(|item| TickerSubscription::from_string(item)
+                    .is_none_or(|subscription| entitlements.allows(self.identity.as_deref(), &subscription.ticker)));
+            let denied: Vec<String> = denied.iter()
+                .filter_map(|item| TickerSubscription::from_string(item))
+                .map(|subscription| subscription.ticker)
+                .collect();
+            if denied.is_empty() {
+                return Ok((allowed.join(","), String::new()))
+            }
+            log::warn!("session {} ({}): tickers not entitled {}", self.session_id,
+                       self.identity.as_deref().unwrap_or("no auth"), denied.join(","));
+            if TickerSubscription::parse_list(&allowed.join(",")).is_empty() {
+                return Err(format!("Error stream: not entitled {}\n", denied.join(",")))
+            }
+            Ok((allowed.join(","), format!("DENIED|{}\n", denied.join(","))))
+        }
+
+        fn stream_command(&mut self, mut cmd: SplitWhitespace, udp_socket: UdpSocket,
+                          writer: Arc<Mutex<ControlStream>>, receiver: Receiver<MarketMessage>,
+                          peer_ip: IpAddr) -> String {
+            //STREAM tcp | udp://<addr> <tickers>: тикеры без прав исключаются, ответу предшествует DENIED
+            let (Some(target), Some(tickers)) = (cmd.next(), cmd.next()) else {
+                return "Error command stream\n".to_string()
+            };
+            let (tickers, denied) = match self.entitled_tickers(tickers) {
+                Ok(entitled) => entitled,
+                Err(e) => return e,
+            };
+            if let Some(max_tickers) = self.limits.max_tickers &&
+                TickerSubscription::parse_list(&tickers).len() > max_tickers {
+                return format!("Error limit: too many tickers (max {})\n", max_tickers)
+            }
+            let command = format!("{} {}", target, tickers);
+            let response = if target == "tcp" {
+                log::info!("start stream tcp");
+                self.stop_quote_stream();
+                self.start_quote_stream_tcp(command.split_whitespace(), writer)
+            } else if !self.multicast_groups.is_empty() {
+                self.multicast_stream(command.split_whitespace())
+            } else {
+                log::info!("start stream");
+                self.request_quote_stream(udp_socket, command.split_whitespace(), receiver, peer_ip)
+            };
+            format!("{}{}", denied, response)
+        }
+
+        fn subscription_tickers(subscriptions: &[TickerSubscription]) -> Vec<String> {
+            subscriptions.iter().map(|subscription| subscription.ticker.clone()).collect()
+        }
+
+        fn multicast_stream(&self, mut cmd: SplitWhitespace) -> String {
+            //в режиме многоадресной рассылки клиент получает группы, публикующие его тикеры
+            let Some((_, tickers)) = QuoteServer::parse_cmd_stream(&mut cmd) else {
+                return "Error command stream\n".to_string()
+            };
+            let subscriptions = TickerSubscription::parse_list(&tickers);
+            if subscriptions.is_empty() {
+                return "Error command stream\n".to_string()
+            }
+            //ключ групп выдается запечатанным ключом сессии - без KEY рассылка не открывается
+            let (Some(sealer), Some(group_key)) = (&self.udp_sealer, &self.group_key) else {
+                return "Error stream: key required\n".to_string()
+            };
+            let mut groups: Vec<String> = Vec::new();
+            for subscription in subscriptions.iter() {
+                let Some(group) = self.multicast_groups.iter()
+                    .find(|group| group.publishes(&subscription.ticker)) else {
+                    return format!("Error multicast: ticker {} not published\n", subscription.ticker)
+                };
+                if !groups.contains(&group.addr.to_string()) {
+                    groups.push(group.addr.to_string());
+                }
+            }
+            self.sessions.start_stream(self.session_id, "multicast", &groups.join(" "),
+                                       QuoteServer::subscription_tickers(&subscriptions), None);
+            format!("OK Multicast {} key={}\n", groups.join(" "), group_key.grant(sealer))
+        }
+
         fn parse_cmd_stream(split_whitespace: &mut SplitWhitespace) -> Option<(String, String)> {
             if let Some(client_adr) = split_whitespace.next() && let Some(tickers) =
                 split_whitespace.next() {
@@ -39,32 +269,136 @@
             None
         }
 
-        fn start_quote_stream(&mut self, udp_socket: UdpSocket,
-                              mut cmd: SplitWhitespace,
-                              receiver: Receiver<StockQuote>) -> String {
-            if let Some((client_adr, tickers)) = QuoteServer::parse_cmd_stream(&mut cmd)
-            {
-                let thread_state_stream = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
-                if let Ok(mut tickers_subscribe_lock) = self.subscribe_tickers.lock() {
-                    tickers_subscribe_lock.clear();
-                    *tickers_subscribe_lock = StockQuote::get_tickers_subscribe(&tickers);
-                } else {
-                    return "Error store subscribe tickers\n".to_string()
+        fn request_quote_stream(&mut self, udp_socket: UdpSocket, mut cmd: SplitWhitespace,
+                                receiver: Receiver<MarketMessage>, peer_ip: IpAddr) -> String {
+            //STREAM udp://<addr> <tickers>: поток запускается после подтверждения адреса командой VERIFY
+            let Some((client_adr, tickers)) = QuoteServer::parse_cmd_stream(&mut cmd) else {
+                return "Error command stream\n".to_string()
+            };
+            if TickerSubscription::parse_list(&tickers).is_empty() {
+                return "Error command stream\n".to_string()
+            }
+            if self.udp_sealer.is_none() {
+                return "Error stream: key required\n".to_string()
+            }
+            let target = match resolve_target(&client_adr, peer_ip, self.allow_any_udp_target) {
+                //клиент IPv4 на сокете `[::]` получает поток по адресу ::ffff:a.b.c.d
+                Ok(target) => match udp_socket.local_addr() {
+                    Ok(local) => netaddr::for_socket(target, &local),
+                    Err(_) => target,
+                },
+                Err(e) => {
+                    log::warn!("stream from {} rejected: {}", peer_ip, e);
+                    return format!("Error stream: {}\n", e)
                 }
-                let subscribe_tickers = self.subscribe_tickers.clone();
-                self.thread_state = Some(thread_state_stream.clone());
-                self.thread = Some(thread::spawn(move || {
-                    return QuoteStream::thread_stream(
-                        udp_socket,
-                        &client_adr,
-                        receiver,
-                        subscribe_tickers,
-                        thread_state_stream
-                    )
-                }));
-                "OK Stream\n".to_string()
-            } else { "Error command stream\n".to_string() }
+            };
+            //адрес подтвержден в этой сессии - RESTREAM без повторной проверки
+            if self.verified_target == Some(target) {
+                self.stop_quote_stream();
+                return self.start_quote_stream(udp_socket, target, &tickers, receiver)
+            }
+            let challenge = UdpChallenge::new(target, tickers);
+            if let Err(e) = challenge.send(&udp_socket) {
+                return format!("Error stream: challenge {}\n", e)
+            }
+            self.challenge = Some(challenge);
+            "OK Challenge\n".to_string()
+        }
+
+        fn verify_quote_stream(&mut self, udp_socket: UdpSocket, mut cmd: SplitWhitespace,
+                               receiver: Receiver<MarketMessage>) -> String {
+            //VERIFY <nonce> - nonce из датаграммы CHALLENGE
+            let Some(challenge) = self.challenge.take() else {
+                return "Error verify: no challenge\n".to_string()
+            };
+            if let Err(e) = challenge.verify(cmd.next().unwrap_or_default()) {
+                log::warn!("verify {} failed: {}", challenge.target, e);
+                return format!("Error verify: {}\n", e)
+            }
+            self.verified_target = Some(challenge.target);
+            self.stop_quote_stream();
+            self.start_quote_stream(udp_socket, challenge.target, &challenge.tickers, receiver)
+        }
+
+        fn start_quote_stream(&mut self, udp_socket: UdpSocket, target: SocketAddr, tickers: &str,
+                              receiver: Receiver<MarketMessage>) -> String {
+            let client_adr = target.to_string();
+            let subscriptions = TickerSubscription::parse_list(tickers);
+            if subscriptions.is_empty() {
+                return "Error command stream\n".to_string()
+            }
+            let Some(sealer) = self.udp_sealer.clone() else {
+                return "Error stream: key required\n".to_string()
+            };
+            let thread_state_stream = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
+            let generation = self.sessions.start_stream(self.session_id, "udp", &client_adr,
+                                                        QuoteServer::subscription_tickers(&subscriptions),
+                                                        Some(thread_state_stream.clone()));
+            if let Ok(mut tickers_subscribe_lock) = self.subscribe_tickers.lock() {
+                tickers_subscribe_lock.clear();
+                *tickers_subscribe_lock = subscriptions.into_iter()
+                    .map(SubscribedTicker::new).collect();
+            } else {
+                return "Error store subscribe tickers\n".to_string()
+            }
+            let subscribe_tickers = self.subscribe_tickers.clone();
+            self.thread_state = Some(thread_state_stream.clone());
+            let (sessions, session_id) = (self.sessions.clone(), self.session_id);
+            let (counters, metrics) = (self.sessions.counters(self.session_id), self.metrics.clone());
+            let (budget, tuning) = (self.limits.datagram_budget.clone(), self.stream_tuning);
+            self.thread = Some(thread::spawn(move || {
+                let result = QuoteStream::thread_stream(
+                    udp_socket,
+                    &client_adr,
+                    receiver,
+                    subscribe_tickers,
+                    thread_state_stream,
+                    counters,
+                    metrics,
+                    sealer,
+                    budget,
+                    tuning
+                );
+                sessions.stream_stopped(session_id, generation);
+                result
+            }));
+            "OK Stream\n".to_string()
+        }
+        fn start_quote_stream_tcp(&mut self, mut cmd: SplitWhitespace,
+                                  writer: Arc<Mutex<ControlStream>>) -> String {
+            //STREAM tcp <tickers> - котировки передаются по управляющему соединению
+            let Some((_, tickers)) = QuoteServer::parse_cmd_stream(&mut cmd) else {
+                return "Error command stream\n".to_string()
+            };
+            let subscriptions = TickerSubscription::parse_list(&tickers);
+            if subscriptions.is_empty() {
+                return "Error command stream\n".to_string()
+            }
+            let thread_state_stream = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
+            self.thread_state = Some(thread_state_stream.clone());
+            let market = self.market.clone();
+            let generation = self.sessions.start_stream(self.session_id, "tcp", "control",
+                                                        QuoteServer::subscription_tickers(&subscriptions),
+                                                        Some(thread_state_stream.clone()));
+            let (sessions, session_id) = (self.sessions.clone(), self.session_id);
+            let counters = self.sessions.counters(self.session_id);
+            self.thread = Some(thread::spawn(move || {
+                let result = QuoteStream::thread_stream_tcp(writer, market, subscriptions, thread_state_stream,
+                                                            counters);
+                sessions.stream_stopped(session_id, generation);
+                result
+            }));
+            "OK Stream tcp\n".to_string()
+        }
+
+        fn join_quote_stream(&mut self) {
+            //ожидание завершения потока котировок сессии
+            if let Some(thread) = self.thread.take()
+                && let Err(e) = thread.join() {
+                log::error!("stream thread panic: {:?}", e);
+            }
         }
+
         fn stop_quote_stream(&mut self) {
             if let Some(thread_state) = &self.thread_state &&
                 let Ok(mut state) = thread_state.lock() {
@@ -72,36 +406,151 @@
             }
         }
 
-        fn handle_client(&mut self, udp_socket: UdpSocket, stream: TcpStream, receiver: Receiver<StockQuote>) {
+        fn place_order(&mut self, mut cmd: SplitWhitespace, writer: Arc<Mutex<ControlStream>>) -> String {
+            let Some((side, ticker, quantity, order_type)) = PaperAccount::parse_order(&mut cmd) else {
+                return "Error command order\n".to_string()
+            };
+            let result = match self.account.lock() {
+                Ok(mut account) => account.place_order(side, &ticker, quantity, order_type, &self.market),
+                Err(_) => return "Error store order\n".to_string()
+            };
+            match result {
+                Ok((order_id, reports)) => {
+                    //поток исполнения лимитных заявок запускается с первой заявкой сессии
+                    if self.matching_state.0.is_none() {
+                        let thread_state_matching = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
+                        self.matching_state.0 = Some(thread_state_matching.clone());
+                        let account = self.account.clone();
+                        let market = self.market.clone();
+                        thread::spawn(move || {
+                            PaperAccount::thread_matching(account, market, writer, thread_state_matching)
+                        });
+                    }
+                    let mut response = format!("OK Order {}\n", order_id);
+                    reports.iter().for_each(|report| response += &format!("{}\n", report));
+                    response
+                }
+                Err(e) => format!("Error order: {}\n", e)
+            }
+        }
+
+        fn cancel_order(&mut self, mut cmd: SplitWhitespace) -> String {
+            let Some(order_id) = cmd.next().and_then(|id| id.parse::<u64>().ok()) else {
+                return "Error command cancel\n".to_string()
+            };
+            match self.account.lock().ok().and_then(|mut account| account.cancel_order(order_id)) {
+                Some(report) => format!("OK Cancel {}\n{}\n", order_id, report),
+                None => format!("Error cancel: order {} not found\n", order_id)
+            }
+        }
+
+        fn stop_matching(&mut self) {
+            self.matching_state.stop();
+        }
+
+        fn add_alert(&mut self, mut cmd: SplitWhitespace, writer: Arc<Mutex<ControlStream>>) -> String {
+            let Some((ticker, condition)) = ServerAlerts::parse_alert(&mut cmd) else {
+                return "Error command alert\n".to_string()
+            };
+            let result = match self.alerts.lock() {
+                Ok(mut alerts) => alerts.add_alert(&ticker, condition, &self.market),
+                Err(_) => return "Error store alert\n".to_string()
+            };
+            match result {
+                Ok(alert_id) => {
+                    //поток проверки оповещений запускается с первым оповещением сессии
+                    if self.alerts_state.0.is_none() {
+                        let thread_state_alerts = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
+                        self.alerts_state.0 = Some(thread_state_alerts.clone());
+                        let alerts = self.alerts.clone();
+                        let market = self.market.clone();
+                        thread::spawn(move || {
+                            ServerAlerts::thread_alerts(alerts, market, writer, thread_state_alerts)
+                        });
+                    }
+                    format!("OK Alert {}\n", alert_id)
+                }
+                Err(e) => format!("Error alert: {}\n", e)
+            }
+        }
+
+        fn remove_alert(&mut self, mut cmd: SplitWhitespace) -> String {
+            let Some(alert_id) = cmd.next().and_then(|id| id.parse::<u64>().ok()) else {
+                return "Error command unalert\n".to_string()
+            };
+            if self.alerts.lock().is_ok_and(|mut alerts| alerts.remove_alert(alert_id)) {
+                format!("OK Unalert {}\n", alert_id)
+            } else {
+                format!("Error unalert: alert {} not found\n", alert_id)
+            }
+        }
+
+        fn stop_alerts(&mut self) {
+            self.alerts_state.stop();
+        }
+
+        fn handle_client(&mut self, udp_socket: UdpSocket, stream: ControlStream, receiver: Receiver<MarketMessage>) {
             // поток для ответ хапрос клиента
             // клонируем stream: один экземпляр для чтения (обёрнут в BufReader), другой — для записи
-            let mut writer = stream.try_clone().expect("failed to clone stream tcp");
+            // запись общая с потоком исполнения заявок
+            let writer = Arc::new(Mutex::new(stream.try_clone().expect("failed to clone stream tcp")));
+            let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+            let peer_ip = stream.peer_addr().map(|addr| addr.ip()).unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+            self.session_id = self.sessions.register(&peer);
+            if let Ok(control) = stream.tcp_stream().try_clone() {
+                self.sessions.attach_control(self.session_id, control, writer.clone());
+            }
             let mut reader = BufReader::new(stream);
             // send initial prompt
-            let _ = writer.write_all(b"Welcome to quotation stream!\n");
-            let _ = writer.flush();
+            if let Ok(mut writer) = writer.lock() {
+                let _ = writer.write_all(b"Welcome to quotation stream!\n");
+                let _ = writer.flush();
+            }
+            //ограничение команд в секунду на соединение
+            let command_rate = self.limits.max_commands_per_second.map(RateLimiter::new);
             let mut line = String::new();
             loop {
                 line.clear();
                 match reader.read_line(&mut line) {
                     Ok(0) => {
-                        //соединие закрыто
+                        //соединие закрыто сервером при завершении - потоки сессии останавливаются
+                        if self.sessions.is_closing() {
+                            break;
+                        }
+                        //соединие закрыто, поток котировок продолжается до таймаута PING,
+                        //отчетам заявок и оповещениям некуда отправляться - их потоки останавливаются
+                        self.stop_matching();
+                        self.stop_alerts();
+                        self.sessions.disconnect(self.session_id);
+                        self.join_quote_stream();
                         return;
                     }
                     Ok(_) => {
                         //обработка комманды
                         let input = line.trim();
                         if input.is_empty() {
-                            let _ = writer.flush();
                             continue;
                         }
                         let mut parts = input.split_whitespace();
-                        let response = match parts.next() {
+                        let command = parts.next();
+                        //при заданных учетных данных команды, кроме AUTH, принимаются только после AUTH
+                        let response = match command {
+                            _ if command_rate.as_ref().is_some_and(|rate| !rate.try_acquire()) => {
+                                format!("Error limit: too many commands (max {} per second)\n",
+                                        self.limits.max_commands_per_second.unwrap_or_default())
+                            }
+                            Some("AUTH") => self.authenticate(parts, peer_ip),
+                            _ if self.auth.is_some() && self.identity.is_none() => {
+                                "Error auth required\n".to_string()
+                            }
                             Some("STREAM") | Some("RESTREAM") => {
                                 let udp = udp_socket.try_clone().expect("failed to clone stream udp");
-                                log::info!("start stream");
-                                self.stop_quote_stream();
-                                self.start_quote_stream(udp, parts, receiver.clone())
+                                self.stream_command(parts, udp, writer.clone(), receiver.clone(), peer_ip)
+                            }
+                            Some("KEY") => self.exchange_key(parts),
+                            Some("VERIFY") => {
+                                let udp = udp_socket.try_clone().expect("failed to clone stream udp");
+                                self.verify_quote_stream(udp, parts, receiver.clone())
                             }
                             Some("STOP") => {
                                 if let Some(_) = self.thread_state {
@@ -111,12 +560,22 @@
                                     "Thread not running\n".to_string()
                                 }
                             }
+                            Some("ORDER") => self.place_order(parts, writer.clone()),
+                            Some("CANCEL") => self.cancel_order(parts),
+                            Some("ORDERS") => match self.account.lock() {
+                                Ok(account) => format!("{}OK Orders\n", account.report(&self.market)),
+                                Err(_) => "Error orders\n".to_string()
+                            },
+                            Some("ALERT") => self.add_alert(parts, writer.clone()),
+                            Some("UNALERT") => self.remove_alert(parts),
                             _ => "Error command\n".to_string(),
                         };
 
                         // отправляем ответ и снова показываем prompt
-                        let _ = writer.write_all(response.as_bytes());
-                        let _ = writer.flush();
+                        if let Ok(mut writer) = writer.lock() {
+                            let _ = writer.write_all(response.as_bytes());
+                            let _ = writer.flush();
+                        }
                     }
                     Err(_) => {
                         // ошибка чтения — закрываем
@@ -126,36 +585,179 @@
                 }
             }
             self.stop_quote_stream();
+            self.stop_matching();
+            self.stop_alerts();
+            self.sessions.disconnect(self.session_id);
+            self.join_quote_stream();
         }
 
-        pub fn run_quote_server<R: Read>(r: &mut R, tcp_bind: &str, udp_bind: &str) -> Result<(), QuoteStreamServerError> {
+        pub fn run_quote_server<R: Read>(r: &mut R, tcp_bind: &str, udp_bind: &str,
+                                         options: ServerOptions) -> Result<(), QuoteStreamServerError> {
+            let ServerOptions { book_depth, multicast_groups, multicast_options, ws_bind, http_bind,
+                metrics_bind, admin_bind, admin_allow_remote, credentials, allow_any_udp_target, tls, entitlements, limits, shutdown, tickers_file, reload, watch_tickers,
+                stream_tuning, generator_tuning } = options;
+            let auth = credentials.map(|credentials| Arc::new(Authenticator::new(credentials)));
+            let entitlements = entitlements.map(Arc::new);
+            let session_limits = limits.session_limits();
+            let connection_limiter = Arc::new(ConnectionLimiter::new(&limits));
+            //ключ групп многоадресной рассылки - новый при каждом запуске сервера
+            let group_key = if multicast_groups.is_empty() {
+                None
+            } else {
+                Some(Arc::new(GroupKey::generate().map_err(QuoteStreamServerError::BadNetworkBindSocket)?))
+            };
+            //порт администратора проверяется до запуска сервера
+            if let Some(admin_bind) = &admin_bind {
+                AdminServer::check_bind(admin_bind, admin_allow_remote)?;
+            }
             //запуск сервера котирово - ожидание запроса клиента, и создание потока для обработк запроса
             if let Ok(tickers) = StockQuote::get_tickers(r) {
-                let (sender, receiver) = bounded::<StockQuote>(tickers.len());
+                //на каждый тикер генерируются сделка, котировка, статистика сессии и стакан
+                let (sender, receiver) = bounded::<MarketMessage>(tickers.len() * 4);
+                let market = MarketState::default();
+                let sessions = SessionRegistry::default();
+                let metrics = Arc::new(ServerMetrics::default());
+                let generator_state = Arc::new(Mutex::new(QuoteServerThreadState::Stopped));
+                let universe = TickerUniverse::new(tickers);
+                let reloader = TickerReloader::new(tickers_file, universe.clone(), sessions.clone());
                 //поток генрации котировок (генирирует котировоки и отправляет их в канал)
                 let _ = thread::scope(|s| {
+                    let state = generator_state.clone();
                     s.spawn(|| {
-                        QuoteGenerator::thread_generate(sender, &tickers)
+                        QuoteGenerator::with_tuning(book_depth, generator_tuning)
+                            .thread_generate(sender, &universe, market.clone(), state)
                             .expect("Generator quote run error");
                     });
+                    //перезагрузка файла тикеров по SIGHUP и, если задано, по изменению файла
+                    let watcher = reloader.clone();
+                    thread::spawn(move || watcher.run_watch(reload, watch_tickers));
+                    //поток многоадресной рассылки - единственный получатель сообщений генератора
+                    if let Some(group_key) = group_key.clone() {
+                        let groups = multicast_groups.clone();
+                        let receiver = receiver.clone();
+                        let (budget, metrics) = (session_limits.datagram_budget.clone(), metrics.clone());
+                        s.spawn(move || {
+                            if let Err(e) = QuoteStream::thread_multicast(receiver, groups, multicast_options,
+                                                                          budget, metrics, group_key) {
+                                log::error!("multicast stream error: {}", e);
+                            }
+                        });
+                    }
+                    //шлюз WebSocket для браузеров читает общее состояние рынка
+                    //служебные серверы не ожидаются при завершении - их соединения закрываются с процессом
+                    if let Some(ws_bind) = ws_bind {
+                        let (market, entitlements) = (market.clone(), entitlements.clone());
+                        thread::spawn(move || {
+                            if let Err(e) = run_ws_gateway(&ws_bind, market, entitlements) {
+                                log::error!("websocket gateway error: {}", e);
+                            }
+                        });
+                    }
+                    //HTTP API снимков котировок и сессий
+                    if let Some(http_bind) = http_bind {
+                        let api = HttpApi::new(market.clone(), sessions.clone(), entitlements.clone());
+                        thread::spawn(move || {
+                            if let Err(e) = api.run(&http_bind) {
+                                log::error!("http api error: {}", e);
+                            }
+                        });
+                    }
+                    //метрики Prometheus
+                    if let Some(metrics_bind) = metrics_bind {
+                        let (market, sessions, metrics) = (market.clone(), sessions.clone(), metrics.clone());
+                        thread::spawn(move || {
+                            if let Err(e) = run_metrics_server(&metrics_bind,
+                                                               || metrics.render(&market, &sessions)) {
+                                log::error!("metrics error: {}", e);
+                            }
+                        });
+                    }
+                    //административные команды LIST, STATUS, SESSIONS, KICK
+                    if let Some(admin_bind) = admin_bind {
+                        let admin = AdminServer::new(universe.clone(), reloader.clone(), market.clone(),
+                                                     sessions.clone());
+                        thread::spawn(move || {
+                            if let Err(e) = admin.run(&admin_bind) {
+                                log::error!("admin error: {}", e);
+                            }
+                        });
+                    }
                     //поток ответа от клиента
                     s.spawn(|| {
-                        let listener = TcpListener::bind(tcp_bind)?;
-                        let udp_bind = UdpSocket::bind(udp_bind)?;
+                        let listener = netaddr::listen_tcp(tcp_bind)?;
+                        let udp_bind = netaddr::bind_udp(udp_bind)?;
                         log::info!("{}", format!("server listening on: {}", tcp_bind.to_string()));
-                        for stream in listener.incoming() {
-                            match stream {
-                                Ok(stream) => {
+                        //прием без блокировки - флаг завершения проверяется между соединениями
+                        listener.set_nonblocking(true)?;
+                        let mut handlers: Vec<JoinHandle<()>> = Vec::new();
+                        while !shutdown.load(SeqCst) {
+                            match listener.accept() {
+                                Ok((stream, _)) => {
+                                    stream.set_nonblocking(false)?;
+                                    //ограничение сессий проверяется до создания потока соединения
+                                    let peer_ip = stream.peer_addr().map(|addr| addr.ip())
+                                        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+                                    let guard = match connection_limiter.acquire(peer_ip) {
+                                        Ok(guard) => guard,
+                                        Err(e) => {
+                                            log::warn!("connection from {} rejected: {}", peer_ip, e);
+                                            metrics.connections_rejected.fetch_add(1, Relaxed);
+                                            //клиенту TLS ответ открытым текстом не отправляется
+                                            if tls.is_none() {
+                                                let _ = (&stream).write_all(format!("Error limit: {}\n", e).as_bytes());
+                                            }
+                                            continue;
+                                        }
+                                    };
                                     let value = receiver.clone();
                                     let udb_bind_adr = udp_bind.try_clone()?;
-                                    thread::spawn(move || {
-                                        let mut quote_server = QuoteServer::default();
+                                    let market = market.clone();
+                                    let multicast_groups = multicast_groups.clone();
+                                    let group_key = group_key.clone();
+                                    let sessions = sessions.clone();
+                                    let metrics = metrics.clone();
+                                    let auth = auth.clone();
+                                    let tls = tls.clone();
+                                    let entitlements = entitlements.clone();
+                                    let session_limits = session_limits.clone();
+                                    handlers.retain(|handler| !handler.is_finished());
+                                    handlers.push(thread::spawn(move || {
+                                        let _guard = guard;
+                                        //рукопожатие TLS в потоке клиента, не задерживает прием соединений
+                                        let stream = match &tls {
+                                            Some(config) => match ControlStream::accept(stream, config) {
+                                                Ok(stream) => stream,
+                                                Err(e) => {
+                                                    log::warn!("tls handshake failed: {}", e);
+                                                    return;
+                                                }
+                                            },
+                                            None => ControlStream::Plain(stream),
+                                        };
+                                        let mut quote_server = QuoteServer::new(market, multicast_groups, group_key,
+                                                                                sessions, metrics, auth,
+                                                                                allow_any_udp_target, entitlements,
+                                                                                session_limits, stream_tuning);
                                         quote_server.handle_client(udb_bind_adr, stream, value);
-                                    });
+                                    }));
+                                }
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                                    thread::sleep(Duration::from_millis(ACCEPT_POLL_MILLISECOND));
                                 }
                                 Err(e) => return Err(QuoteStreamServerError::BadCreateTcpStream(e.to_string()))
                             }
                         }
+                        //завершение: новые соединения не принимаются, сессии уведомляются и закрываются,
+                        //обработчики ожидают свои потоки котировок, генератор останавливается последним
+                        drop(listener);
+                        let notified = sessions.shutdown_all("SHUTDOWN\n");
+                        log::info!("server shutdown: {} sessions closed", notified);
+                        for handler in handlers {
+                            let _ = handler.join();
+                        }
+                        if let Ok(mut state) = generator_state.lock() {
+                            *state = QuoteServerThreadState::Cancelled;
+                        }
                         Ok(())
                     });
                 });
@@ -166,3 +768,62 @@
             Ok(())
         }
     }
+
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use quote_lib::quote::orderbook::{BookLevel, OrderBook};
+        use std::time::Instant;
+
+        #[test]
+        fn test_session_threads_stop_on_disconnect() {
+            //потоки исполнения заявок и оповещений завершаются после закрытия соединения клиентом
+            let market = MarketState::default();
+            let mut book = OrderBook::new("A");
+            book.bids = vec![BookLevel { price: 9.9, size: 100 }];
+            book.asks = vec![BookLevel { price: 10.1, size: 100 }];
+            market.update(&MarketMessage::Book(book));
+            let mut server = QuoteServer { market, ..Default::default() };
+            let account = server.account.clone();
+            let alerts = server.alerts.clone();
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let (_sender, receiver) = bounded(1);
+            let handler = thread::spawn(move || {
+                server.handle_client(udp, ControlStream::Plain(stream), receiver);
+                server
+            });
+
+            let mut reader = BufReader::new(client.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            client.write_all(b"ORDER BUY A 10 LIMIT 9\n").unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert!(line.starts_with("OK Order"), "{}", line);
+            client.write_all(b"ALERT A PRICE > 100\n").unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert!(line.starts_with("OK Alert"), "{}", line);
+            //обработчик и потоки сессии держат счет и оповещения
+            assert_eq!(Arc::strong_count(&account), 3);
+            assert_eq!(Arc::strong_count(&alerts), 3);
+            drop(reader);
+            drop(client);
+
+            //обработчик вернулся, сервер сессии еще жив - потоки остановлены по EOF
+            let server = handler.join().unwrap();
+            let started = Instant::now();
+            while (Arc::strong_count(&account) > 2 || Arc::strong_count(&alerts) > 2)
+                && started.elapsed() < Duration::from_secs(2) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(Arc::strong_count(&account), 2);
+            assert_eq!(Arc::strong_count(&alerts), 2);
+            drop(server);
+        }
+    }
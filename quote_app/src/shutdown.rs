@@ -0,0 +1,41 @@
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+//флаг завершения процесса по SIGINT и SIGTERM
+//первый сигнал выставляет флаг для корректной остановки, повторный завершает процесс сразу
+pub(crate) fn register_shutdown() -> io::Result<Arc<AtomicBool>> {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, shutdown.clone())?;
+        signal_hook::flag::register(signal, shutdown.clone())?;
+    }
+    Ok(shutdown)
+}
+
+//флаг перезагрузки файла тикеров сервера по SIGHUP, сбрасывается потоком перезагрузки
+#[cfg(any(feature = "server", test))]
+pub(crate) fn register_reload() -> io::Result<Arc<AtomicBool>> {
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, reload.clone())?;
+    Ok(reload)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::Ordering::SeqCst;
+
+    #[test]
+    fn test_register_shutdown() {
+        let shutdown = register_shutdown().unwrap();
+        assert!(!shutdown.load(SeqCst));
+        signal_hook::low_level::raise(SIGTERM).unwrap();
+        assert!(shutdown.load(SeqCst));
+        let reload = register_reload().unwrap();
+        signal_hook::low_level::raise(SIGHUP).unwrap();
+        assert!(reload.load(SeqCst));
+    }
+}
@@ -0,0 +1,322 @@
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, ClientConnection, Connection, RootCertStore, ServerConfig, ServerConnection};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+//таймаут рукопожатия TLS, сек
+const TLS_HANDSHAKE_TIMEOUT_SECOND: u64 = 10;
+//размер блока чтения зашифрованных данных из сокета
+const TLS_READ_CHUNK: usize = 16 * 1024;
+
+fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("certificates {}: {}", path, e)))?;
+    if certs.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("no certificates in {}", path)));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("private key {}: {}", path, e)))
+}
+
+fn load_roots(path: &str) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    }
+    Ok(roots)
+}
+
+//сертификат и ключ сервера, client_ca - обязательный сертификат клиента, подписанный этим CA
+#[cfg(any(feature = "server", test))]
+pub(crate) fn server_config(cert: &str, key: &str, client_ca: Option<&str>) -> io::Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder();
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(client_ca)?))
+                .build()
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    Ok(Arc::new(config))
+}
+
+//CA для проверки сертификата сервера, client_cert - пара сертификат/ключ клиента
+#[cfg(any(feature = "client", test))]
+pub(crate) fn client_config(ca: &str, client_cert: Option<(&str, &str)>) -> io::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder().with_root_certificates(load_roots(ca)?);
+    let config = match client_cert {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+//состояние TLS общее для клонов: поток чтения и писатели (ответы, котировки, исполнения)
+pub(crate) struct TlsState {
+    connection: Connection,
+    //зашифрованные данные, прочитанные из сокета, но еще не принятые rustls
+    incoming: Vec<u8>,
+    eof: bool,
+}
+
+//управляющее соединение: TCP или TLS поверх TCP
+pub(crate) enum ControlStream {
+    Plain(TcpStream),
+    Tls(Arc<Mutex<TlsState>>, TcpStream),
+}
+
+impl fmt::Debug for ControlStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlStream::Plain(stream) => f.debug_tuple("Plain").field(stream).finish(),
+            ControlStream::Tls(_, stream) => f.debug_tuple("Tls").field(stream).finish(),
+        }
+    }
+}
+
+fn lock_error<T>(_: T) -> io::Error {
+    io::Error::other("tls state lock poisoned")
+}
+
+impl ControlStream {
+    #[cfg(any(feature = "server", test))]
+    pub(crate) fn accept(stream: TcpStream, config: &Arc<ServerConfig>) -> io::Result<Self> {
+        let connection = ServerConnection::new(config.clone()).map_err(io::Error::other)?;
+        ControlStream::handshake(Connection::Server(connection), stream)
+    }
+
+    #[cfg(any(feature = "client", test))]
+    pub(crate) fn connect(stream: TcpStream, config: &Arc<ClientConfig>, server_name: &str) -> io::Result<Self> {
+        let name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, format!("server name {}: {}", server_name, e)))?;
+        let connection = ClientConnection::new(config.clone(), name).map_err(io::Error::other)?;
+        ControlStream::handshake(Connection::Client(connection), stream)
+    }
+
+    fn handshake(mut connection: Connection, stream: TcpStream) -> io::Result<Self> {
+        //рукопожатие в потоке соединения, таймаут сокета восстанавливается после него
+        let read_timeout = stream.read_timeout()?;
+        stream.set_read_timeout(Some(Duration::from_secs(TLS_HANDSHAKE_TIMEOUT_SECOND)))?;
+        let mut socket = &stream;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut socket)?;
+        }
+        stream.set_read_timeout(read_timeout)?;
+        let state = TlsState { connection, incoming: Vec::new(), eof: false };
+        Ok(ControlStream::Tls(Arc::new(Mutex::new(state)), stream))
+    }
+
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            ControlStream::Plain(stream) => Ok(ControlStream::Plain(stream.try_clone()?)),
+            ControlStream::Tls(state, stream) => Ok(ControlStream::Tls(state.clone(), stream.try_clone()?)),
+        }
+    }
+
+    //сокет соединения, например для закрытия сессии администратором
+    pub(crate) fn tcp_stream(&self) -> &TcpStream {
+        match self {
+            ControlStream::Plain(stream) | ControlStream::Tls(_, stream) => stream,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp_stream().peer_addr()
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn shutdown(&self) -> io::Result<()> {
+        self.tcp_stream().shutdown(Shutdown::Both)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_tls(&self) -> bool {
+        matches!(self, ControlStream::Tls(..))
+    }
+
+    fn read_plaintext(state: &Mutex<TlsState>, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        //принимаем накопленные данные и читаем расшифрованные, None - нужны данные из сокета
+        let mut state = state.lock().map_err(lock_error)?;
+        let TlsState { connection, incoming, eof } = &mut *state;
+        while !incoming.is_empty() {
+            let mut data: &[u8] = incoming;
+            //буфер расшифрованных данных заполнен - остаток принимается после чтения
+            let Ok(size) = connection.read_tls(&mut data) else {
+                break;
+            };
+            incoming.drain(..size);
+            connection.process_new_packets().map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            if size == 0 {
+                break;
+            }
+        }
+        match connection.reader().read(buf) {
+            Ok(size) => Ok(Some(size)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock && *eof => Ok(Some(0)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Read for ControlStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (state, stream) = match self {
+            ControlStream::Plain(stream) => return stream.read(buf),
+            ControlStream::Tls(state, stream) => (state, stream),
+        };
+        loop {
+            if let Some(size) = ControlStream::read_plaintext(state, buf)? {
+                return Ok(size);
+            }
+            //чтение сокета без блокировки состояния: писатели не ждут данных клиента,
+            //таймаут чтения сокета действует как таймаут чтения соединения
+            let mut chunk = [0u8; TLS_READ_CHUNK];
+            let size = stream.read(&mut chunk)?;
+            let mut state = state.lock().map_err(lock_error)?;
+            if size == 0 {
+                state.eof = true;
+            }
+            state.incoming.extend_from_slice(&chunk[..size]);
+        }
+    }
+}
+
+impl Write for ControlStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ControlStream::Plain(stream) => stream.write(buf),
+            ControlStream::Tls(state, stream) => {
+                let mut state = state.lock().map_err(lock_error)?;
+                let size = state.connection.writer().write(buf)?;
+                let mut socket = &*stream;
+                while state.connection.wants_write() {
+                    state.connection.write_tls(&mut socket)?;
+                }
+                Ok(size)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ControlStream::Plain(stream) => stream.flush(),
+            ControlStream::Tls(state, stream) => {
+                let mut state = state.lock().map_err(lock_error)?;
+                state.connection.writer().flush()?;
+                let mut socket = &*stream;
+                while state.connection.wants_write() {
+                    state.connection.write_tls(&mut socket)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn write_pem(name: &str, pem: String) -> String {
+        let path = std::env::temp_dir().join(format!("quote_tls_{}_{}", std::process::id(), name));
+        std::fs::write(&path, pem).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn self_signed(name: &str, host: &str) -> (String, String) {
+        let certified = rcgen::generate_simple_self_signed(vec![host.to_string()]).unwrap();
+        (
+            write_pem(&format!("{}.crt", name), certified.cert.pem()),
+            write_pem(&format!("{}.key", name), certified.signing_key.serialize_pem()),
+        )
+    }
+
+    fn serve_echo(config: Arc<ServerConfig>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let Ok(stream) = ControlStream::accept(stream, &config) else {
+                return;
+            };
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                writer.write_all(format!("OK {}", line).as_bytes()).unwrap();
+                writer.flush().unwrap();
+                line.clear();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (server_cert, server_key) = self_signed("server", "127.0.0.1");
+        let (client_cert, client_key) = self_signed("client", "client");
+        let server = server_config(&server_cert, &server_key, Some(&client_cert)).unwrap();
+        let addr = serve_echo(server);
+        let client = client_config(&server_cert, Some((&client_cert, &client_key))).unwrap();
+        let stream = ControlStream::connect(TcpStream::connect(addr).unwrap(), &client, "127.0.0.1").unwrap();
+        assert!(stream.is_tls());
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        for command in ["PING", "STREAM tcp AAPL"] {
+            writer.write_all(format!("{}\n", command).as_bytes()).unwrap();
+            writer.flush().unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, format!("OK {}\n", command));
+        }
+    }
+
+    #[test]
+    fn test_client_certificate_required() {
+        let (server_cert, server_key) = self_signed("required", "127.0.0.1");
+        let (client_cert, _) = self_signed("required_client", "client");
+        let server = server_config(&server_cert, &server_key, Some(&client_cert)).unwrap();
+        let addr = serve_echo(server);
+        let client = client_config(&server_cert, None).unwrap();
+        //в TLS 1.3 отказ сервера приходит после рукопожатия клиента - при первом чтении
+        let result = ControlStream::connect(TcpStream::connect(addr).unwrap(), &client, "127.0.0.1")
+            .and_then(|mut stream| {
+                stream.write_all(b"PING\n")?;
+                stream.flush()?;
+                let mut line = String::new();
+                BufReader::new(stream).read_line(&mut line)
+            });
+        assert!(!matches!(result, Ok(size) if size > 0));
+        //сервер с чужим сертификатом не проходит проверку клиента
+        assert!(client_config(&client_cert, None)
+            .and_then(|client| {
+                let addr = serve_echo(server_config(&server_cert, &server_key, None).unwrap());
+                ControlStream::connect(TcpStream::connect(addr).unwrap(), &client, "127.0.0.1")
+            })
+            .is_err());
+    }
+}
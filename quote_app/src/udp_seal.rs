@@ -0,0 +1,313 @@
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::agreement::{agree_ephemeral, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+//соль HKDF ключа датаграмм сессии
+const SEAL_KDF_SALT: &[u8] = b"quote-stream udp seal v1";
+//длина номера датаграммы в начале запечатанной датаграммы
+const SEAL_SEQUENCE_LEN: usize = 8;
+//окно принятых номеров: датаграммы старше окна отбрасываются как повтор
+#[cfg(any(feature = "client", test))]
+const REPLAY_WINDOW: u64 = 64;
+//длина ключа групп многоадресной рассылки (ChaCha20-Poly1305)
+const GROUP_KEY_LEN: usize = 32;
+//длина открытого ключа и подписи Ed25519 датаграмм групп
+#[cfg(any(feature = "client", test))]
+const GROUP_VERIFY_KEY_LEN: usize = 32;
+#[cfg(any(feature = "client", test))]
+const GROUP_SIGNATURE_LEN: usize = 64;
+
+//ключ сессии по X25519 обмену: клиент `KEY <hex>`, сервер `OK Key <hex>`
+pub(crate) struct KeyExchange {
+    private: EphemeralPrivateKey,
+    public: Vec<u8>,
+}
+
+impl KeyExchange {
+    pub(crate) fn new() -> Result<Self, String> {
+        let private = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new())
+            .map_err(|_| "key generation failed".to_string())?;
+        let public = private
+            .compute_public_key()
+            .map_err(|_| "key generation failed".to_string())?
+            .as_ref()
+            .to_vec();
+        Ok(Self { private, public })
+    }
+
+    pub(crate) fn public_hex(&self) -> String {
+        hex::encode(&self.public)
+    }
+
+    //ключ ChaCha20-Poly1305, одинаковый у клиента и сервера: открытые ключи входят в HKDF
+    //в порядке клиент, сервер
+    pub(crate) fn derive(self, peer_hex: &str, is_server: bool) -> Result<LessSafeKey, String> {
+        let peer = hex::decode(peer_hex).map_err(|_| "bad public key".to_string())?;
+        let (client, server) = if is_server { (&peer, &self.public) } else { (&self.public, &peer) };
+        let info = [client.as_slice(), server.as_slice()];
+        let key = agree_ephemeral(self.private, &UnparsedPublicKey::new(&X25519, &peer), |shared| {
+            Salt::new(HKDF_SHA256, SEAL_KDF_SALT)
+                .extract(shared)
+                .expand(&info, &CHACHA20_POLY1305)
+                .map(UnboundKey::from)
+        })
+        .map_err(|_| "bad public key".to_string())?
+        .map_err(|_| "key derivation failed".to_string())?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+fn nonce(sequence: u64) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - SEAL_SEQUENCE_LEN..].copy_from_slice(&sequence.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+//запечатывание датаграмм сервера: номер (8 байт) | шифротекст | тег
+//номер общий для всех потоков сессии - nonce не повторяется при RESTREAM
+#[cfg(any(feature = "server", test))]
+pub(crate) struct UdpSealer {
+    key: LessSafeKey,
+    sequence: AtomicU64,
+}
+
+#[cfg(any(feature = "server", test))]
+impl UdpSealer {
+    pub(crate) fn new(key: LessSafeKey) -> Self {
+        Self { key, sequence: AtomicU64::new(0) }
+    }
+
+    pub(crate) fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let sequence = self.sequence.fetch_add(1, Relaxed) + 1;
+        let mut sealed = sequence.to_be_bytes().to_vec();
+        let mut payload = data.to_vec();
+        //ошибка только при превышении длины, допустимой для ChaCha20-Poly1305
+        if self.key.seal_in_place_append_tag(nonce(sequence), Aad::empty(), &mut payload).is_ok() {
+            sealed.extend_from_slice(&payload);
+        }
+        sealed
+    }
+
+    //номер последней запечатанной датаграммы
+    pub(crate) fn sequence(&self) -> u64 {
+        self.sequence.load(Relaxed)
+    }
+}
+
+//ключ групп многоадресной рассылки: один на процесс сервера, клиент получает его в ответе
+//OK Multicast запечатанным ключом своей сессии
+//ключ групп есть у каждого подписчика, поэтому датаграммы подписываются ключом Ed25519 сервера:
+//подписчик может расшифровать рассылку, но не подделать ее
+#[cfg(any(feature = "server", test))]
+pub(crate) struct GroupKey {
+    raw: [u8; GROUP_KEY_LEN],
+    sealer: UdpSealer,
+    signing: Ed25519KeyPair,
+}
+
+#[cfg(any(feature = "server", test))]
+impl GroupKey {
+    pub(crate) fn generate() -> Result<Self, String> {
+        let mut raw = [0u8; GROUP_KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut raw)
+            .map_err(|_| "key generation failed".to_string())?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &raw).map_err(|_| "key generation failed".to_string())?;
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| "key generation failed".to_string())?;
+        let signing = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| "key generation failed".to_string())?;
+        Ok(Self { raw, sealer: UdpSealer::new(LessSafeKey::new(key)), signing })
+    }
+
+    //запечатанная датаграмма и подпись сервера над ней
+    pub(crate) fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let mut sealed = self.sealer.seal(data);
+        let signature = self.signing.sign(&sealed);
+        sealed.extend_from_slice(signature.as_ref());
+        sealed
+    }
+
+    //ключ группы, номер последней датаграммы и открытый ключ подписи,
+    //запечатанные ключом сессии клиента, hex
+    pub(crate) fn grant(&self, session: &UdpSealer) -> String {
+        let mut grant = self.raw.to_vec();
+        grant.extend_from_slice(&self.sealer.sequence().to_be_bytes());
+        grant.extend_from_slice(self.signing.public_key().as_ref());
+        hex::encode(session.seal(&grant))
+    }
+}
+
+#[cfg(any(feature = "client", test))]
+#[derive(Debug, PartialEq)]
+pub(crate) enum OpenError {
+    //датаграмма не прошла проверку тега: подделана или запечатана другим ключом
+    Forged,
+    //номер уже принят или старше окна
+    Replayed,
+}
+
+//проверка датаграмм клиентом с окном номеров против повтора
+#[cfg(any(feature = "client", test))]
+pub(crate) struct UdpOpener {
+    key: LessSafeKey,
+    highest: u64,
+    //бит i - принят номер highest - i
+    window: u64,
+    //открытый ключ подписи датаграмм групп, None - датаграммы сессии без подписи
+    verify_key: Option<Vec<u8>>,
+}
+
+#[cfg(any(feature = "client", test))]
+impl UdpOpener {
+    pub(crate) fn new(key: LessSafeKey) -> Self {
+        Self { key, highest: 0, window: 0, verify_key: None }
+    }
+
+    //проверка рассылки групп по ключу из ответа OK Multicast, открытому ключом сессии:
+    //датаграммы, запечатанные до выдачи ключа, отбрасываются как повтор
+    pub(crate) fn for_group(&mut self, grant_hex: &str) -> Result<UdpOpener, String> {
+        let grant = hex::decode(grant_hex).map_err(|_| "bad group key".to_string())?;
+        let grant = self.open(&grant).map_err(|_| "bad group key".to_string())?;
+        if grant.len() != GROUP_KEY_LEN + SEAL_SEQUENCE_LEN + GROUP_VERIFY_KEY_LEN {
+            return Err("bad group key".to_string());
+        }
+        let (raw, rest) = grant.split_at(GROUP_KEY_LEN);
+        let (sequence, verify_key) = rest.split_at(SEAL_SEQUENCE_LEN);
+        let key = UnboundKey::new(&CHACHA20_POLY1305, raw).map_err(|_| "bad group key".to_string())?;
+        let highest = u64::from_be_bytes(sequence.try_into().map_err(|_| "bad group key".to_string())?);
+        Ok(Self { key: LessSafeKey::new(key), highest, window: u64::MAX, verify_key: Some(verify_key.to_vec()) })
+    }
+
+    pub(crate) fn open(&mut self, datagram: &[u8]) -> Result<Vec<u8>, OpenError> {
+        //датаграмма групп без подписи сервера отбрасывается до расшифровки
+        let datagram = match &self.verify_key {
+            Some(verify_key) => {
+                if datagram.len() < GROUP_SIGNATURE_LEN {
+                    return Err(OpenError::Forged);
+                }
+                let (signed, signature) = datagram.split_at(datagram.len() - GROUP_SIGNATURE_LEN);
+                signature::UnparsedPublicKey::new(&signature::ED25519, verify_key)
+                    .verify(signed, signature)
+                    .map_err(|_| OpenError::Forged)?;
+                signed
+            }
+            None => datagram,
+        };
+        if datagram.len() < SEAL_SEQUENCE_LEN {
+            return Err(OpenError::Forged);
+        }
+        let (sequence, payload) = datagram.split_at(SEAL_SEQUENCE_LEN);
+        let sequence = u64::from_be_bytes(sequence.try_into().map_err(|_| OpenError::Forged)?);
+        if sequence == 0 {
+            return Err(OpenError::Forged);
+        }
+        //повтор определяется до расшифровки, окно сдвигается только после проверки тега
+        if sequence <= self.highest
+            && (self.highest - sequence >= REPLAY_WINDOW || self.window & (1 << (self.highest - sequence)) != 0)
+        {
+            return Err(OpenError::Replayed);
+        }
+        let mut payload = payload.to_vec();
+        let size = self
+            .key
+            .open_in_place(nonce(sequence), Aad::empty(), &mut payload)
+            .map_err(|_| OpenError::Forged)?
+            .len();
+        payload.truncate(size);
+        if sequence > self.highest {
+            let shift = sequence - self.highest;
+            self.window = if shift >= REPLAY_WINDOW { 0 } else { self.window << shift };
+            self.highest = sequence;
+        }
+        self.window |= 1 << (self.highest - sequence);
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session_keys() -> (LessSafeKey, LessSafeKey) {
+        let client = KeyExchange::new().unwrap();
+        let server = KeyExchange::new().unwrap();
+        let (client_public, server_public) = (client.public_hex(), server.public_hex());
+        (client.derive(&server_public, false).unwrap(), server.derive(&client_public, true).unwrap())
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let (client_key, server_key) = session_keys();
+        let sealer = UdpSealer::new(server_key);
+        let mut opener = UdpOpener::new(client_key);
+        let first = sealer.seal(b"T|AAPL|1|10|100|R|1000\n");
+        let second = sealer.seal(b"T|AAPL|2|11|100|R|1001\n");
+        assert_eq!(opener.open(&second).unwrap(), b"T|AAPL|2|11|100|R|1001\n");
+        //перестановка в пределах окна допустима, повтор - нет
+        assert_eq!(opener.open(&first).unwrap(), b"T|AAPL|1|10|100|R|1000\n");
+        assert_eq!(opener.open(&first), Err(OpenError::Replayed));
+        let mut forged = sealer.seal(b"T|AAPL|3|12|100|R|1002\n");
+        forged[SEAL_SEQUENCE_LEN] ^= 1;
+        assert_eq!(opener.open(&forged), Err(OpenError::Forged));
+        assert_eq!(opener.open(b"T|AAPL|1|1|100|R|1000\n"), Err(OpenError::Forged));
+        assert!(KeyExchange::new().unwrap().derive("zz", true).is_err());
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let (client_key, server_key) = session_keys();
+        let sealer = UdpSealer::new(server_key);
+        let mut opener = UdpOpener::new(client_key);
+        let sealed: Vec<Vec<u8>> = (0..REPLAY_WINDOW + 2).map(|_| sealer.seal(b"Q")).collect();
+        assert!(opener.open(&sealed[REPLAY_WINDOW as usize + 1]).is_ok());
+        //номер 1 старше окна
+        assert_eq!(opener.open(&sealed[0]), Err(OpenError::Replayed));
+        assert!(opener.open(&sealed[2]).is_ok());
+        assert_eq!(opener.open(&sealed[2]), Err(OpenError::Replayed));
+    }
+
+    #[test]
+    fn test_group_key() {
+        let (client_key, server_key) = session_keys();
+        let session = UdpSealer::new(server_key);
+        let mut session_opener = UdpOpener::new(client_key);
+        let group = GroupKey::generate().unwrap();
+        let before = group.seal(b"T|AAPL|1|10|100|R|1000\n");
+        let mut opener = session_opener.for_group(&group.grant(&session)).unwrap();
+        //датаграмма до выдачи ключа - повтор, после - принимается
+        assert_eq!(opener.open(&before), Err(OpenError::Replayed));
+        assert_eq!(opener.open(&group.seal(b"T|AAPL|2|11|100|R|1001\n")).unwrap(), b"T|AAPL|2|11|100|R|1001\n");
+        //ключ другого сервера и подделанная выдача ключа
+        let other = GroupKey::generate().unwrap();
+        let other_sealed = (0..3).map(|_| other.seal(b"Q")).last().unwrap();
+        assert_eq!(opener.open(&other_sealed), Err(OpenError::Forged));
+        let mut forged = hex::decode(group.grant(&session)).unwrap();
+        forged[SEAL_SEQUENCE_LEN] ^= 1;
+        assert!(session_opener.for_group(&hex::encode(forged)).is_err());
+    }
+
+    #[test]
+    fn test_group_signature() {
+        //подписчик с ключом групп запечатывает датаграмму - подпись сервера не сходится
+        let (client_key, server_key) = session_keys();
+        let session = UdpSealer::new(server_key);
+        let group = GroupKey::generate().unwrap();
+        let mut opener = UdpOpener::new(client_key).for_group(&group.grant(&session)).unwrap();
+        let subscriber = UdpSealer::new(LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &group.raw).unwrap()));
+        let unsigned = (0..3).map(|_| subscriber.seal(b"T|AAPL|1|1|100|R|1000\n")).last().unwrap();
+        assert_eq!(opener.open(&unsigned), Err(OpenError::Forged));
+        let mut signed = unsigned.clone();
+        signed.extend_from_slice(&[0u8; GROUP_SIGNATURE_LEN]);
+        assert_eq!(opener.open(&signed), Err(OpenError::Forged));
+        //датаграмма сервера с подменой байта сообщения
+        let mut tampered = group.seal(b"T|AAPL|1|10|100|R|1000\n");
+        tampered[SEAL_SEQUENCE_LEN] ^= 1;
+        assert_eq!(opener.open(&tampered), Err(OpenError::Forged));
+        assert!(opener.open(&group.seal(b"T|AAPL|1|10|100|R|1000\n")).is_ok());
+    }
+}
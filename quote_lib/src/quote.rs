@@ -1,4 +1,9 @@
 //! Модуль для реализации типа котровок
 //!
 //! Предоставляет функциональность для работы со структурой данных котировок
-pub mod stockquote;
\ No newline at end of file
+pub mod stockquote;
+pub mod alert;
+pub mod execution;
+pub mod marketmessage;
+pub mod orderbook;
+pub mod subscription;
@@ -0,0 +1,215 @@
+//! Модуль оповещений по котировкам
+//!
+//! Предоставляет операторы сравнения для правил оповещений и сообщение оповещения
+//! сервера в формате передачи по управляющему TCP каналу
+
+use crate::errors::QuoteGeneratorError;
+use chrono::DateTime;
+use std::fmt;
+
+/// Тег сообщения оповещения
+pub const TAG_ALERT: &str = "ALERT";
+
+/// Оператор сравнения значения с порогом
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertComparison {
+    /// больше порога
+    Greater,
+    /// больше или равно порогу
+    GreaterEq,
+    /// меньше порога
+    Less,
+    /// меньше или равно порогу
+    LessEq,
+}
+
+impl AlertComparison {
+    /// Оператор по строке `>`, `>=`, `<`, `<=`, `None` если оператор неизвестен
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            ">" => Some(AlertComparison::Greater),
+            ">=" => Some(AlertComparison::GreaterEq),
+            "<" => Some(AlertComparison::Less),
+            "<=" => Some(AlertComparison::LessEq),
+            _ => None,
+        }
+    }
+
+    /// Проверяет значение относительно порога
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::alert::AlertComparison;
+    ///
+    /// let comparison = AlertComparison::parse(">=").unwrap();
+    /// assert!(comparison.matches(200.0, 200.0));
+    /// assert!(!comparison.matches(199.9, 200.0));
+    /// ```
+    pub fn matches(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            AlertComparison::Greater => value > threshold,
+            AlertComparison::GreaterEq => value >= threshold,
+            AlertComparison::Less => value < threshold,
+            AlertComparison::LessEq => value <= threshold,
+        }
+    }
+
+    /// Проверяет, что значение ушло от порога в обратную сторону на величину гистерезиса
+    pub fn rearm(&self, value: f64, threshold: f64, hysteresis: f64) -> bool {
+        match self {
+            AlertComparison::Greater | AlertComparison::GreaterEq => value <= threshold - hysteresis,
+            AlertComparison::Less | AlertComparison::LessEq => value >= threshold + hysteresis,
+        }
+    }
+}
+
+/// Тип условия оповещения сервера
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertKind {
+    /// цена относительно порога
+    Price,
+    /// изменение цены в процентах за окно времени
+    Move,
+    /// объем сделки относительно среднего объема сделки за сессию
+    Volume,
+}
+
+impl AlertKind {
+    /// Код типа в формате передачи
+    pub fn code(&self) -> &'static str {
+        match self {
+            AlertKind::Price => "PRICE",
+            AlertKind::Move => "MOVE",
+            AlertKind::Volume => "VOLUME",
+        }
+    }
+
+    /// Тип по коду (без учета регистра), `None` если код неизвестен
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_uppercase().as_str() {
+            "PRICE" => Some(AlertKind::Price),
+            "MOVE" => Some(AlertKind::Move),
+            "VOLUME" => Some(AlertKind::Volume),
+            _ => None,
+        }
+    }
+}
+
+/// Сообщение о сработавшем оповещении сервера
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerAlert {
+    /// идентификатор оповещения в сессии
+    pub alert_id: u64,
+    /// название котировки
+    pub ticker: String,
+    /// тип условия
+    pub kind: AlertKind,
+    /// значение, на котором сработало условие (цена, процент изменения или кратность объема)
+    pub value: f64,
+    /// цена последней сделки
+    pub price: f64,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+impl ServerAlert {
+    /// Создает сообщение из строки формата
+    /// `"ALERT|<alert_id>|<ticker>|<kind>|<value>|<price>|<timestamp>"`
+    ///
+    /// # Возращает
+    /// - `Some(ServerAlert)` если десериализация прошла без ошибок
+    /// - `None` если формат входных данных не соовествует
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::alert::{AlertKind, ServerAlert};
+    ///
+    /// let alert = ServerAlert::from_string("ALERT|2|AAPL|MOVE|2.5|215.3|1697071010").unwrap();
+    /// assert_eq!(alert.kind, AlertKind::Move);
+    /// assert_eq!(alert.to_string(), "ALERT|2|AAPL|MOVE|2.5|215.3|1697071010");
+    /// ```
+    pub fn from_string(s: &str) -> Option<Self> {
+        let binding = s.replace('\n', "");
+        let parts: Vec<&str> = binding.split('|').collect();
+        if parts.len() != 7 || parts[0] != TAG_ALERT {
+            return None;
+        }
+        Some(ServerAlert {
+            alert_id: parts[1].parse().ok()?,
+            ticker: parts[2].to_string(),
+            kind: AlertKind::from_code(parts[3])?,
+            value: parts[4].parse().ok()?,
+            price: parts[5].parse().ok()?,
+            timestamp: parts[6].parse().ok()?,
+        })
+    }
+
+    /// Конвертация сообщения в формат json
+    ///
+    /// # Возращает
+    /// * `Ok(String)` - json строку с полем `type` = `alert`
+    /// * `Err(QuoteGeneratorError)` - ошибку преобразования метки времени
+    pub fn to_json(&self) -> Result<String, QuoteGeneratorError> {
+        let Some(date_time) = DateTime::from_timestamp_millis(self.timestamp as i64) else {
+            return Err(QuoteGeneratorError::BadParseTimestampQuote(
+                "Error parse".to_string(),
+            ));
+        };
+        Ok(format!(
+            "{{\"type\": \"alert\", \"alert_id\": {}, \"ticker\": \"{}\", \"kind\": \"{}\", \
+             \"value\": {}, \"price\": {}, \"timestamp\": \"{}\"}}",
+            self.alert_id,
+            self.ticker,
+            self.kind.code(),
+            self.value,
+            self.price,
+            date_time.format("%Y-%m-%dT%H:%M:%S%.3f")
+        ))
+    }
+}
+
+impl fmt::Display for ServerAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}|{}|{}|{}|{}|{}|{}",
+            TAG_ALERT,
+            self.alert_id,
+            self.ticker,
+            self.kind.code(),
+            self.value,
+            self.price,
+            self.timestamp
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison_rearm() {
+        let comparison = AlertComparison::Greater;
+        assert!(comparison.matches(201.0, 200.0));
+        assert!(!comparison.rearm(199.5, 200.0, 1.0));
+        assert!(comparison.rearm(199.0, 200.0, 1.0));
+        assert!(AlertComparison::Less.rearm(151.0, 150.0, 1.0));
+        assert!(AlertComparison::parse("=>").is_none());
+    }
+
+    #[test]
+    fn test_server_alert_round_trip() {
+        let alert = ServerAlert {
+            alert_id: 1,
+            ticker: "MSFT".to_string(),
+            kind: AlertKind::Volume,
+            value: 3.2,
+            price: 214.5,
+            timestamp: 1697071010,
+        };
+        assert_eq!(ServerAlert::from_string(&alert.to_string()).unwrap(), alert);
+        assert!(alert.to_json().unwrap().contains("\"kind\": \"VOLUME\""));
+        assert!(ServerAlert::from_string("ALERT|1|MSFT|SPIKE|1|1|1").is_none());
+    }
+}
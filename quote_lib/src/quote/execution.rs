@@ -0,0 +1,197 @@
+//! Модуль отчетов об исполнении заявок бумажной торговли
+//!
+//! Предоставляет сторону заявки, статус исполнения и отчет об исполнении
+//! в формате передачи по управляющему TCP каналу
+
+use crate::errors::QuoteGeneratorError;
+use chrono::DateTime;
+use std::fmt;
+
+/// Тег отчета об исполнении
+pub const TAG_EXECUTION: &str = "EXEC";
+
+/// Сторона заявки
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderSide {
+    /// покупка
+    Buy,
+    /// продажа
+    Sell,
+}
+
+impl OrderSide {
+    /// Код стороны в формате передачи
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "BUY",
+            OrderSide::Sell => "SELL",
+        }
+    }
+
+    /// Сторона по коду (без учета регистра), `None` если код неизвестен
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_uppercase().as_str() {
+            "BUY" => Some(OrderSide::Buy),
+            "SELL" => Some(OrderSide::Sell),
+            _ => None,
+        }
+    }
+}
+
+/// Статус заявки после исполнения
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecutionStatus {
+    /// заявка исполнена частично
+    PartiallyFilled,
+    /// заявка исполнена полностью
+    Filled,
+    /// заявка (или ее остаток) снята
+    Canceled,
+}
+
+impl ExecutionStatus {
+    /// Код статуса в формате передачи
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExecutionStatus::PartiallyFilled => "PARTIAL",
+            ExecutionStatus::Filled => "FILLED",
+            ExecutionStatus::Canceled => "CANCELED",
+        }
+    }
+
+    /// Статус по коду, `None` если код неизвестен
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "PARTIAL" => Some(ExecutionStatus::PartiallyFilled),
+            "FILLED" => Some(ExecutionStatus::Filled),
+            "CANCELED" => Some(ExecutionStatus::Canceled),
+            _ => None,
+        }
+    }
+}
+
+/// Отчет об исполнении заявки
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionReport {
+    /// идентификатор заявки в сессии
+    pub order_id: u64,
+    /// название котировки
+    pub ticker: String,
+    /// сторона заявки
+    pub side: OrderSide,
+    /// исполненный объем в этом отчете
+    pub quantity: u32,
+    /// цена исполнения
+    pub price: f64,
+    /// неисполненный остаток заявки
+    pub leaves: u32,
+    /// статус заявки
+    pub status: ExecutionStatus,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+impl ExecutionReport {
+    /// Создает отчет из строки формата
+    /// `"EXEC|<order_id>|<ticker>|<side>|<quantity>|<price>|<leaves>|<status>|<timestamp>"`
+    ///
+    /// # Возращает
+    /// - `Some(ExecutionReport)` если десериализация прошла без ошибок
+    /// - `None` если формат входных данных не соовествует
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::execution::{ExecutionReport, ExecutionStatus};
+    ///
+    /// let report = ExecutionReport::from_string("EXEC|1|AAPL|BUY|100|210.5|0|FILLED|1697071010").unwrap();
+    /// assert_eq!(report.status, ExecutionStatus::Filled);
+    /// assert_eq!(report.to_string(), "EXEC|1|AAPL|BUY|100|210.5|0|FILLED|1697071010");
+    /// ```
+    pub fn from_string(s: &str) -> Option<Self> {
+        let binding = s.replace('\n', "");
+        let parts: Vec<&str> = binding.split('|').collect();
+        if parts.len() != 9 || parts[0] != TAG_EXECUTION {
+            return None;
+        }
+        Some(ExecutionReport {
+            order_id: parts[1].parse().ok()?,
+            ticker: parts[2].to_string(),
+            side: OrderSide::from_code(parts[3])?,
+            quantity: parts[4].parse().ok()?,
+            price: parts[5].parse().ok()?,
+            leaves: parts[6].parse().ok()?,
+            status: ExecutionStatus::from_code(parts[7])?,
+            timestamp: parts[8].parse().ok()?,
+        })
+    }
+
+    /// Конвертация отчета в формат json
+    ///
+    /// # Возращает
+    /// * `Ok(String)` - json строку с полем `type` = `execution`
+    /// * `Err(QuoteGeneratorError)` - ошибку преобразования метки времени
+    pub fn to_json(&self) -> Result<String, QuoteGeneratorError> {
+        let Some(date_time) = DateTime::from_timestamp_millis(self.timestamp as i64) else {
+            return Err(QuoteGeneratorError::BadParseTimestampQuote(
+                "Error parse".to_string(),
+            ));
+        };
+        Ok(format!(
+            "{{\"type\": \"execution\", \"order_id\": {}, \"ticker\": \"{}\", \"side\": \"{}\", \
+             \"quantity\": {}, \"price\": {}, \"leaves\": {}, \"status\": \"{}\", \"timestamp\": \"{}\"}}",
+            self.order_id,
+            self.ticker,
+            self.side.code(),
+            self.quantity,
+            self.price,
+            self.leaves,
+            self.status.code(),
+            date_time.format("%Y-%m-%dT%H:%M:%S%.3f")
+        ))
+    }
+}
+
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            TAG_EXECUTION,
+            self.order_id,
+            self.ticker,
+            self.side.code(),
+            self.quantity,
+            self.price,
+            self.leaves,
+            self.status.code(),
+            self.timestamp
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let report = ExecutionReport {
+            order_id: 7,
+            ticker: "MSFT".to_string(),
+            side: OrderSide::Sell,
+            quantity: 50,
+            price: 214.31,
+            leaves: 150,
+            status: ExecutionStatus::PartiallyFilled,
+            timestamp: 1697071010,
+        };
+        assert_eq!(ExecutionReport::from_string(&report.to_string()).unwrap(), report);
+        assert!(report.to_json().unwrap().contains("\"status\": \"PARTIAL\""));
+    }
+
+    #[test]
+    fn test_from_string_bad_format() {
+        assert!(ExecutionReport::from_string("EXEC|1|AAPL|HOLD|1|1|0|FILLED|1").is_none());
+        assert!(ExecutionReport::from_string("T|AAPL|1|157.92|300|R|1697071010").is_none());
+    }
+}
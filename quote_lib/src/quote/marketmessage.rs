@@ -0,0 +1,641 @@
+//! Модуль рыночных сообщений стрима
+//!
+//! Предоставляет отдельные типы для сделок (Trade), котировок bid/ask (Quote)
+//! и накопленной статистики торговой сессии (SessionStats), стакана заявок,
+//! а также формат передачи с тегом типа сообщения
+
+use crate::errors::QuoteGeneratorError;
+use crate::quote::orderbook::{BookChange, BookLevel, BookSide, BookUpdate, OrderBook};
+use chrono::DateTime;
+use std::fmt;
+
+/// Тег сообщения о сделке
+pub const TAG_TRADE: &str = "T";
+/// Тег сообщения котировки bid/ask
+pub const TAG_QUOTE: &str = "Q";
+/// Тег сообщения статистики сессии
+pub const TAG_STATS: &str = "S";
+/// Тег снимка стакана
+pub const TAG_BOOK: &str = "B";
+/// Тег инкрементального обновления стакана
+pub const TAG_BOOK_UPDATE: &str = "U";
+
+/// Условие сделки
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TradeCondition {
+    /// обычная сделка
+    Regular,
+    /// первая сделка сессии
+    Opening,
+    /// сделка нестандартным лотом (меньше 100 акций)
+    OddLot,
+    /// крупная (блочная) сделка
+    Block,
+}
+
+impl TradeCondition {
+    /// Код условия сделки в формате передачи
+    pub fn code(&self) -> &'static str {
+        match self {
+            TradeCondition::Regular => "R",
+            TradeCondition::Opening => "O",
+            TradeCondition::OddLot => "L",
+            TradeCondition::Block => "B",
+        }
+    }
+
+    /// Условие сделки по коду, `None` если код неизвестен
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "R" => Some(TradeCondition::Regular),
+            "O" => Some(TradeCondition::Opening),
+            "L" => Some(TradeCondition::OddLot),
+            "B" => Some(TradeCondition::Block),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TradeCondition::Regular => "regular",
+            TradeCondition::Opening => "opening",
+            TradeCondition::OddLot => "odd_lot",
+            TradeCondition::Block => "block",
+        }
+    }
+}
+
+/// Сделка (принт): цена и объем конкретной сделки
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    /// название котировки
+    pub ticker: String,
+    /// номер версии по тикеру, растет с каждой сгенерированной сделкой
+    pub sequence: u64,
+    /// цена сделки
+    pub price: f64,
+    /// объем этой сделки
+    pub size: u32,
+    /// условие сделки
+    pub condition: TradeCondition,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+/// Котировка: лучшие цены покупки и продажи
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    /// название котировки
+    pub ticker: String,
+    /// номер версии по тикеру, растет с каждой сгенерированной сделкой
+    pub sequence: u64,
+    /// лучшая цена покупки
+    pub bid: f64,
+    /// объем на лучшей цене покупки
+    pub bid_size: u32,
+    /// лучшая цена продажи
+    pub ask: f64,
+    /// объем на лучшей цене продажи
+    pub ask_size: u32,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+/// Накопленная статистика торговой сессии по тикеру
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionStats {
+    /// название котировки
+    pub ticker: String,
+    /// номер версии по тикеру, растет с каждой сгенерированной сделкой
+    pub sequence: u64,
+    /// цена открытия
+    pub open: f64,
+    /// максимальная цена
+    pub high: f64,
+    /// минимальная цена
+    pub low: f64,
+    /// цена последней сделки
+    pub last: f64,
+    /// накопленный объем за сессию
+    pub volume: u64,
+    /// количество сделок за сессию
+    pub trades: u64,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+impl SessionStats {
+    /// Создает статистику сессии по первой сделке
+    ///
+    /// # Аргументы
+    /// * `trade` - первая сделка сессии
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::marketmessage::{SessionStats, Trade, TradeCondition};
+    ///
+    /// let trade = Trade { ticker: "AAPL".to_string(), sequence: 1, price: 10.0, size: 100,
+    ///     condition: TradeCondition::Opening, timestamp: 1 };
+    /// let stats = SessionStats::open(&trade);
+    /// assert_eq!(stats.volume, 100);
+    /// assert_eq!(stats.trades, 1);
+    /// ```
+    pub fn open(trade: &Trade) -> Self {
+        SessionStats {
+            ticker: trade.ticker.clone(),
+            sequence: trade.sequence,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            last: trade.price,
+            volume: trade.size as u64,
+            trades: 1,
+            timestamp: trade.timestamp,
+        }
+    }
+
+    /// Учитывает очередную сделку в статистике сессии
+    ///
+    /// # Аргументы
+    /// * `trade` - очередная сделка по тикеру
+    pub fn apply(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.last = trade.price;
+        self.volume += trade.size as u64;
+        self.trades += 1;
+        self.sequence = trade.sequence;
+        self.timestamp = trade.timestamp;
+    }
+}
+
+/// Сообщение стрима с тегом типа
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketMessage {
+    /// сделка
+    Trade(Trade),
+    /// котировка bid/ask
+    Quote(Quote),
+    /// статистика сессии
+    Stats(SessionStats),
+    /// снимок стакана
+    Book(OrderBook),
+    /// инкрементальное обновление стакана
+    BookUpdate(BookUpdate),
+}
+
+fn format_timestamp(timestamp: u64) -> Result<String, QuoteGeneratorError> {
+    match DateTime::from_timestamp_millis(timestamp as i64) {
+        Some(date_time) => Ok(date_time.format("%Y-%m-%dT%H:%M:%S%.3f").to_string()),
+        None => Err(QuoteGeneratorError::BadParseTimestampQuote(
+            "Error parse".to_string(),
+        )),
+    }
+}
+
+//уровни стакана в формате `<price>:<size>;<price>:<size>`
+fn levels_to_string(levels: &[BookLevel]) -> String {
+    levels
+        .iter()
+        .map(|level| format!("{}:{}", level.price, level.size))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+fn levels_from_string(s: &str) -> Option<Vec<BookLevel>> {
+    s.split(';')
+        .filter(|level| !level.is_empty())
+        .map(|level| {
+            let (price, size) = level.split_once(':')?;
+            Some(BookLevel {
+                price: price.parse().ok()?,
+                size: size.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn levels_to_json(levels: &[BookLevel]) -> String {
+    let levels: Vec<String> = levels
+        .iter()
+        .map(|level| format!("[{}, {}]", level.price, level.size))
+        .collect();
+    format!("[{}]", levels.join(", "))
+}
+
+//изменения стакана в формате `<side>:<price>:<size>;...`
+fn changes_to_string(changes: &[BookChange]) -> String {
+    changes
+        .iter()
+        .map(|change| format!("{}:{}:{}", change.side.code(), change.price, change.size))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+fn changes_from_string(s: &str) -> Option<Vec<BookChange>> {
+    s.split(';')
+        .filter(|change| !change.is_empty())
+        .map(|change| {
+            let mut parts = change.split(':');
+            Some(BookChange {
+                side: BookSide::from_code(parts.next()?)?,
+                price: parts.next()?.parse().ok()?,
+                size: parts.next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+impl MarketMessage {
+    /// Имя тикера сообщения
+    pub fn ticker(&self) -> &str {
+        match self {
+            MarketMessage::Trade(trade) => &trade.ticker,
+            MarketMessage::Quote(quote) => &quote.ticker,
+            MarketMessage::Stats(stats) => &stats.ticker,
+            MarketMessage::Book(book) => &book.ticker,
+            MarketMessage::BookUpdate(update) => &update.ticker,
+        }
+    }
+
+    /// Метка времени сообщения в миллисекундах
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            MarketMessage::Trade(trade) => trade.timestamp,
+            MarketMessage::Quote(quote) => quote.timestamp,
+            MarketMessage::Stats(stats) => stats.timestamp,
+            MarketMessage::Book(book) => book.timestamp,
+            MarketMessage::BookUpdate(update) => update.timestamp,
+        }
+    }
+
+    /// Номер версии сообщения по тикеру
+    pub fn sequence(&self) -> u64 {
+        match self {
+            MarketMessage::Trade(trade) => trade.sequence,
+            MarketMessage::Quote(quote) => quote.sequence,
+            MarketMessage::Stats(stats) => stats.sequence,
+            MarketMessage::Book(book) => book.sequence,
+            MarketMessage::BookUpdate(update) => update.sequence,
+        }
+    }
+
+    /// Текущая цена инструмента по сообщению: цена сделки, середина котировки,
+    /// последняя цена сессии или середина лучших уровней стакана
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::marketmessage::MarketMessage;
+    ///
+    /// let message = MarketMessage::from_string("Q|AAPL|1|10|100|11|200|1697071010").unwrap();
+    /// assert_eq!(message.mark_price(), Some(10.5));
+    /// ```
+    pub fn mark_price(&self) -> Option<f64> {
+        match self {
+            MarketMessage::Trade(trade) => Some(trade.price),
+            MarketMessage::Quote(quote) => Some((quote.bid + quote.ask) / 2.0),
+            MarketMessage::Stats(stats) => Some(stats.last),
+            MarketMessage::Book(book) => {
+                Some((book.best_bid()?.price + book.best_ask()?.price) / 2.0)
+            }
+            MarketMessage::BookUpdate(_) => None,
+        }
+    }
+
+    /// Создает сообщение из строки формата передачи
+    ///
+    /// # Параметр
+    /// - `s`: строка одного из форматов:
+    ///   * `"T|<ticker>|<sequence>|<price>|<size>|<condition>|<timestamp>"`
+    ///   * `"Q|<ticker>|<sequence>|<bid>|<bid_size>|<ask>|<ask_size>|<timestamp>"`
+    ///   * `"S|<ticker>|<sequence>|<open>|<high>|<low>|<last>|<volume>|<trades>|<timestamp>"`
+    ///   * `"B|<ticker>|<sequence>|<timestamp>|<bids>|<asks>"`, уровни `<price>:<size>;...`
+    ///   * `"U|<ticker>|<prev_sequence>|<sequence>|<timestamp>|<changes>"`,
+    ///     изменения `<b|a>:<price>:<size>;...`
+    ///
+    /// # Возращает
+    /// - `Some(MarketMessage)` если десериализация прошла без ошибок
+    /// - `None` если тег неизвестен или формат не соответствует
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::marketmessage::MarketMessage;
+    ///
+    /// let message = MarketMessage::from_string("T|AAPL|1|157.92|300|R|1697071010").unwrap();
+    /// assert_eq!(message.ticker(), "AAPL");
+    /// assert!(MarketMessage::from_string("X|AAPL|157.92").is_none());
+    /// ```
+    pub fn from_string(s: &str) -> Option<Self> {
+        let binding = s.replace('\n', "");
+        let parts: Vec<&str> = binding.split('|').collect();
+        match (parts[0], parts.len()) {
+            (TAG_TRADE, 7) => Some(MarketMessage::Trade(Trade {
+                ticker: parts[1].to_string(),
+                sequence: parts[2].parse().ok()?,
+                price: parts[3].parse().ok()?,
+                size: parts[4].parse().ok()?,
+                condition: TradeCondition::from_code(parts[5])?,
+                timestamp: parts[6].parse().ok()?,
+            })),
+            (TAG_QUOTE, 8) => Some(MarketMessage::Quote(Quote {
+                ticker: parts[1].to_string(),
+                sequence: parts[2].parse().ok()?,
+                bid: parts[3].parse().ok()?,
+                bid_size: parts[4].parse().ok()?,
+                ask: parts[5].parse().ok()?,
+                ask_size: parts[6].parse().ok()?,
+                timestamp: parts[7].parse().ok()?,
+            })),
+            (TAG_STATS, 10) => Some(MarketMessage::Stats(SessionStats {
+                ticker: parts[1].to_string(),
+                sequence: parts[2].parse().ok()?,
+                open: parts[3].parse().ok()?,
+                high: parts[4].parse().ok()?,
+                low: parts[5].parse().ok()?,
+                last: parts[6].parse().ok()?,
+                volume: parts[7].parse().ok()?,
+                trades: parts[8].parse().ok()?,
+                timestamp: parts[9].parse().ok()?,
+            })),
+            (TAG_BOOK, 6) => Some(MarketMessage::Book(OrderBook {
+                ticker: parts[1].to_string(),
+                sequence: parts[2].parse().ok()?,
+                timestamp: parts[3].parse().ok()?,
+                bids: levels_from_string(parts[4])?,
+                asks: levels_from_string(parts[5])?,
+            })),
+            (TAG_BOOK_UPDATE, 6) => Some(MarketMessage::BookUpdate(BookUpdate {
+                ticker: parts[1].to_string(),
+                prev_sequence: parts[2].parse().ok()?,
+                sequence: parts[3].parse().ok()?,
+                timestamp: parts[4].parse().ok()?,
+                changes: changes_from_string(parts[5])?,
+            })),
+            _ => None,
+        }
+    }
+
+    /// Сереализует сообщение в байтовый вектор формата передачи с символом переноса строки
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::marketmessage::{MarketMessage, Trade, TradeCondition};
+    ///
+    /// let message = MarketMessage::Trade(Trade { ticker: "AAPL".to_string(), sequence: 7,
+    ///     price: 150.34, size: 200, condition: TradeCondition::Regular, timestamp: 1672531200 });
+    /// assert_eq!(String::from_utf8(message.to_bytes()).unwrap(), "T|AAPL|7|150.34|200|R|1672531200\n");
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!("{}\n", self).into_bytes()
+    }
+
+    /// Конвертация сообщения в формат json
+    ///
+    /// # Возращает
+    /// * `Ok(String)` - json строку с полем `type` (`trade`, `quote`, `stats`, `book`, `book_update`)
+    /// * `Err(QuoteGeneratorError)` - ошибку преобразования метки времени
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::marketmessage::MarketMessage;
+    ///
+    /// let message = MarketMessage::from_string("Q|AAPL|3|10.5|100|10.6|200|1633045692000").unwrap();
+    /// assert_eq!(message.to_json().unwrap(), "{\"type\": \"quote\", \"ticker\": \"AAPL\", \
+    ///     \"sequence\": 3, \"bid\": 10.5, \"bid_size\": 100, \"ask\": 10.6, \"ask_size\": 200, \
+    ///     \"timestamp\": \"2021-09-30T23:48:12.000\"}");
+    /// ```
+    pub fn to_json(&self) -> Result<String, QuoteGeneratorError> {
+        let date_time = format_timestamp(self.timestamp())?;
+        Ok(match self {
+            MarketMessage::Trade(trade) => format!(
+                "{{\"type\": \"trade\", \"ticker\": \"{}\", \"sequence\": {}, \"price\": {}, \
+                 \"size\": {}, \"condition\": \"{}\", \"timestamp\": \"{}\"}}",
+                trade.ticker,
+                trade.sequence,
+                trade.price,
+                trade.size,
+                trade.condition.name(),
+                date_time
+            ),
+            MarketMessage::Quote(quote) => format!(
+                "{{\"type\": \"quote\", \"ticker\": \"{}\", \"sequence\": {}, \"bid\": {}, \
+                 \"bid_size\": {}, \"ask\": {}, \"ask_size\": {}, \"timestamp\": \"{}\"}}",
+                quote.ticker, quote.sequence, quote.bid, quote.bid_size, quote.ask, quote.ask_size, date_time
+            ),
+            MarketMessage::Stats(stats) => format!(
+                "{{\"type\": \"stats\", \"ticker\": \"{}\", \"sequence\": {}, \"open\": {}, \
+                 \"high\": {}, \"low\": {}, \"last\": {}, \"volume\": {}, \"trades\": {}, \
+                 \"timestamp\": \"{}\"}}",
+                stats.ticker,
+                stats.sequence,
+                stats.open,
+                stats.high,
+                stats.low,
+                stats.last,
+                stats.volume,
+                stats.trades,
+                date_time
+            ),
+            MarketMessage::Book(book) => format!(
+                "{{\"type\": \"book\", \"ticker\": \"{}\", \"sequence\": {}, \"bids\": {}, \
+                 \"asks\": {}, \"timestamp\": \"{}\"}}",
+                book.ticker,
+                book.sequence,
+                levels_to_json(&book.bids),
+                levels_to_json(&book.asks),
+                date_time
+            ),
+            MarketMessage::BookUpdate(update) => {
+                let changes: Vec<String> = update
+                    .changes
+                    .iter()
+                    .map(|change| {
+                        format!(
+                            "{{\"side\": \"{}\", \"price\": {}, \"size\": {}}}",
+                            change.side.name(),
+                            change.price,
+                            change.size
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"type\": \"book_update\", \"ticker\": \"{}\", \"prev_sequence\": {}, \
+                     \"sequence\": {}, \"changes\": [{}], \"timestamp\": \"{}\"}}",
+                    update.ticker,
+                    update.prev_sequence,
+                    update.sequence,
+                    changes.join(", "),
+                    date_time
+                )
+            }
+        })
+    }
+}
+
+impl fmt::Display for MarketMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketMessage::Trade(trade) => write!(
+                f,
+                "{}|{}|{}|{}|{}|{}|{}",
+                TAG_TRADE,
+                trade.ticker,
+                trade.sequence,
+                trade.price,
+                trade.size,
+                trade.condition.code(),
+                trade.timestamp
+            ),
+            MarketMessage::Quote(quote) => write!(
+                f,
+                "{}|{}|{}|{}|{}|{}|{}|{}",
+                TAG_QUOTE,
+                quote.ticker,
+                quote.sequence,
+                quote.bid,
+                quote.bid_size,
+                quote.ask,
+                quote.ask_size,
+                quote.timestamp
+            ),
+            MarketMessage::Stats(stats) => write!(
+                f,
+                "{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+                TAG_STATS,
+                stats.ticker,
+                stats.sequence,
+                stats.open,
+                stats.high,
+                stats.low,
+                stats.last,
+                stats.volume,
+                stats.trades,
+                stats.timestamp
+            ),
+            MarketMessage::Book(book) => write!(
+                f,
+                "{}|{}|{}|{}|{}|{}",
+                TAG_BOOK,
+                book.ticker,
+                book.sequence,
+                book.timestamp,
+                levels_to_string(&book.bids),
+                levels_to_string(&book.asks)
+            ),
+            MarketMessage::BookUpdate(update) => write!(
+                f,
+                "{}|{}|{}|{}|{}|{}",
+                TAG_BOOK_UPDATE,
+                update.ticker,
+                update.prev_sequence,
+                update.sequence,
+                update.timestamp,
+                changes_to_string(&update.changes)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_trade() -> Trade {
+        Trade {
+            ticker: "AAPL".to_string(),
+            sequence: 1,
+            price: 150.5,
+            size: 300,
+            condition: TradeCondition::Regular,
+            timestamp: 1697071010,
+        }
+    }
+
+    #[test]
+    fn test_trade_round_trip() {
+        let message = MarketMessage::Trade(test_trade());
+        let parsed = MarketMessage::from_string(&message.to_string()).unwrap();
+        assert_eq!(message, parsed);
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        let message = MarketMessage::Quote(Quote {
+            ticker: "MSFT".to_string(),
+            sequence: 4,
+            bid: 100.1,
+            bid_size: 500,
+            ask: 100.2,
+            ask_size: 700,
+            timestamp: 1697071010,
+        });
+        let bytes = message.to_bytes();
+        let parsed = MarketMessage::from_string(&String::from_utf8_lossy(&bytes)).unwrap();
+        assert_eq!(message, parsed);
+    }
+
+    #[test]
+    fn test_stats_apply() {
+        let mut trade = test_trade();
+        let mut stats = SessionStats::open(&trade);
+        trade.price = 155.0;
+        trade.size = 200;
+        trade.sequence = 2;
+        stats.apply(&trade);
+        trade.price = 149.0;
+        stats.apply(&trade);
+        assert_eq!(stats.open, 150.5);
+        assert_eq!(stats.high, 155.0);
+        assert_eq!(stats.low, 149.0);
+        assert_eq!(stats.last, 149.0);
+        assert_eq!(stats.volume, 700);
+        assert_eq!(stats.trades, 3);
+        assert_eq!(stats.sequence, 2);
+        let message = MarketMessage::Stats(stats);
+        assert_eq!(
+            MarketMessage::from_string(&message.to_string()).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_book_round_trip() {
+        let book = MarketMessage::Book(OrderBook {
+            ticker: "AAPL".to_string(),
+            sequence: 12,
+            bids: vec![BookLevel { price: 10.0, size: 100 }, BookLevel { price: 9.99, size: 200 }],
+            asks: vec![BookLevel { price: 10.01, size: 300 }],
+            timestamp: 1697071010,
+        });
+        assert_eq!(MarketMessage::from_string(&book.to_string()).unwrap(), book);
+        let update = MarketMessage::BookUpdate(BookUpdate {
+            ticker: "AAPL".to_string(),
+            prev_sequence: 12,
+            sequence: 15,
+            changes: vec![BookChange { side: BookSide::Ask, price: 10.01, size: 0 }],
+            timestamp: 1697071010,
+        });
+        assert_eq!(update.to_string(), "U|AAPL|12|15|1697071010|a:10.01:0");
+        assert_eq!(MarketMessage::from_string(&update.to_string()).unwrap(), update);
+    }
+
+    #[test]
+    fn test_from_string_bad_format() {
+        assert!(MarketMessage::from_string("AAPL|157.92|300000|1697071010").is_none());
+        assert!(MarketMessage::from_string("T|AAPL|1|157.92|300|X|1697071010").is_none());
+        assert!(MarketMessage::from_string("Q|AAPL|1|2|3").is_none());
+        //сообщение без номера версии
+        assert!(MarketMessage::from_string("T|AAPL|157.92|300|R|1697071010").is_none());
+        assert!(MarketMessage::from_string("B|AAPL|1|2|10.0;100|").is_none());
+    }
+
+    #[test]
+    fn test_trade_to_json() {
+        let message = MarketMessage::Trade(test_trade());
+        assert_eq!(
+            message.to_json().unwrap(),
+            "{\"type\": \"trade\", \"ticker\": \"AAPL\", \"sequence\": 1, \"price\": 150.5, \
+             \"size\": 300, \"condition\": \"regular\", \"timestamp\": \"1970-01-20T15:24:31.010\"}"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Модуль стакана заявок (Level 2)
+//!
+//! Предоставляет структуру стакана с несколькими ценовыми уровнями на каждой стороне,
+//! вычисление инкрементальных изменений между версиями стакана и их применение
+
+/// Сторона стакана
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookSide {
+    /// заявки на покупку
+    Bid,
+    /// заявки на продажу
+    Ask,
+}
+
+impl BookSide {
+    /// Код стороны в формате передачи
+    pub fn code(&self) -> &'static str {
+        match self {
+            BookSide::Bid => "b",
+            BookSide::Ask => "a",
+        }
+    }
+
+    /// Сторона по коду, `None` если код неизвестен
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "b" => Some(BookSide::Bid),
+            "a" => Some(BookSide::Ask),
+            _ => None,
+        }
+    }
+
+    /// Имя стороны для json
+    pub fn name(&self) -> &'static str {
+        match self {
+            BookSide::Bid => "bid",
+            BookSide::Ask => "ask",
+        }
+    }
+}
+
+/// Ценовой уровень стакана
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookLevel {
+    /// цена уровня
+    pub price: f64,
+    /// суммарный объем заявок на уровне
+    pub size: u32,
+}
+
+/// Изменение одного уровня стакана, объем 0 - удаление уровня
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookChange {
+    /// сторона стакана
+    pub side: BookSide,
+    /// цена уровня
+    pub price: f64,
+    /// новый объем уровня
+    pub size: u32,
+}
+
+/// Инкрементальное обновление стакана от версии `prev_sequence` к версии `sequence`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookUpdate {
+    /// название котировки
+    pub ticker: String,
+    /// версия стакана, к которой применяется обновление
+    pub prev_sequence: u64,
+    /// версия стакана после применения обновления
+    pub sequence: u64,
+    /// изменения уровней
+    pub changes: Vec<BookChange>,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+/// Стакан заявок по тикеру
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBook {
+    /// название котировки
+    pub ticker: String,
+    /// версия стакана
+    pub sequence: u64,
+    /// уровни покупки, по убыванию цены
+    pub bids: Vec<BookLevel>,
+    /// уровни продажи, по возрастанию цены
+    pub asks: Vec<BookLevel>,
+    /// метка времени в миллисекундах
+    pub timestamp: u64,
+}
+
+fn diff_side(side: BookSide, old: &[BookLevel], new: &[BookLevel], changes: &mut Vec<BookChange>) {
+    for level in new {
+        if !old.contains(level) {
+            changes.push(BookChange {
+                side,
+                price: level.price,
+                size: level.size,
+            });
+        }
+    }
+    for level in old {
+        if !new.iter().any(|new_level| new_level.price == level.price) {
+            changes.push(BookChange {
+                side,
+                price: level.price,
+                size: 0,
+            });
+        }
+    }
+}
+
+impl OrderBook {
+    /// Создает пустой стакан по тикеру
+    pub fn new(ticker: &str) -> Self {
+        OrderBook {
+            ticker: ticker.to_string(),
+            sequence: 0,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            timestamp: 0,
+        }
+    }
+
+    /// Лучший уровень покупки
+    pub fn best_bid(&self) -> Option<&BookLevel> {
+        self.bids.first()
+    }
+
+    /// Лучший уровень продажи
+    pub fn best_ask(&self) -> Option<&BookLevel> {
+        self.asks.first()
+    }
+
+    /// Копия стакана с первыми `depth` уровнями на каждой стороне
+    pub fn top(&self, depth: usize) -> OrderBook {
+        OrderBook {
+            ticker: self.ticker.clone(),
+            sequence: self.sequence,
+            bids: self.bids.iter().take(depth).copied().collect(),
+            asks: self.asks.iter().take(depth).copied().collect(),
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// Вычисляет инкрементальное обновление от текущего стакана к более новому
+    ///
+    /// # Возращает
+    /// - `Some(BookUpdate)` с измененными уровнями
+    /// - `None` если уровни не изменились
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::orderbook::{BookLevel, OrderBook};
+    ///
+    /// let mut old = OrderBook::new("AAPL");
+    /// old.bids = vec![BookLevel { price: 10.0, size: 100 }];
+    /// let mut new = old.clone();
+    /// new.sequence = 5;
+    /// new.bids[0].size = 200;
+    /// let update = old.diff(&new).unwrap();
+    /// old.apply(&update);
+    /// assert_eq!(old, new);
+    /// ```
+    pub fn diff(&self, newer: &OrderBook) -> Option<BookUpdate> {
+        let mut changes = Vec::new();
+        diff_side(BookSide::Bid, &self.bids, &newer.bids, &mut changes);
+        diff_side(BookSide::Ask, &self.asks, &newer.asks, &mut changes);
+        if changes.is_empty() {
+            return None;
+        }
+        Some(BookUpdate {
+            ticker: self.ticker.clone(),
+            prev_sequence: self.sequence,
+            sequence: newer.sequence,
+            changes,
+            timestamp: newer.timestamp,
+        })
+    }
+
+    /// Применяет инкрементальное обновление к стакану
+    ///
+    /// # Возращает
+    /// - `true` если обновление применено
+    /// - `false` если версия стакана не совпадает с `prev_sequence` обновления
+    ///   (пропущено обновление, требуется новый снимок)
+    pub fn apply(&mut self, update: &BookUpdate) -> bool {
+        if update.prev_sequence != self.sequence {
+            return false;
+        }
+        for change in &update.changes {
+            let levels = match change.side {
+                BookSide::Bid => &mut self.bids,
+                BookSide::Ask => &mut self.asks,
+            };
+            levels.retain(|level| level.price != change.price);
+            if change.size > 0 {
+                levels.push(BookLevel {
+                    price: change.price,
+                    size: change.size,
+                });
+            }
+        }
+        self.bids.sort_by(|a, b| b.price.total_cmp(&a.price));
+        self.asks.sort_by(|a, b| a.price.total_cmp(&b.price));
+        self.sequence = update.sequence;
+        self.timestamp = update.timestamp;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_book() -> OrderBook {
+        OrderBook {
+            ticker: "AAPL".to_string(),
+            sequence: 1,
+            bids: vec![
+                BookLevel { price: 10.0, size: 100 },
+                BookLevel { price: 9.99, size: 200 },
+            ],
+            asks: vec![
+                BookLevel { price: 10.01, size: 300 },
+                BookLevel { price: 10.02, size: 400 },
+            ],
+            timestamp: 1,
+        }
+    }
+
+    #[test]
+    fn test_diff_apply() {
+        let mut old = test_book();
+        let mut new = test_book();
+        new.sequence = 7;
+        new.timestamp = 2;
+        new.asks.remove(0);
+        new.asks.push(BookLevel { price: 10.03, size: 100 });
+        new.bids.insert(0, BookLevel { price: 10.01, size: 50 });
+        let update = old.diff(&new).unwrap();
+        assert_eq!(update.prev_sequence, 1);
+        assert_eq!(update.changes.len(), 3);
+        assert!(old.apply(&update));
+        assert_eq!(old, new);
+    }
+
+    #[test]
+    fn test_apply_sequence_gap() {
+        let mut book = test_book();
+        let update = BookUpdate {
+            ticker: "AAPL".to_string(),
+            prev_sequence: 3,
+            sequence: 4,
+            changes: vec![],
+            timestamp: 0,
+        };
+        assert!(!book.apply(&update));
+        assert_eq!(book, test_book());
+    }
+
+    #[test]
+    fn test_diff_unchanged() {
+        let book = test_book();
+        assert!(book.diff(&book).is_none());
+        let top = book.top(1);
+        assert_eq!(top.bids.len(), 1);
+        assert_eq!(top.best_ask(), book.best_ask());
+    }
+}
@@ -0,0 +1,143 @@
+//! Модуль подписки на типы сообщений по тикерам
+//!
+//! Предоставляет разбор списка подписки вида `AAPL,MSFT:trades,GOOG:quotes+stats+book`
+
+use crate::quote::marketmessage::MarketMessage;
+
+/// Подписка на сообщения по одному тикеру
+#[derive(Debug, Clone, PartialEq)]
+pub struct TickerSubscription {
+    /// название котировки
+    pub ticker: String,
+    /// подписка на сделки
+    pub trades: bool,
+    /// подписка на котировки bid/ask
+    pub quotes: bool,
+    /// подписка на статистику сессии
+    pub stats: bool,
+    /// подписка на стакан заявок
+    pub book: bool,
+}
+
+impl TickerSubscription {
+    /// Создает подписку на все типы сообщений по тикеру
+    pub fn new(ticker: &str) -> Self {
+        TickerSubscription {
+            ticker: ticker.to_string(),
+            trades: true,
+            quotes: true,
+            stats: true,
+            book: true,
+        }
+    }
+
+    /// Разбор подписки одного тикера формата `<ticker>[:<kind>[+<kind>...]]`
+    ///
+    /// Типы: `trades`, `quotes`, `stats`, `book`, `all`. Без указания типа - подписка на все сообщения
+    ///
+    /// # Возращает
+    /// - `Some(TickerSubscription)` если формат корректен
+    /// - `None` если имя тикера пустое или тип неизвестен
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::subscription::TickerSubscription;
+    ///
+    /// let subscription = TickerSubscription::from_string("AAPL:trades+stats").unwrap();
+    /// assert!(subscription.trades && subscription.stats && !subscription.quotes);
+    /// assert!(TickerSubscription::from_string("AAPL:book_of_wonders").is_none());
+    /// ```
+    pub fn from_string(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (ticker, kinds) = match s.split_once(':') {
+            Some((ticker, kinds)) => (ticker, Some(kinds)),
+            None => (s, None),
+        };
+        if ticker.is_empty() {
+            return None;
+        }
+        let mut subscription = TickerSubscription::new(ticker);
+        if let Some(kinds) = kinds {
+            subscription.trades = false;
+            subscription.quotes = false;
+            subscription.stats = false;
+            subscription.book = false;
+            for kind in kinds.split('+') {
+                match kind {
+                    "trades" => subscription.trades = true,
+                    "quotes" => subscription.quotes = true,
+                    "stats" => subscription.stats = true,
+                    "book" => subscription.book = true,
+                    "all" => subscription = TickerSubscription::new(ticker),
+                    _ => return None,
+                }
+            }
+        }
+        Some(subscription)
+    }
+
+    /// Разбор списка подписок, разделенных запятой. Некорректные элементы пропускаются
+    ///
+    /// # Пример
+    /// ```rust
+    /// use quote_lib::quote::subscription::TickerSubscription;
+    ///
+    /// let subscriptions = TickerSubscription::parse_list("AAPL,MSFT:quotes,,GOOG:bad");
+    /// assert_eq!(subscriptions.len(), 2);
+    /// ```
+    pub fn parse_list(tickers: &str) -> Vec<TickerSubscription> {
+        tickers
+            .split(',')
+            .filter_map(TickerSubscription::from_string)
+            .collect()
+    }
+
+    /// Проверяет, входит ли сообщение в подписку
+    pub fn accepts(&self, message: &MarketMessage) -> bool {
+        if message.ticker() != self.ticker {
+            return false;
+        }
+        match message {
+            MarketMessage::Trade(_) => self.trades,
+            MarketMessage::Quote(_) => self.quotes,
+            MarketMessage::Stats(_) => self.stats,
+            MarketMessage::Book(_) | MarketMessage::BookUpdate(_) => self.book,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::marketmessage::{Trade, TradeCondition};
+
+    #[test]
+    fn test_from_string_default_all() {
+        let subscription = TickerSubscription::from_string("AAPL").unwrap();
+        assert_eq!(subscription, TickerSubscription::new("AAPL"));
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let subscriptions = TickerSubscription::parse_list("AAPL:trades,MSFT:quotes+stats,GOOG:book");
+        assert!(subscriptions[0].trades && !subscriptions[0].quotes && !subscriptions[0].stats);
+        assert!(!subscriptions[1].trades && subscriptions[1].quotes && subscriptions[1].stats);
+        assert!(subscriptions[2].book && !subscriptions[2].trades && !subscriptions[2].quotes);
+    }
+
+    #[test]
+    fn test_accepts() {
+        let subscription = TickerSubscription::from_string("AAPL:quotes").unwrap();
+        let trade = MarketMessage::Trade(Trade {
+            ticker: "AAPL".to_string(),
+            sequence: 1,
+            price: 1.0,
+            size: 1,
+            condition: TradeCondition::Regular,
+            timestamp: 0,
+        });
+        assert!(!subscription.accepts(&trade));
+        assert!(TickerSubscription::new("AAPL").accepts(&trade));
+        assert!(!TickerSubscription::new("MSFT").accepts(&trade));
+    }
+}